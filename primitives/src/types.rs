@@ -19,7 +19,7 @@ pub struct ProofInputs {
     pub contract_storage: Vec<ContractStorage>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ContractStorage {
     pub address: Address,
     pub value: TrieAccount,
@@ -29,7 +29,7 @@ pub struct ContractStorage {
     pub storage_slots: Vec<StorageSlotWithProof>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct StorageSlotWithProof {
     pub key: B256,
     pub value: U256,
@@ -93,6 +93,15 @@ sol! {
 
         event HeadUpdate(uint256 indexed slot, bytes32 indexed root);
         event SyncCommitteeUpdate(uint256 indexed period, bytes32 indexed root);
+        event LightClientVkeyUpdate(bytes32 indexed newVkey);
+        event StorageSlotVkeyUpdate(bytes32 indexed newVkey);
+
+        error SlotBehindHead(uint256 slot);
+        error SyncCommitteeStartMismatch(bytes32 given, bytes32 expected);
+        error SyncCommitteeNotSet(uint256 period);
+        error NextSyncCommitteeMismatch(bytes32 given, bytes32 expected);
+        error NonCheckpointSlot(uint256 slot);
+        error MissingStateRoot(uint256 blockNumber);
 
         function update(
             bytes calldata proof,
@@ -105,7 +114,27 @@ sol! {
             StorageSlot[] memory _storageSlots
         ) external;
 
+        function updateStorageSlot(
+            bytes calldata proof,
+            StorageSlot[] memory _storageSlots,
+            uint256 blockNumber
+        ) external;
+
+        function getStorageSlot(uint256 blockNumber, address contractAddress, bytes32 key)
+            external
+            view
+            returns (bytes32);
+
         function getSyncCommitteePeriod(uint256 slot) internal view returns (uint256);
         function getCurrentEpoch() internal view returns (uint256);
     }
+
+    /// Errors raised by the SP1 verifier gateway and the verifiers it routes to.
+    #[allow(missing_docs)]
+    interface ISP1Verifier {
+        error RouteNotFound(bytes4 selector);
+        error RouteIsFrozen(bytes4 selector);
+        error WrongVerifierSelector(bytes4 received, bytes4 expected);
+        error InvalidProof();
+    }
 }
@@ -19,8 +19,10 @@ use helios_consensus_core::consensus_spec::MainnetConsensusSpec;
 use helios_consensus_core::types::BeaconBlock;
 use helios_ethereum::rpc::ConsensusRpc;
 use sp1_helios_primitives::types::ProofInputs;
+use sp1_helios_script::rpc::ConsensusEndpoints;
 use sp1_helios_script::{get_client, get_updates};
 use std::path::PathBuf;
+use std::time::Duration;
 use tree_hash::TreeHash;
 
 const SLOTS_PER_PERIOD: u64 = 8192;
@@ -35,9 +37,17 @@ fn sync_period(slot: u64) -> u64 {
 #[derive(Parser, Debug)]
 #[command(about = "Generate the ProofInputs CBOR fixture for the light-client executor test.")]
 struct Args {
-    /// Mainnet beacon (consensus) RPC serving the helios light-client API.
-    #[arg(long)]
-    source_consensus_rpc: String,
+    /// Mainnet beacon (consensus) RPCs serving the helios light-client API, tried in order.
+    #[arg(long, value_delimiter = ',', required = true)]
+    source_consensus_rpc: Vec<String>,
+
+    /// How many consensus RPCs must agree on bootstraps and finality updates.
+    #[arg(long, default_value = "1")]
+    consensus_quorum: usize,
+
+    /// How long to wait for a consensus RPC before failing over, in seconds.
+    #[arg(long, default_value = "30")]
+    consensus_timeout_secs: u64,
 
     /// Source chain id (mainnet = 1).
     #[arg(long, default_value = "1")]
@@ -51,10 +61,15 @@ struct Args {
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    let consensus_rpc = ConsensusEndpoints {
+        quorum: args.consensus_quorum,
+        timeout: Duration::from_secs(args.consensus_timeout_secs),
+        ..ConsensusEndpoints::new(args.source_consensus_rpc.clone())?
+    };
 
     // 1. Bootstrap at the latest checkpoint just to talk to the RPC, and read the live finality
     //    update so we know the head it finalizes and the sync-committee period it signs against.
-    let latest = get_client(None, &args.source_consensus_rpc, args.source_chain_id).await?;
+    let latest = get_client(None, &consensus_rpc, args.source_chain_id).await?;
     let finality_update = latest
         .rpc
         .get_finality_update()
@@ -99,12 +114,7 @@ async fn main() -> Result<()> {
     }
 
     // 2. Bootstrap the client at that checkpoint, exactly like the operator does.
-    let client = get_client(
-        Some(bootstrap_slot),
-        &args.source_consensus_rpc,
-        args.source_chain_id,
-    )
-    .await?;
+    let client = get_client(Some(bootstrap_slot), &consensus_rpc, args.source_chain_id).await?;
     assert_eq!(
         client.store.finalized_header.beacon().slot,
         bootstrap_slot,
@@ -113,7 +123,7 @@ async fn main() -> Result<()> {
 
     // 3. Fetch committee updates (mirrors request_update). These populate next_sync_committee in
     //    the positive test; the negative test empties them.
-    let updates = get_updates(&client).await;
+    let updates = get_updates(&client).await?;
 
     println!(
         "bootstrap slot: {bootstrap_slot} (period {}), updates: {}, finality new head: {new_head}",
@@ -6,11 +6,13 @@ use clap::Parser;
 use helios_consensus_core::consensus_spec::{ConsensusSpec, MainnetConsensusSpec};
 use serde::{Deserialize, Serialize};
 use sp1_helios_script::get_client;
+use sp1_helios_script::rpc::ConsensusEndpoints;
 use sp1_sdk::{HashableKey, Prover, ProverClient, ProvingKey};
 use std::{
     fmt::Debug,
     fs,
     path::{Path, PathBuf},
+    time::Duration,
 };
 use tracing_subscriber::{fmt, EnvFilter};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -58,9 +60,17 @@ pub struct GenesisArgs {
     #[arg(long, default_value = "1")]
     pub source_chain_id: u64,
 
-    /// The RPC URL of the source chain.
-    #[arg(long)]
-    pub source_consensus_rpc: String,
+    /// The consensus RPC URLs of the source chain, tried in order.
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub source_consensus_rpc: Vec<String>,
+
+    /// How many consensus RPCs must agree on bootstraps and finality updates.
+    #[arg(long, default_value = "1")]
+    pub consensus_quorum: usize,
+
+    /// How long to wait for a consensus RPC before failing over, in seconds.
+    #[arg(long, default_value = "30")]
+    pub consensus_timeout_secs: u64,
 
     /// The Etherscan API key to use for the deployer account.
     #[arg(long)]
@@ -122,7 +132,13 @@ pub async fn main() {
         .await
         .expect("Failed to setup storage slots program");
 
-    let helios_client = get_client(args.slot, &args.source_consensus_rpc, args.source_chain_id)
+    let consensus_rpc = ConsensusEndpoints {
+        quorum: args.consensus_quorum,
+        timeout: Duration::from_secs(args.consensus_timeout_secs),
+        ..ConsensusEndpoints::new(args.source_consensus_rpc.clone())
+            .expect("Invalid consensus RPCs")
+    };
+    let helios_client = get_client(args.slot, &consensus_rpc, args.source_chain_id)
         .await
         .expect("Failed to create genesis client");
     let finalized_header = helios_client
@@ -1,9 +1,20 @@
 use alloy::network::EthereumWallet;
 use alloy::primitives::Address;
-use alloy::providers::ProviderBuilder;
+use alloy::providers::{Provider, ProviderBuilder};
 use alloy::signers::local::PrivateKeySigner;
+use opentelemetry::trace::TracerProvider;
+use sp1_helios_script::auth::Authorizer;
+use sp1_helios_script::cache::StorageProofCache;
+use sp1_helios_script::config::{
+    self, ApiSettings, DestinationConfig, OperatorConfig, ProvingSettings, RelaySettings,
+    RetrySettings, SourceConfig, StorageSettings, TelemetrySettings,
+};
+use sp1_helios_script::db::OperatorDb;
+use sp1_helios_script::metrics;
 use sp1_helios_script::operator::SP1HeliosOperator;
-use std::time::Duration;
+use sp1_helios_script::{server, telemetry};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use tracing_subscriber::fmt;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
@@ -14,21 +25,113 @@ use clap::Parser;
 #[derive(Parser, Debug, Clone)]
 #[command(about = "Run the SP1 Helios operator.")]
 pub struct OperatorArgs {
+    /// A TOML or YAML file with every setting but the private key, and optionally the storage
+    /// watchlist. Conflicts with every other flag but `--private-key`.
+    ///
+    /// The watchlist, relay settings and loop delay are reloaded whenever the file changes.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "rpc_url",
+            "contract_address",
+            "destinations",
+            "source_chain_id",
+            "source_consensus_rpc",
+            "consensus_quorum",
+            "consensus_timeout_secs",
+            "rpc_max_retries",
+            "rpc_initial_backoff_ms",
+            "rpc_max_backoff_ms",
+            "rpc_failure_threshold",
+            "rpc_unhealthy_secs",
+            "execution_timeout_secs",
+            "loop_delay_mins",
+            "execution_rpc",
+            "max_keys_per_proof_request",
+            "max_concurrent_proof_requests",
+            "max_update_storage_cycles",
+            "max_follow_up_storage_cycles",
+            "max_concurrent_proofs",
+            "max_queued_storage_proofs",
+            "storage_proof_batch_window_ms",
+            "storage_proof_cache_entries",
+            "storage_proof_cache_max_age_mins",
+            "storage_proof_cache_dir",
+            "db_dir",
+            "changed_slots_only",
+            "slot_max_staleness_mins",
+            "finality_events",
+            "api_addr",
+            "api_clients",
+            "metrics_addr",
+            "shutdown_timeout_secs",
+            "otlp_endpoint",
+            "confirmations",
+            "replacement_timeout_secs",
+            "max_replacements",
+            "confirmation_timeout_secs",
+            "fee_bump_percent",
+            "fee_multiplier_percent",
+            "max_fee_per_gas",
+            "max_priority_fee_per_gas",
+        ]
+    )]
+    pub config: Option<PathBuf>,
+
     /// The RPC URL where the light client contract is deployed.
-    #[arg(long)]
-    pub rpc_url: String,
+    #[arg(long, required_unless_present = "config")]
+    pub rpc_url: Option<String>,
 
     /// The address of the light client contract.
-    #[arg(long)]
-    pub contract_address: Address,
+    #[arg(long, required_unless_present = "config")]
+    pub contract_address: Option<Address>,
+
+    /// Additional light client contracts to keep updated, as `<contract-address>@<rpc-url>`.
+    ///
+    /// Every destination must track the same source chain and share the signer.
+    #[arg(long = "destination", value_parser = parse_destination)]
+    pub destinations: Vec<(Address, String)>,
 
     /// The chain ID of the source chain.
-    #[arg(long)]
-    pub source_chain_id: u64,
+    #[arg(long, required_unless_present = "config")]
+    pub source_chain_id: Option<u64>,
 
-    /// The RPC URL of the source chain.
-    #[arg(long)]
-    pub source_consensus_rpc: String,
+    /// The consensus RPC URLs of the source chain, tried in order.
+    #[arg(long, value_delimiter = ',', required_unless_present = "config")]
+    pub source_consensus_rpc: Vec<String>,
+
+    /// How many consensus RPCs must agree on bootstraps and finality updates.
+    #[arg(long, default_value = "1")]
+    pub consensus_quorum: usize,
+
+    /// How long to wait for a consensus RPC before failing over, in seconds.
+    #[arg(long, default_value = "30")]
+    pub consensus_timeout_secs: u64,
+
+    /// How many times an RPC request is retried, with exponential backoff, after it failed on
+    /// every endpoint.
+    #[arg(long, default_value = "3")]
+    pub rpc_max_retries: u32,
+
+    /// The delay before the first retry of an RPC request, in milliseconds.
+    #[arg(long, default_value = "500")]
+    pub rpc_initial_backoff_ms: u64,
+
+    /// The longest delay between retries of an RPC request, in milliseconds.
+    #[arg(long, default_value = "30000")]
+    pub rpc_max_backoff_ms: u64,
+
+    /// How many requests in a row must fail before an RPC endpoint is skipped as unhealthy.
+    #[arg(long, default_value = "5")]
+    pub rpc_failure_threshold: u32,
+
+    /// How long an unhealthy RPC endpoint is skipped before it is tried again, in seconds.
+    #[arg(long, default_value = "60")]
+    pub rpc_unhealthy_secs: u64,
+
+    /// How long to wait for an execution RPC before failing over to the next, in seconds.
+    #[arg(long, default_value = "30")]
+    pub execution_timeout_secs: u64,
 
     #[arg(long)]
     pub private_key: String,
@@ -36,12 +139,164 @@ pub struct OperatorArgs {
     /// The delay between operator runs in minutes.
     #[arg(long, default_value = "5")]
     pub loop_delay_mins: u64,
+
+    /// Execution RPC URLs of the source chain used to fetch storage proofs, tried in order.
+    ///
+    /// Defaults to `--rpc-url` if not set.
+    #[arg(long, value_delimiter = ',')]
+    pub execution_rpc: Vec<String>,
+
+    /// The maximum number of storage keys requested in a single `eth_getProof` call.
+    #[arg(long, default_value = "50")]
+    pub max_keys_per_proof_request: usize,
+
+    /// The maximum number of `eth_getProof` calls in flight at once.
+    #[arg(long, default_value = "4")]
+    pub max_concurrent_proof_requests: usize,
+
+    /// The estimated zkVM cycles of storage verification allowed in an update proof. Watched slots
+    /// beyond it are proven in follow-up storage proofs.
+    #[arg(long)]
+    pub max_update_storage_cycles: Option<u64>,
+
+    /// The estimated zkVM cycles allowed in each follow-up storage proof.
+    #[arg(long, requires = "max_update_storage_cycles")]
+    pub max_follow_up_storage_cycles: Option<u64>,
+
+    /// The maximum number of proofs generated at once. Update proofs go ahead of storage proofs.
+    #[arg(long, default_value = "2")]
+    pub max_concurrent_proofs: usize,
+
+    /// The maximum number of storage proof requests waiting to be proven.
+    #[arg(long, default_value = "64")]
+    pub max_queued_storage_proofs: usize,
+
+    /// How long to wait for more storage proof requests for the same block, to prove them together.
+    #[arg(long, default_value = "1000")]
+    pub storage_proof_batch_window_ms: u64,
+
+    /// The maximum number of storage proofs cached. Set to 0 to disable the cache.
+    #[arg(long, default_value = "128")]
+    pub storage_proof_cache_entries: usize,
+
+    /// How long a cached storage proof is kept, in minutes.
+    #[arg(long, default_value = "1440")]
+    pub storage_proof_cache_max_age_mins: u64,
+
+    /// A directory to persist cached storage proofs to, so they survive restarts.
+    #[arg(long)]
+    pub storage_proof_cache_dir: Option<PathBuf>,
+
+    /// A directory to persist the storage slot watchlist, generated proofs and their relays to.
+    /// On restart, the watchlist is restored and proofs that had not landed are relayed again.
+    #[arg(long)]
+    pub db_dir: Option<PathBuf>,
+
+    /// Only include watched storage slots whose value changed since it was last written.
+    #[arg(long)]
+    pub changed_slots_only: bool,
+
+    /// With `--changed-slots-only`, include unchanged slots anyway once they were last written this
+    /// many minutes ago.
+    #[arg(long, requires = "changed_slots_only")]
+    pub slot_max_staleness_mins: Option<u64>,
+
+    /// Trigger updates from the beacon node's finality events, on top of polling every
+    /// `--loop-delay-mins`.
+    #[arg(long)]
+    pub finality_events: bool,
+
+    /// Serve the operator's HTTP JSON API on this address, e.g. `127.0.0.1:8080`. Only loopback
+    /// addresses are allowed without `--api-clients`.
+    #[arg(long)]
+    pub api_addr: Option<SocketAddr>,
+
+    /// A JSON file listing the API clients allowed to use the HTTP API, and their quotas.
+    ///
+    /// Required unless the API is served on a loopback address, in which case it is
+    /// unauthenticated.
+    #[arg(long, requires = "api_addr")]
+    pub api_clients: Option<PathBuf>,
+
+    /// Serve Prometheus metrics at `/metrics` on this address, e.g. `127.0.0.1:9090`.
+    #[arg(long)]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// How long to wait on shutdown for running updates and storage proofs to finish before
+    /// cancelling them, in seconds.
+    #[arg(long, default_value = "60")]
+    pub shutdown_timeout_secs: u64,
+
+    /// Export tracing spans to an OTLP collector over gRPC, e.g. `http://localhost:4317`.
+    #[arg(long)]
+    pub otlp_endpoint: Option<String>,
+
+    /// The number of confirmations to wait for on update transactions.
+    #[arg(long, default_value = "3")]
+    pub confirmations: u64,
+
+    /// How long to wait for an update transaction before replacing it with higher fees, in seconds.
+    #[arg(long, default_value = "60")]
+    pub replacement_timeout_secs: u64,
+
+    /// How many times a stuck update transaction is replaced before giving up.
+    #[arg(long, default_value = "5")]
+    pub max_replacements: u32,
+
+    /// How long an included update transaction may take to be confirmed before it is treated as
+    /// dropped and replaced, in seconds.
+    #[arg(long, default_value = "600")]
+    pub confirmation_timeout_secs: u64,
+
+    /// How much each replacement raises the fees by, in percent.
+    #[arg(long, default_value = "20")]
+    pub fee_bump_percent: u64,
+
+    /// Multiplier applied to the estimated fees, in percent.
+    #[arg(long, default_value = "100")]
+    pub fee_multiplier_percent: u64,
+
+    /// Upper bound on the max fee per gas, in wei.
+    #[arg(long)]
+    pub max_fee_per_gas: Option<u128>,
+
+    /// Upper bound on the max priority fee per gas, in wei.
+    #[arg(long)]
+    pub max_priority_fee_per_gas: Option<u128>,
+}
+
+fn main() {
+    let args = OperatorArgs::parse();
+
+    let config = match &args.config {
+        Some(path) => OperatorConfig::load(path).expect("Failed to load config"),
+        None => {
+            let config = args.to_config();
+            config.validate().expect("Invalid flags");
+            config
+        }
+    };
+
+    // The prover reads its mode from the environment, which is only safe to change before the
+    // runtime starts its worker threads.
+    if let Some(mode) = config.proving.mode {
+        std::env::set_var("SP1_PROVER", mode.as_env());
+    }
+
+    tokio::runtime::Runtime::new()
+        .expect("Failed to start the tokio runtime")
+        .block_on(run(args, config));
 }
 
-#[tokio::main]
-async fn main() {
+async fn run(args: OperatorArgs, config: OperatorConfig) {
+    let tracer_provider = config.telemetry.otlp_endpoint.as_deref().map(|endpoint| {
+        telemetry::otlp_tracer_provider(endpoint).expect("Failed to set up the OTLP exporter")
+    });
     tracing_subscriber::registry()
         .with(fmt::layer())
+        .with(tracer_provider.as_ref().map(|provider| {
+            tracing_opentelemetry::layer().with_tracer(provider.tracer(telemetry::SERVICE_NAME))
+        }))
         .with(
             EnvFilter::try_from_default_env()
                 .unwrap_or_default()
@@ -50,31 +305,225 @@ async fn main() {
         .try_init()
         .expect("Failed to initialize tracing");
 
-    let args = OperatorArgs::parse();
-
     let wallet = EthereumWallet::from(
         args.private_key
             .parse::<PrivateKeySigner>()
             .expect("Failed to parse private key"),
     );
 
+    let (primary, destinations) = config
+        .destinations
+        .split_first()
+        .expect("At least one destination is required");
     let provider = ProviderBuilder::new()
-        .wallet(wallet)
-        .connect_http(args.rpc_url.parse().expect("Failed to parse RPC URL"));
+        .wallet(wallet.clone())
+        .connect_http(primary.rpc_url.parse().expect("Failed to parse RPC URL"));
+
+    // Loading the cache reads every persisted proof, so it runs off the runtime.
+    let cache_config = config.storage_proof_cache_config();
+    let storage_proof_cache =
+        tokio::task::spawn_blocking(move || StorageProofCache::new(cache_config))
+            .await
+            .expect("Storage proof cache loading panicked")
+            .expect("Failed to load storage proof cache");
 
-    let operator = SP1HeliosOperator::new(
+    let mut operator = SP1HeliosOperator::new(
         provider,
-        args.contract_address,
-        args.source_consensus_rpc,
-        args.source_chain_id,
+        primary.contract_address,
+        config
+            .consensus_endpoints()
+            .expect("Invalid consensus RPCs"),
+        config.source_chain_id,
     )
-    .await;
+    .await
+    .expect("Failed to create operator")
+    .with_retry_policy(config.retry_policy())
+    .with_relay_config(config.relay_config())
+    .with_finality_events(config.finality_events)
+    .with_execution_rpcs(
+        config
+            .source
+            .execution_rpcs
+            .iter()
+            .map(|url| {
+                ProviderBuilder::new()
+                    .connect_http(url.parse().expect("Failed to parse execution RPC URL"))
+                    .erased()
+            })
+            .collect(),
+    )
+    .with_storage_fetch_config(config.storage_fetch_config())
+    .with_storage_cycle_budget(config.storage_cycle_budget())
+    .with_proving_config(config.proving_config())
+    .with_storage_proof_cache(storage_proof_cache)
+    .with_inclusion_policy(config.inclusion_policy());
+
+    if let Some(db_dir) = &config.db_dir {
+        operator = operator
+            .with_db(OperatorDb::open(db_dir.clone()).expect("Failed to open operator database"))
+            .expect("Failed to load the stored watchlist");
+    }
+
+    // Seed the watchlist before the first run, so that it already proves the configured slots.
+    if let Some(storage_slots) = config.storage_slots() {
+        operator = operator
+            .with_storage_slots(storage_slots)
+            .expect("Failed to save the configured watchlist");
+    }
+
+    for destination in destinations {
+        let provider = ProviderBuilder::new().wallet(wallet.clone()).connect_http(
+            destination
+                .rpc_url
+                .parse()
+                .expect("Failed to parse RPC URL"),
+        );
+        operator = operator
+            .with_destination(provider, destination.contract_address)
+            .await
+            .expect("Failed to add destination");
+    }
 
     // Run the operator indefinitely, spawns a background task
     tracing::info!("Running operator");
-    let handle = operator.run(Duration::from_secs(args.loop_delay_mins * 60));
+    let handle = operator.run(config.loop_delay());
+
+    if let Some(path) = args.config.clone() {
+        tokio::spawn(config::watch(path, config.clone(), handle.clone()));
+    }
+
+    if let Some(api_addr) = config.api.addr {
+        let auth = config
+            .api
+            .clients
+            .as_deref()
+            .map(|path| Authorizer::from_file(path).expect("Failed to load API clients"));
+        if auth.is_none() {
+            assert!(
+                api_addr.ip().is_loopback(),
+                "Serving the operator API on {api_addr} requires --api-clients"
+            );
+            tracing::warn!("Serving the operator API without authentication");
+        }
+
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            if let Err(e) = server::serve(handle, api_addr, auth).await {
+                tracing::error!("Operator API failed: {:?}", e);
+            }
+        });
+    }
+
+    if let Some(metrics_addr) = config.telemetry.metrics_addr {
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(handle, metrics_addr).await {
+                tracing::error!("Metrics endpoint failed: {:?}", e);
+            }
+        });
+    }
 
     tokio::signal::ctrl_c().await.unwrap();
 
-    handle.shutdown().await;
+    let summary = handle.shutdown(config.shutdown_timeout()).await;
+    if summary.timed_out || !summary.abandoned_jobs.is_empty() {
+        tracing::warn!("Abandoned work on shutdown: {summary:?}");
+    }
+    if let Some(provider) = tracer_provider {
+        if let Err(e) = provider.shutdown() {
+            tracing::warn!("Failed to flush spans: {:?}", e);
+        }
+    }
+}
+
+impl OperatorArgs {
+    /// The settings a config file would cover, taken from the flags.
+    fn to_config(&self) -> OperatorConfig {
+        let primary = DestinationConfig {
+            contract_address: self
+                .contract_address
+                .expect("--contract-address is required"),
+            rpc_url: self.rpc_url.clone().expect("--rpc-url is required"),
+        };
+        let destinations =
+            std::iter::once(primary)
+                .chain(self.destinations.iter().map(|(contract_address, rpc_url)| {
+                    DestinationConfig {
+                        contract_address: *contract_address,
+                        rpc_url: rpc_url.clone(),
+                    }
+                }))
+                .collect();
+
+        OperatorConfig {
+            source_chain_id: self.source_chain_id.expect("--source-chain-id is required"),
+            source: SourceConfig {
+                consensus_rpcs: self.source_consensus_rpc.clone(),
+                consensus_quorum: self.consensus_quorum,
+                consensus_timeout_secs: self.consensus_timeout_secs,
+                execution_rpcs: self.execution_rpc.clone(),
+            },
+            destinations,
+            loop_delay_mins: self.loop_delay_mins,
+            finality_events: self.finality_events,
+            proving: ProvingSettings {
+                mode: None,
+                max_concurrent_proofs: self.max_concurrent_proofs,
+                max_queued_storage_proofs: self.max_queued_storage_proofs,
+                storage_proof_batch_window_ms: self.storage_proof_batch_window_ms,
+            },
+            relay: RelaySettings {
+                confirmations: self.confirmations,
+                replacement_timeout_secs: self.replacement_timeout_secs,
+                max_replacements: self.max_replacements,
+                confirmation_timeout_secs: self.confirmation_timeout_secs,
+                fee_bump_percent: self.fee_bump_percent,
+                fee_multiplier_percent: self.fee_multiplier_percent,
+                max_fee_per_gas: self.max_fee_per_gas,
+                max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+            },
+            retry: RetrySettings {
+                max_retries: self.rpc_max_retries,
+                initial_backoff_ms: self.rpc_initial_backoff_ms,
+                max_backoff_ms: self.rpc_max_backoff_ms,
+                failure_threshold: self.rpc_failure_threshold,
+                unhealthy_secs: self.rpc_unhealthy_secs,
+                timeout_secs: self.execution_timeout_secs,
+            },
+            storage: StorageSettings {
+                max_keys_per_proof_request: self.max_keys_per_proof_request,
+                max_concurrent_proof_requests: self.max_concurrent_proof_requests,
+                max_update_cycles: self.max_update_storage_cycles,
+                max_follow_up_cycles: self.max_follow_up_storage_cycles,
+                cache_entries: self.storage_proof_cache_entries,
+                cache_max_age_mins: self.storage_proof_cache_max_age_mins,
+                cache_dir: self.storage_proof_cache_dir.clone(),
+                changed_slots_only: self.changed_slots_only,
+                slot_max_staleness_mins: self.slot_max_staleness_mins,
+            },
+            db_dir: self.db_dir.clone(),
+            api: ApiSettings {
+                addr: self.api_addr,
+                clients: self.api_clients.clone(),
+            },
+            telemetry: TelemetrySettings {
+                metrics_addr: self.metrics_addr,
+                otlp_endpoint: self.otlp_endpoint.clone(),
+            },
+            shutdown_timeout_secs: self.shutdown_timeout_secs,
+            watchlist: None,
+        }
+    }
+}
+
+/// Parse a destination given as `<contract-address>@<rpc-url>`.
+fn parse_destination(s: &str) -> Result<(Address, String), String> {
+    let (address, rpc_url) = s
+        .split_once('@')
+        .ok_or_else(|| format!("expected <contract-address>@<rpc-url>, got {s}"))?;
+    let address = address
+        .parse()
+        .map_err(|e| format!("invalid contract address {address}: {e}"))?;
+
+    Ok((address, rpc_url.to_string()))
 }
@@ -0,0 +1,188 @@
+use crate::handle::ContractKeys;
+use alloy::primitives::{keccak256, B256};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// An API client and the limits applied to its storage proof requests.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiClient {
+    /// A unique name for the client, used in logs and to tell whose jobs are whose.
+    pub name: String,
+    /// The key the client authenticates with.
+    pub key: String,
+    /// How many storage proof requests the client may make per minute.
+    pub requests_per_minute: u32,
+    /// The maximum number of contracts in a single storage proof request.
+    pub max_contracts_per_request: usize,
+    /// The maximum number of storage slots, across all contracts, in a single storage proof
+    /// request.
+    pub max_slots_per_request: usize,
+    /// The maximum number of storage slots the client may have on the watchlist at once.
+    pub max_watched_slots: usize,
+    /// Whether the client may change or remove storage slots other clients or the config added.
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// Why a request was rejected.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Rejection {
+    #[error("missing or unknown API key")]
+    Unauthorized,
+    #[error("rate limit exceeded, retry in {}s", retry_after.as_secs().max(1))]
+    RateLimited { retry_after: Duration },
+    #[error("request has {requested} contracts, the limit is {max}")]
+    TooManyContracts { requested: usize, max: usize },
+    #[error("request has {requested} storage slots, the limit is {max}")]
+    TooManySlots { requested: usize, max: usize },
+    #[error("client already watches {max} storage slots, the limit")]
+    TooManyWatchedSlots { max: usize },
+    #[error(
+        "storage slots added by another client or the config can only be changed or removed by an admin"
+    )]
+    NotOwner,
+}
+
+impl Rejection {
+    /// A stable, machine-readable code for the rejection.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Rejection::Unauthorized => "unauthorized",
+            Rejection::RateLimited { .. } => "rate_limited",
+            Rejection::TooManyContracts { .. } => "too_many_contracts",
+            Rejection::TooManySlots { .. } => "too_many_slots",
+            Rejection::TooManyWatchedSlots { .. } => "too_many_watched_slots",
+            Rejection::NotOwner => "not_owner",
+        }
+    }
+}
+
+/// Authenticates API clients and enforces their storage proof quotas.
+pub struct Authorizer {
+    /// The clients, keyed by the keccak256 hash of their API key, so that looking a key up does
+    /// not leak how much of it matches a known one through timing.
+    clients: HashMap<B256, ApiClient>,
+    /// Token buckets for each client's rate limit, keyed by client name.
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl Authorizer {
+    pub fn new(clients: Vec<ApiClient>) -> Self {
+        Self {
+            clients: clients
+                .into_iter()
+                .map(|client| (keccak256(&client.key), client))
+                .collect(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Load the clients from a JSON file containing a list of [`ApiClient`]s.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read API clients from {}", path.display()))?;
+        let clients: Vec<ApiClient> = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse API clients from {}", path.display()))?;
+
+        let mut names = HashSet::new();
+        if let Some(client) = clients.iter().find(|client| !names.insert(&client.name)) {
+            anyhow::bail!(
+                "API client name {} is used more than once in {}",
+                client.name,
+                path.display()
+            );
+        }
+
+        Ok(Self::new(clients))
+    }
+
+    /// Authenticate a client by its API key.
+    pub fn authenticate(&self, key: Option<&str>) -> Result<&ApiClient, Rejection> {
+        key.and_then(|key| self.clients.get(&keccak256(key)))
+            .ok_or(Rejection::Unauthorized)
+    }
+
+    /// Authenticate a client and check a storage proof request against its quota.
+    ///
+    /// Only requests within the size limits count against the client's rate limit.
+    pub fn authorize_proof_request(
+        &self,
+        key: Option<&str>,
+        contract_keys: &[ContractKeys],
+    ) -> Result<&ApiClient, Rejection> {
+        let client = self.authenticate(key)?;
+
+        if contract_keys.len() > client.max_contracts_per_request {
+            return Err(Rejection::TooManyContracts {
+                requested: contract_keys.len(),
+                max: client.max_contracts_per_request,
+            });
+        }
+
+        let slots = contract_keys
+            .iter()
+            .map(|keys| keys.storage_slots.len())
+            .sum();
+        if slots > client.max_slots_per_request {
+            return Err(Rejection::TooManySlots {
+                requested: slots,
+                max: client.max_slots_per_request,
+            });
+        }
+
+        self.buckets
+            .lock()
+            .expect("Rate limit lock poisoned")
+            .entry(client.name.clone())
+            .or_insert_with(|| TokenBucket::new(client.requests_per_minute))
+            .take()
+            .map_err(|retry_after| Rejection::RateLimited { retry_after })?;
+
+        Ok(client)
+    }
+}
+
+/// A token bucket that refills continuously up to a capacity of one minute's worth of requests.
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(requests_per_minute: u32) -> Self {
+        let capacity = requests_per_minute as f64;
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_sec: capacity / 60.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take a token, or return how long until one is available.
+    fn take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+
+        if self.refill_per_sec <= 0.0 {
+            return Err(Duration::MAX);
+        }
+
+        Err(Duration::from_secs_f64(
+            (1.0 - self.tokens) / self.refill_per_sec,
+        ))
+    }
+}
@@ -0,0 +1,114 @@
+use crate::handle::{ContractKeys, StorageProofRequest};
+use crate::jobs::JobId;
+use alloy::primitives::{Address, B256};
+use std::collections::{HashMap, HashSet};
+
+/// Storage proof requests for the same block, proven together over the union of their keys.
+pub struct StorageProofBatch {
+    pub block_number: u64,
+    requests: Vec<(JobId, Vec<ContractKeys>)>,
+    /// The jobs that asked for the proof to be relayed.
+    relay: HashSet<JobId>,
+}
+
+impl StorageProofBatch {
+    pub fn new(request: StorageProofRequest) -> Self {
+        let mut batch = Self {
+            block_number: request.block_number,
+            requests: vec![],
+            relay: HashSet::new(),
+        };
+        batch.push(request);
+        batch
+    }
+
+    /// Add a request for the same block to the batch.
+    pub fn push(&mut self, request: StorageProofRequest) {
+        debug_assert_eq!(request.block_number, self.block_number);
+        if request.relay {
+            self.relay.insert(request.job);
+        }
+        self.requests.push((request.job, request.contract_keys));
+    }
+
+    /// Keep only the requests of the jobs matching `f`.
+    pub fn retain(&mut self, f: impl Fn(JobId) -> bool) {
+        self.requests.retain(|(job, _)| f(*job));
+        self.relay.retain(|job| f(*job));
+    }
+
+    /// Whether the job asked for the proof to be relayed.
+    pub fn relays(&self, job: JobId) -> bool {
+        self.relay.contains(&job)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    pub fn jobs(&self) -> Vec<JobId> {
+        self.requests.iter().map(|(job, _)| *job).collect()
+    }
+
+    /// The union of every request's keys, with contracts and slots in order of first request.
+    pub fn contract_keys(&self) -> Vec<ContractKeys> {
+        let mut union: Vec<ContractKeys> = Vec::new();
+        let mut contracts: HashMap<Address, (usize, HashSet<B256>)> = HashMap::new();
+
+        for keys in self.requests.iter().flat_map(|(_, keys)| keys) {
+            let (i, seen) = contracts.entry(keys.address).or_insert_with(|| {
+                union.push(ContractKeys {
+                    address: keys.address,
+                    storage_slots: vec![],
+                });
+                (union.len() - 1, HashSet::new())
+            });
+
+            for slot in &keys.storage_slots {
+                if seen.insert(*slot) {
+                    union[*i].storage_slots.push(*slot);
+                }
+            }
+        }
+
+        union
+    }
+
+    /// The position of each job's slots in the outputs of a proof over [`Self::contract_keys`].
+    pub fn slot_indices(&self) -> Vec<(JobId, Vec<usize>)> {
+        let positions = slot_positions(&self.contract_keys());
+        let positions = &positions;
+
+        self.requests
+            .iter()
+            .map(|(job, keys)| {
+                let indices = keys
+                    .iter()
+                    .flat_map(|keys| {
+                        keys.storage_slots
+                            .iter()
+                            .map(move |slot| positions[&(keys.address, *slot)])
+                    })
+                    .collect();
+
+                (*job, indices)
+            })
+            .collect()
+    }
+}
+
+/// The position of each storage slot in the outputs of a proof over `contract_keys`.
+///
+/// The storage program outputs the slots of each contract in turn, in the order given.
+pub fn slot_positions(contract_keys: &[ContractKeys]) -> HashMap<(Address, B256), usize> {
+    contract_keys
+        .iter()
+        .flat_map(|keys| {
+            keys.storage_slots
+                .iter()
+                .map(move |slot| (keys.address, *slot))
+        })
+        .enumerate()
+        .map(|(i, key)| (key, i))
+        .collect()
+}
@@ -0,0 +1,146 @@
+use alloy::primitives::{Address, Bytes, B256};
+use sp1_helios_primitives::types::{ContractStorage, StorageSlotWithProof};
+use std::collections::HashMap;
+
+/// Rough zkVM cycles to hash and decode one node of a Merkle-Patricia proof.
+const CYCLES_PER_PROOF_NODE: u64 = 20_000;
+/// Rough zkVM cycles per byte of a Merkle-Patricia proof, mostly spent hashing it.
+const CYCLES_PER_PROOF_BYTE: u64 = 60;
+
+/// Limits on the estimated cycles spent verifying storage proofs in a single proof.
+///
+/// Costs are estimated from the node count and size of each account and storage proof.
+#[derive(Debug, Clone, Default)]
+pub struct StorageCycleBudget {
+    /// The estimated cycles of storage verification allowed in an update proof. Watched slots
+    /// beyond it are proven in follow-up storage proofs. Unlimited by default.
+    pub max_update_cycles: Option<u64>,
+    /// The estimated cycles allowed in each follow-up storage proof. Unlimited by default.
+    pub max_follow_up_cycles: Option<u64>,
+}
+
+/// The estimated cycles to verify a Merkle-Patricia proof.
+pub fn proof_cycles(proof: &[Bytes]) -> u64 {
+    proof
+        .iter()
+        .map(|node| CYCLES_PER_PROOF_NODE + node.len() as u64 * CYCLES_PER_PROOF_BYTE)
+        .sum()
+}
+
+/// Storage proofs split between an update and the storage proofs that follow it.
+pub struct SplitStorage {
+    pub update: Vec<ContractStorage>,
+    pub follow_ups: Vec<Vec<ContractStorage>>,
+}
+
+/// Split fetched storage between an update proof and follow-up storage proofs, in order, within
+/// the budget.
+///
+/// Slots matching `required` always stay in the update. A slot that does not fit any proof on its
+/// own is proven alone.
+pub fn split_storage(
+    contracts: Vec<ContractStorage>,
+    budget: &StorageCycleBudget,
+    required: impl Fn(Address, B256) -> bool,
+) -> SplitStorage {
+    let Some(max_update_cycles) = budget.max_update_cycles else {
+        return SplitStorage {
+            update: contracts,
+            follow_ups: vec![],
+        };
+    };
+
+    let mut update = Chunk::default();
+    for contract in &contracts {
+        for slot in &contract.storage_slots {
+            if required(contract.address, slot.key) {
+                update.push(contract, Some(slot));
+            }
+        }
+    }
+
+    let mut follow_ups: Vec<Chunk> = Vec::new();
+    let max_follow_up_cycles = budget.max_follow_up_cycles.unwrap_or(u64::MAX);
+    let mut place = |contract: &ContractStorage, slot: Option<&StorageSlotWithProof>| {
+        if update.fits(contract, slot, max_update_cycles) {
+            update.push(contract, slot);
+            return;
+        }
+
+        match follow_ups.last_mut() {
+            Some(chunk) if chunk.fits(contract, slot, max_follow_up_cycles) => {
+                chunk.push(contract, slot)
+            }
+            _ => {
+                let mut chunk = Chunk::default();
+                chunk.push(contract, slot);
+                follow_ups.push(chunk);
+            }
+        }
+    };
+
+    for contract in &contracts {
+        if contract.storage_slots.is_empty() {
+            place(contract, None);
+        }
+        for slot in &contract.storage_slots {
+            if !required(contract.address, slot.key) {
+                place(contract, Some(slot));
+            }
+        }
+    }
+
+    SplitStorage {
+        update: update.contracts,
+        follow_ups: follow_ups
+            .into_iter()
+            .map(|chunk| chunk.contracts)
+            .collect(),
+    }
+}
+
+/// The contracts and slots of one proof, and their estimated cycles.
+#[derive(Default)]
+struct Chunk {
+    contracts: Vec<ContractStorage>,
+    positions: HashMap<Address, usize>,
+    cycles: u64,
+}
+
+impl Chunk {
+    fn added_cycles(&self, contract: &ContractStorage, slot: Option<&StorageSlotWithProof>) -> u64 {
+        let account = if self.positions.contains_key(&contract.address) {
+            0
+        } else {
+            proof_cycles(&contract.mpt_proof)
+        };
+
+        account + slot.map_or(0, |slot| proof_cycles(&slot.mpt_proof))
+    }
+
+    fn fits(
+        &self,
+        contract: &ContractStorage,
+        slot: Option<&StorageSlotWithProof>,
+        max_cycles: u64,
+    ) -> bool {
+        self.cycles
+            .saturating_add(self.added_cycles(contract, slot))
+            <= max_cycles
+    }
+
+    fn push(&mut self, contract: &ContractStorage, slot: Option<&StorageSlotWithProof>) {
+        self.cycles += self.added_cycles(contract, slot);
+
+        let i = *self.positions.entry(contract.address).or_insert_with(|| {
+            self.contracts.push(ContractStorage {
+                address: contract.address,
+                value: contract.value,
+                mpt_proof: contract.mpt_proof.clone(),
+                storage_slots: vec![],
+            });
+            self.contracts.len() - 1
+        });
+        self.contracts[i].storage_slots.extend(slot.cloned());
+    }
+}
@@ -0,0 +1,403 @@
+use crate::batch::slot_positions;
+use crate::fs::{write_atomic, TMP_EXTENSION};
+use crate::handle::ContractKeys;
+use crate::jobs::StorageProof;
+use alloy::primitives::{keccak256, Address, B256};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sp1_sdk::SP1ProofWithPublicValues;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+/// Limits on the storage proof cache.
+#[derive(Debug, Clone)]
+pub struct StorageProofCacheConfig {
+    /// The maximum number of proofs kept. The least recently used proofs are evicted first.
+    pub max_entries: usize,
+    /// How long a proof is kept after it was generated.
+    pub max_age: Duration,
+    /// A directory to persist proofs to, so they survive restarts.
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for StorageProofCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 128,
+            max_age: Duration::from_secs(24 * 60 * 60),
+            dir: None,
+        }
+    }
+}
+
+/// Hit and miss counts of the storage proof cache.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    block_number: u64,
+    /// The vkey of the storage slots program build the proof was generated with.
+    storage_vkey: B256,
+    /// The contracts and slots the proof covers, in the order they were proven.
+    contract_keys: Vec<ContractKeys>,
+    proof: SP1ProofWithPublicValues,
+    /// When the proof was generated, in seconds since the Unix epoch.
+    created_at: u64,
+}
+
+impl CacheEntry {
+    /// The slot indices of `contract_keys` in this proof, if it covers all of them.
+    fn slot_indices(&self, contract_keys: &[ContractKeys]) -> Option<Vec<usize>> {
+        let covered = contract_keys.iter().all(|keys| {
+            self.contract_keys
+                .iter()
+                .any(|cached| cached.address == keys.address)
+        });
+        if !covered {
+            return None;
+        }
+
+        let positions = slot_positions(&self.contract_keys);
+        let positions = &positions;
+        contract_keys
+            .iter()
+            .flat_map(|keys| {
+                keys.storage_slots
+                    .iter()
+                    .map(move |slot| positions.get(&(keys.address, *slot)).copied())
+            })
+            .collect()
+    }
+
+    fn is_expired(&self, max_age: Duration) -> bool {
+        now().saturating_sub(self.created_at) > max_age.as_secs()
+    }
+
+    /// Identifies the entry by its block, program and the sorted set of keys it covers.
+    fn file_name(&self) -> String {
+        let keys: BTreeSet<(Address, Option<B256>)> = self
+            .contract_keys
+            .iter()
+            .flat_map(|keys| {
+                std::iter::once((keys.address, None)).chain(
+                    keys.storage_slots
+                        .iter()
+                        .map(|slot| (keys.address, Some(*slot))),
+                )
+            })
+            .collect();
+
+        let mut preimage = Vec::new();
+        for (address, slot) in keys {
+            preimage.extend_from_slice(address.as_slice());
+            if let Some(slot) = slot {
+                preimage.extend_from_slice(slot.as_slice());
+            }
+        }
+
+        format!(
+            "{}-{}-{}.cbor",
+            self.block_number,
+            self.storage_vkey,
+            keccak256(preimage)
+        )
+    }
+}
+
+/// Caches storage proofs by block, program and key set, in memory and optionally on disk.
+///
+/// A request is served from any cached proof for the same block that covers all of its slots, and
+/// was generated with the build of the storage slots program the destination expects.
+pub struct StorageProofCache {
+    config: StorageProofCacheConfig,
+    /// Entries from least to most recently used.
+    entries: Mutex<Vec<CacheEntry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl StorageProofCache {
+    /// Create a cache, loading any unexpired proofs persisted in its directory.
+    ///
+    /// With a directory, this blocks on disk I/O, so async code runs it with
+    /// [`tokio::task::spawn_blocking`].
+    pub fn new(config: StorageProofCacheConfig) -> Result<Self> {
+        let mut entries = Vec::new();
+        if let Some(dir) = &config.dir {
+            std::fs::create_dir_all(dir).with_context(|| {
+                format!("Failed to create storage proof cache {}", dir.display())
+            })?;
+            entries = load_entries(dir, config.max_age)?;
+        }
+        entries.sort_by_key(|entry| entry.created_at);
+
+        let cache = Self {
+            config,
+            entries: Mutex::new(entries),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        };
+        let evicted = cache.evict(&mut cache.lock());
+        remove_files(&cache.paths(&evicted));
+
+        Ok(cache)
+    }
+
+    /// Get a cached proof for the given block and storage slots vkey that covers every requested
+    /// slot, counting the lookup as a hit or a miss.
+    pub async fn get(
+        &self,
+        block_number: u64,
+        storage_vkey: B256,
+        contract_keys: &[ContractKeys],
+    ) -> Option<StorageProof> {
+        let proof = self.find(block_number, storage_vkey, contract_keys).await;
+        match proof {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+
+        proof
+    }
+
+    /// Like [`Self::get`], without counting the lookup, for further lookups on behalf of a
+    /// request whose first lookup was already counted.
+    ///
+    /// Resolves once the files of the proofs evicted on the way, if any, are removed.
+    pub async fn find(
+        &self,
+        block_number: u64,
+        storage_vkey: B256,
+        contract_keys: &[ContractKeys],
+    ) -> Option<StorageProof> {
+        let mut entries = self.lock();
+        let evicted = self.evict(&mut entries);
+
+        let hit = entries.iter().enumerate().rev().find_map(|(i, entry)| {
+            if entry.block_number != block_number || entry.storage_vkey != storage_vkey {
+                return None;
+            }
+            entry
+                .slot_indices(contract_keys)
+                .map(|slot_indices| (i, slot_indices))
+        });
+
+        let proof = hit.map(|(i, slot_indices)| {
+            // Mark the entry as most recently used.
+            let entry = entries.remove(i);
+            let proof = entry.proof.clone();
+            entries.push(entry);
+
+            StorageProof {
+                proof,
+                slot_indices,
+                receipts: vec![],
+            }
+        });
+        drop(entries);
+        let removed = self.paths(&evicted);
+        if !removed.is_empty() {
+            let _ = tokio::task::spawn_blocking(move || remove_files(&removed)).await;
+        }
+
+        proof
+    }
+
+    /// Like [`Self::find`], or if no cached proof covers `contract_keys`, generate one with
+    /// `prove` and cache it, along with what else `prove` returned.
+    pub async fn find_or_prove<T, F, Fut>(
+        &self,
+        block_number: u64,
+        storage_vkey: B256,
+        contract_keys: Vec<ContractKeys>,
+        prove: F,
+    ) -> Result<(SP1ProofWithPublicValues, Option<T>)>
+    where
+        F: FnOnce(Vec<ContractKeys>) -> Fut,
+        Fut: Future<Output = Result<(SP1ProofWithPublicValues, T)>>,
+    {
+        if let Some(cached) = self.find(block_number, storage_vkey, &contract_keys).await {
+            return Ok((cached.proof, None));
+        }
+
+        let (proof, generated) = prove(contract_keys.clone()).await?;
+        self.insert(block_number, storage_vkey, contract_keys, proof.clone())
+            .await;
+
+        Ok((proof, Some(generated)))
+    }
+
+    /// Cache a proof over `contract_keys`, in the order they were proven with the storage slots
+    /// program of `storage_vkey`.
+    ///
+    /// Cached proofs of the same program covering a subset of the same keys are replaced. With a
+    /// directory, resolves once the proof is persisted.
+    pub async fn insert(
+        &self,
+        block_number: u64,
+        storage_vkey: B256,
+        contract_keys: Vec<ContractKeys>,
+        proof: SP1ProofWithPublicValues,
+    ) {
+        if self.config.max_entries == 0 {
+            return;
+        }
+
+        let entry = CacheEntry {
+            block_number,
+            storage_vkey,
+            contract_keys,
+            proof,
+            created_at: now(),
+        };
+
+        let mut entries = self.lock();
+        let mut evicted = Vec::new();
+        let mut i = 0;
+        while i < entries.len() {
+            let other = &entries[i];
+            if other.block_number == block_number
+                && other.storage_vkey == storage_vkey
+                && entry.slot_indices(&other.contract_keys).is_some()
+            {
+                evicted.push(entries.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+
+        let file_name = entry.file_name();
+        let contents = self.config.dir.as_ref().map(|_| serde_cbor::to_vec(&entry));
+        entries.push(entry);
+        evicted.extend(self.evict(&mut entries));
+        drop(entries);
+
+        let (Some(dir), Some(contents)) = (&self.config.dir, contents) else {
+            return;
+        };
+        let removed = self.paths(&evicted);
+        let path = dir.join(file_name);
+        let persist = tokio::task::spawn_blocking(move || {
+            remove_files(&removed);
+            write_atomic(&path, &contents?)
+        });
+        if let Err(e) = persist
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result)
+        {
+            warn!("Failed to persist storage proof: {:?}", e);
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.lock().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Remove expired entries, then the least recently used ones over the size limit.
+    fn evict(&self, entries: &mut Vec<CacheEntry>) -> Vec<CacheEntry> {
+        let mut evicted = Vec::new();
+        let mut i = 0;
+        while i < entries.len() {
+            if entries[i].is_expired(self.config.max_age) {
+                evicted.push(entries.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+
+        let excess = entries.len().saturating_sub(self.config.max_entries);
+        evicted.extend(entries.drain(..excess));
+
+        evicted
+    }
+
+    /// Where the given entries are persisted, if anywhere.
+    fn paths(&self, entries: &[CacheEntry]) -> Vec<PathBuf> {
+        let Some(dir) = &self.config.dir else {
+            return vec![];
+        };
+
+        entries
+            .iter()
+            .map(|entry| dir.join(entry.file_name()))
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<CacheEntry>> {
+        self.entries
+            .lock()
+            .expect("Storage proof cache lock poisoned")
+    }
+}
+
+fn remove_files(paths: &[PathBuf]) {
+    for path in paths {
+        if let Err(e) = std::fs::remove_file(path) {
+            debug!(
+                "Failed to remove cached storage proof {}: {e}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// Load the unexpired entries persisted in `dir`, removing expired and unreadable ones, and
+/// temporary files left by a crash mid-write.
+fn load_entries(dir: &Path, max_age: Duration) -> Result<Vec<CacheEntry>> {
+    let mut entries = Vec::new();
+    for file in std::fs::read_dir(dir)? {
+        let path = file?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == TMP_EXTENSION)
+        {
+            let _ = std::fs::remove_file(&path);
+            continue;
+        }
+        if path.extension().is_none_or(|extension| extension != "cbor") {
+            continue;
+        }
+
+        let entry = std::fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| Ok(serde_cbor::from_slice::<CacheEntry>(&contents)?));
+        match entry {
+            Ok(entry) if !entry.is_expired(max_age) => entries.push(entry),
+            Ok(_) => {
+                let _ = std::fs::remove_file(&path);
+            }
+            Err(e) => {
+                warn!(
+                    "Removing unreadable cached storage proof {}: {:?}",
+                    path.display(),
+                    e
+                );
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -0,0 +1,511 @@
+use crate::budget::StorageCycleBudget;
+use crate::cache::StorageProofCacheConfig;
+use crate::execution::StorageFetchConfig;
+use crate::handle::{ContractKeys, OperatorHandle};
+use crate::inclusion::InclusionPolicy;
+use crate::retry::RetryPolicy;
+use crate::rpc::ConsensusEndpoints;
+use crate::scheduler::ProvingConfig;
+use crate::transaction::{FeePolicy, RelayConfig};
+use alloy::primitives::{Address, B256};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// How often the config file is checked for changes.
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The operator's settings, read from a TOML or YAML file.
+///
+/// The file covers every setting but the signer's private key. The watchlist, relay settings and
+/// loop delay are reloaded while the operator runs; the other settings take effect on restart.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OperatorConfig {
+    /// The chain ID of the source chain.
+    pub source_chain_id: u64,
+    pub source: SourceConfig,
+    /// The light client contracts to keep updated. The first one's RPC is also used to read
+    /// execution state when no execution RPCs are set.
+    pub destinations: Vec<DestinationConfig>,
+    /// The delay between operator runs, in minutes.
+    #[serde(default = "default_loop_delay_mins")]
+    pub loop_delay_mins: u64,
+    /// Trigger updates from the beacon node's finality events, on top of polling.
+    #[serde(default)]
+    pub finality_events: bool,
+    #[serde(default)]
+    pub proving: ProvingSettings,
+    #[serde(default)]
+    pub relay: RelaySettings,
+    #[serde(default)]
+    pub retry: RetrySettings,
+    #[serde(default)]
+    pub storage: StorageSettings,
+    /// A directory to persist the watchlist, generated proofs and their relays to.
+    #[serde(default)]
+    pub db_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub api: ApiSettings,
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
+    /// How long to wait on shutdown for running work to finish, in seconds.
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// The storage slots proven with every update. If not set, the watchlist is left to the API
+    /// and the operator's database.
+    #[serde(default)]
+    pub watchlist: Option<Vec<ContractKeys>>,
+}
+
+/// The RPCs of the source chain.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+    /// Consensus RPC URLs, tried in order.
+    pub consensus_rpcs: Vec<String>,
+    /// How many consensus RPCs must agree on bootstraps and finality updates.
+    #[serde(default = "default_consensus_quorum")]
+    pub consensus_quorum: usize,
+    /// How long to wait for a consensus RPC before failing over, in seconds.
+    #[serde(default = "default_consensus_timeout_secs")]
+    pub consensus_timeout_secs: u64,
+    /// Execution RPC URLs used to fetch storage proofs, tried in order.
+    #[serde(default)]
+    pub execution_rpcs: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DestinationConfig {
+    pub contract_address: Address,
+    pub rpc_url: String,
+}
+
+/// Which prover generates proofs, as the `SP1_PROVER` environment variable selects it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProvingMode {
+    Cpu,
+    Cuda,
+    Network,
+    Mock,
+}
+
+impl ProvingMode {
+    /// The value of `SP1_PROVER` that selects this prover.
+    pub fn as_env(&self) -> &'static str {
+        match self {
+            Self::Cpu => "cpu",
+            Self::Cuda => "cuda",
+            Self::Network => "network",
+            Self::Mock => "mock",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProvingSettings {
+    /// The prover to use. If not set, `SP1_PROVER` is left as is.
+    pub mode: Option<ProvingMode>,
+    /// The maximum number of proofs generated at once.
+    pub max_concurrent_proofs: usize,
+    /// The maximum number of storage proof requests waiting to be proven.
+    pub max_queued_storage_proofs: usize,
+    /// How long to wait for more storage proof requests for the same block, in milliseconds.
+    pub storage_proof_batch_window_ms: u64,
+}
+
+impl Default for ProvingSettings {
+    fn default() -> Self {
+        let config = ProvingConfig::default();
+        Self {
+            mode: None,
+            max_concurrent_proofs: config.max_concurrent_proofs,
+            max_queued_storage_proofs: config.max_queued_storage_proofs,
+            storage_proof_batch_window_ms: config.batch_window.as_millis() as u64,
+        }
+    }
+}
+
+/// How transactions are priced, replaced and confirmed.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelaySettings {
+    pub confirmations: u64,
+    pub replacement_timeout_secs: u64,
+    pub max_replacements: u32,
+    /// How long an included transaction may take to be confirmed before it is replaced.
+    pub confirmation_timeout_secs: u64,
+    pub fee_bump_percent: u64,
+    pub fee_multiplier_percent: u64,
+    /// Upper bound on the max fee per gas, in wei.
+    pub max_fee_per_gas: Option<u128>,
+    /// Upper bound on the max priority fee per gas, in wei.
+    pub max_priority_fee_per_gas: Option<u128>,
+}
+
+impl Default for RelaySettings {
+    fn default() -> Self {
+        let config = RelayConfig::default();
+        Self {
+            confirmations: config.confirmations,
+            replacement_timeout_secs: config.replacement_timeout.as_secs(),
+            max_replacements: config.max_replacements,
+            confirmation_timeout_secs: config.confirmation_timeout.as_secs(),
+            fee_bump_percent: config.fees.bump_percent,
+            fee_multiplier_percent: config.fees.estimate_multiplier_percent,
+            max_fee_per_gas: config.fees.max_fee_per_gas_cap,
+            max_priority_fee_per_gas: config.fees.max_priority_fee_per_gas_cap,
+        }
+    }
+}
+
+/// How failed RPC requests are retried, and when an endpoint is skipped as unhealthy.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetrySettings {
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// How many requests in a row must fail before an endpoint is skipped.
+    pub failure_threshold: u32,
+    /// How long an unhealthy endpoint is skipped, in seconds.
+    pub unhealthy_secs: u64,
+    /// How long to wait for an execution RPC before failing over, in seconds.
+    pub timeout_secs: u64,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        let policy = RetryPolicy::default();
+        Self {
+            max_retries: policy.max_retries,
+            initial_backoff_ms: policy.initial_backoff.as_millis() as u64,
+            max_backoff_ms: policy.max_backoff.as_millis() as u64,
+            failure_threshold: policy.failure_threshold,
+            unhealthy_secs: policy.unhealthy_for.as_secs(),
+            timeout_secs: policy.timeout.as_secs(),
+        }
+    }
+}
+
+/// How storage proofs are fetched, budgeted, cached and included in updates.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageSettings {
+    /// The maximum number of storage keys requested in a single `eth_getProof` call.
+    pub max_keys_per_proof_request: usize,
+    /// The maximum number of `eth_getProof` calls in flight at once.
+    pub max_concurrent_proof_requests: usize,
+    /// The estimated zkVM cycles of storage verification allowed in an update proof.
+    pub max_update_cycles: Option<u64>,
+    /// The estimated zkVM cycles allowed in each follow-up storage proof.
+    pub max_follow_up_cycles: Option<u64>,
+    /// The maximum number of storage proofs cached, or 0 to disable the cache.
+    pub cache_entries: usize,
+    /// How long a cached storage proof is kept, in minutes.
+    pub cache_max_age_mins: u64,
+    /// A directory to persist cached storage proofs to.
+    pub cache_dir: Option<PathBuf>,
+    /// Only include watched slots whose value changed since it was last written.
+    pub changed_slots_only: bool,
+    /// Include unchanged slots anyway once they were last written this many minutes ago.
+    pub slot_max_staleness_mins: Option<u64>,
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        let fetch = StorageFetchConfig::default();
+        let cache = StorageProofCacheConfig::default();
+        Self {
+            max_keys_per_proof_request: fetch.max_keys_per_request,
+            max_concurrent_proof_requests: fetch.max_concurrent_requests,
+            max_update_cycles: None,
+            max_follow_up_cycles: None,
+            cache_entries: cache.max_entries,
+            cache_max_age_mins: cache.max_age.as_secs() / 60,
+            cache_dir: cache.dir,
+            changed_slots_only: false,
+            slot_max_staleness_mins: None,
+        }
+    }
+}
+
+/// Where the operator's HTTP JSON API is served, and who may use it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiSettings {
+    /// The address to serve the API on. If not set, the API is not served.
+    pub addr: Option<SocketAddr>,
+    /// A JSON file listing the API clients and their quotas. If not set, the API is
+    /// unauthenticated.
+    pub clients: Option<PathBuf>,
+}
+
+/// Where metrics and tracing spans are exported.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetrySettings {
+    /// The address to serve Prometheus metrics on.
+    pub metrics_addr: Option<SocketAddr>,
+    /// The OTLP collector to export tracing spans to over gRPC.
+    pub otlp_endpoint: Option<String>,
+}
+
+fn default_loop_delay_mins() -> u64 {
+    5
+}
+
+fn default_consensus_quorum() -> usize {
+    1
+}
+
+fn default_consensus_timeout_secs() -> u64 {
+    30
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    60
+}
+
+impl OperatorConfig {
+    /// Read a config file, as YAML if it has a `.yaml` or `.yml` extension and TOML otherwise.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+
+        let config: Self = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => serde_yaml_ng::from_str(&contents)?,
+            _ => toml::from_str(&contents)?,
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Check that the settings are consistent, as done when loading a config file.
+    pub fn validate(&self) -> Result<()> {
+        if self.destinations.is_empty() {
+            anyhow::bail!("At least one destination is required");
+        }
+        if self.source.consensus_rpcs.is_empty() {
+            anyhow::bail!("At least one consensus RPC is required");
+        }
+        if !(1..=self.source.consensus_rpcs.len()).contains(&self.source.consensus_quorum) {
+            anyhow::bail!(
+                "source.consensus_quorum must be between 1 and the number of consensus RPCs ({})",
+                self.source.consensus_rpcs.len()
+            );
+        }
+        if self.storage.max_follow_up_cycles.is_some() && self.storage.max_update_cycles.is_none() {
+            anyhow::bail!("storage.max_follow_up_cycles requires storage.max_update_cycles");
+        }
+        if self.storage.slot_max_staleness_mins.is_some() && !self.storage.changed_slots_only {
+            anyhow::bail!("storage.slot_max_staleness_mins requires storage.changed_slots_only");
+        }
+        if self.api.clients.is_some() && self.api.addr.is_none() {
+            anyhow::bail!("api.clients requires api.addr");
+        }
+        if let Some(addr) = self.api.addr {
+            if self.api.clients.is_none() && !addr.ip().is_loopback() {
+                anyhow::bail!(
+                    "api.addr {addr} is not a loopback address, so api.clients is required"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn consensus_endpoints(&self) -> Result<ConsensusEndpoints> {
+        Ok(ConsensusEndpoints {
+            quorum: self.source.consensus_quorum,
+            timeout: Duration::from_secs(self.source.consensus_timeout_secs),
+            retry: self.retry_policy(),
+            ..ConsensusEndpoints::new(self.source.consensus_rpcs.clone())?
+        })
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.retry.max_retries,
+            initial_backoff: Duration::from_millis(self.retry.initial_backoff_ms),
+            max_backoff: Duration::from_millis(self.retry.max_backoff_ms),
+            failure_threshold: self.retry.failure_threshold,
+            unhealthy_for: Duration::from_secs(self.retry.unhealthy_secs),
+            timeout: Duration::from_secs(self.retry.timeout_secs),
+        }
+    }
+
+    pub fn relay_config(&self) -> RelayConfig {
+        RelayConfig {
+            confirmations: self.relay.confirmations,
+            replacement_timeout: Duration::from_secs(self.relay.replacement_timeout_secs),
+            max_replacements: self.relay.max_replacements,
+            confirmation_timeout: Duration::from_secs(self.relay.confirmation_timeout_secs),
+            retry: self.retry_policy(),
+            fees: FeePolicy {
+                estimate_multiplier_percent: self.relay.fee_multiplier_percent,
+                bump_percent: self.relay.fee_bump_percent,
+                max_fee_per_gas_cap: self.relay.max_fee_per_gas,
+                max_priority_fee_per_gas_cap: self.relay.max_priority_fee_per_gas,
+            },
+        }
+    }
+
+    pub fn proving_config(&self) -> ProvingConfig {
+        ProvingConfig {
+            max_concurrent_proofs: self.proving.max_concurrent_proofs,
+            max_queued_storage_proofs: self.proving.max_queued_storage_proofs,
+            batch_window: Duration::from_millis(self.proving.storage_proof_batch_window_ms),
+        }
+    }
+
+    pub fn loop_delay(&self) -> Duration {
+        Duration::from_secs(self.loop_delay_mins * 60)
+    }
+
+    pub fn storage_fetch_config(&self) -> StorageFetchConfig {
+        StorageFetchConfig {
+            max_keys_per_request: self.storage.max_keys_per_proof_request,
+            max_concurrent_requests: self.storage.max_concurrent_proof_requests,
+        }
+    }
+
+    pub fn storage_cycle_budget(&self) -> StorageCycleBudget {
+        StorageCycleBudget {
+            max_update_cycles: self.storage.max_update_cycles,
+            max_follow_up_cycles: self.storage.max_follow_up_cycles,
+        }
+    }
+
+    pub fn storage_proof_cache_config(&self) -> StorageProofCacheConfig {
+        StorageProofCacheConfig {
+            max_entries: self.storage.cache_entries,
+            max_age: Duration::from_secs(self.storage.cache_max_age_mins * 60),
+            dir: self.storage.cache_dir.clone(),
+        }
+    }
+
+    pub fn inclusion_policy(&self) -> InclusionPolicy {
+        if !self.storage.changed_slots_only {
+            return InclusionPolicy::Always;
+        }
+
+        InclusionPolicy::ChangedOnly {
+            max_staleness: self
+                .storage
+                .slot_max_staleness_mins
+                .map(|mins| Duration::from_secs(mins * 60)),
+        }
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+
+    /// The watchlist, if the config sets one.
+    pub fn storage_slots(&self) -> Option<HashMap<Address, HashSet<B256>>> {
+        let watchlist = self.watchlist.as_ref()?;
+
+        let mut storage_slots: HashMap<Address, HashSet<B256>> = HashMap::new();
+        for keys in watchlist {
+            storage_slots
+                .entry(keys.address)
+                .or_default()
+                .extend(keys.storage_slots.iter().copied());
+        }
+
+        Some(storage_slots)
+    }
+
+    /// Apply the settings that can change while the operator runs, warning about the others.
+    ///
+    /// `previous` is the last config loaded from the file, and `running` the one the operator
+    /// started with, so that settings needing a restart are reported until it happens.
+    async fn reload(&self, previous: &Self, running: &Self, handle: &OperatorHandle) {
+        if let Some(storage_slots) = self.storage_slots() {
+            handle.set_storage_slots(storage_slots).await;
+        }
+        // Retry settings take effect after a restart, like the RPCs they apply to, so relays keep
+        // the ones the operator started with.
+        handle.set_relay_config(RelayConfig {
+            retry: running.retry_policy(),
+            ..self.relay_config()
+        });
+        if self.loop_delay_mins != previous.loop_delay_mins {
+            handle.set_loop_delay(self.loop_delay());
+        }
+
+        let restart_required: Vec<&str> = [
+            (
+                "source_chain_id",
+                self.source_chain_id != running.source_chain_id,
+            ),
+            ("source", self.source != running.source),
+            ("destinations", self.destinations != running.destinations),
+            (
+                "finality_events",
+                self.finality_events != running.finality_events,
+            ),
+            ("proving", self.proving != running.proving),
+            ("retry", self.retry != running.retry),
+            ("storage", self.storage != running.storage),
+            ("db_dir", self.db_dir != running.db_dir),
+            ("api", self.api != running.api),
+            ("telemetry", self.telemetry != running.telemetry),
+            (
+                "shutdown_timeout_secs",
+                self.shutdown_timeout_secs != running.shutdown_timeout_secs,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect();
+        if !restart_required.is_empty() {
+            warn!(
+                "Changes to {} take effect after a restart",
+                restart_required.join(", ")
+            );
+        }
+    }
+}
+
+/// Reload the config file whenever it changes, applying the watchlist, relay settings and loop
+/// delay to the running operator.
+///
+/// An invalid config is ignored until it is fixed.
+pub async fn watch(path: PathBuf, running: OperatorConfig, handle: OperatorHandle) {
+    let mut current = running.clone();
+    let mut modified = modified_at(&path);
+    let mut interval = tokio::time::interval(RELOAD_POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let latest = modified_at(&path);
+        if latest == modified {
+            continue;
+        }
+        modified = latest;
+
+        match OperatorConfig::load(&path) {
+            Ok(config) => {
+                info!("Reloading config {}", path.display());
+                config.reload(&current, &running, &handle).await;
+                current = config;
+            }
+            Err(e) => warn!("Ignoring invalid config {}: {:?}", path.display(), e),
+        }
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,547 @@
+use crate::handle::WatchlistOwners;
+use alloy::primitives::{keccak256, Address, B256};
+use alloy::rpc::types::TransactionReceipt;
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use sp1_helios_primitives::types::ContractStorage;
+use sp1_sdk::SP1ProofWithPublicValues;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// How long records of proofs that finished relaying are kept.
+pub const FINISHED_PROOF_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+const DB_FILE: &str = "operator.sqlite";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS watched_contracts (
+        address BLOB PRIMARY KEY
+    );
+    CREATE TABLE IF NOT EXISTS watched_slots (
+        address BLOB NOT NULL,
+        storage_slot BLOB NOT NULL,
+        PRIMARY KEY (address, storage_slot)
+    );
+    CREATE TABLE IF NOT EXISTS slot_owners (
+        address BLOB NOT NULL,
+        storage_slot BLOB NOT NULL,
+        client TEXT,
+        PRIMARY KEY (address, storage_slot)
+    );
+    CREATE TABLE IF NOT EXISTS slot_max_staleness (
+        address BLOB NOT NULL,
+        storage_slot BLOB NOT NULL,
+        max_staleness_secs INTEGER NOT NULL,
+        PRIMARY KEY (address, storage_slot)
+    );
+    CREATE TABLE IF NOT EXISTS proofs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        kind TEXT NOT NULL,
+        proof BLOB NOT NULL,
+        inputs_hash BLOB,
+        destinations TEXT NOT NULL,
+        follow_ups BLOB NOT NULL,
+        state TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        finished_at INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS proofs_by_state ON proofs (state, finished_at);
+    CREATE TABLE IF NOT EXISTS relay_attempts (
+        proof_id INTEGER NOT NULL REFERENCES proofs (id) ON DELETE CASCADE,
+        contract_address BLOB NOT NULL,
+        attempted_at INTEGER NOT NULL,
+        outcome TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS relay_attempts_by_proof ON relay_attempts (proof_id);
+";
+
+/// What a recorded proof proves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProofKind {
+    /// A light client update from the given contract head.
+    Update { head: u64 },
+    /// Storage slots at the given execution block.
+    Storage { block_number: u64 },
+}
+
+/// Whether a recorded proof still has to be relayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProofState {
+    Relaying,
+    /// Landed on every destination.
+    Relayed,
+    /// Given up on, e.g. because the destinations moved past it.
+    Abandoned,
+}
+
+impl ProofState {
+    fn as_str(self) -> &'static str {
+        match self {
+            ProofState::Relaying => "relaying",
+            ProofState::Relayed => "relayed",
+            ProofState::Abandoned => "abandoned",
+        }
+    }
+
+    fn parse(state: &str) -> Result<Self> {
+        match state {
+            "relaying" => Ok(ProofState::Relaying),
+            "relayed" => Ok(ProofState::Relayed),
+            "abandoned" => Ok(ProofState::Abandoned),
+            other => anyhow::bail!("Unknown proof state {other}"),
+        }
+    }
+}
+
+/// The outcome of relaying a proof to one destination.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum RelayOutcome {
+    Landed {
+        transaction_hash: B256,
+        block_number: Option<u64>,
+        gas_used: u64,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayAttempt {
+    pub contract_address: Address,
+    /// When the attempt finished, in seconds since the Unix epoch.
+    pub attempted_at: u64,
+    pub outcome: RelayOutcome,
+}
+
+/// A generated proof, and every attempt to relay it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProofRecord {
+    pub id: u64,
+    pub kind: ProofKind,
+    pub proof: SP1ProofWithPublicValues,
+    /// The keccak256 hash of the program inputs the proof was generated from, unknown for storage
+    /// proofs served from the cache.
+    pub inputs_hash: Option<B256>,
+    /// The destinations the proof is relayed to.
+    pub destinations: Vec<Address>,
+    /// The watched storage left out of an update, to prove separately once it landed on every
+    /// destination.
+    pub follow_ups: Vec<Vec<ContractStorage>>,
+    pub relays: Vec<RelayAttempt>,
+    pub state: ProofState,
+    /// When the proof was generated, in seconds since the Unix epoch.
+    pub created_at: u64,
+}
+
+impl ProofRecord {
+    /// The destinations the proof has not landed on yet.
+    pub fn pending_destinations(&self) -> Vec<Address> {
+        let landed: HashSet<Address> = self
+            .relays
+            .iter()
+            .filter(|attempt| matches!(attempt.outcome, RelayOutcome::Landed { .. }))
+            .map(|attempt| attempt.contract_address)
+            .collect();
+
+        self.destinations
+            .iter()
+            .filter(|destination| !landed.contains(destination))
+            .copied()
+            .collect()
+    }
+}
+
+/// The watchlist, as stored in the database.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Watchlist {
+    /// The storage slots proven with every update, by contract.
+    pub storage_slots: HashMap<Address, HashSet<B256>>,
+    /// The owners of the storage slots added at runtime.
+    pub owners: WatchlistOwners,
+    /// Per-slot overrides of how long an unchanged slot may go without being included in an
+    /// update.
+    pub max_staleness: HashMap<(Address, B256), Duration>,
+}
+
+/// A local SQLite database of the operator's watchlist, the proofs it generates and their relays,
+/// so they survive restarts.
+///
+/// Every method blocks on disk I/O, so async code calls them through [`OperatorDb::run`].
+pub struct OperatorDb {
+    conn: Mutex<Connection>,
+}
+
+impl OperatorDb {
+    /// Open the database in `dir`, creating it if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create operator database {}", dir.display()))?;
+
+        let path = dir.join(DB_FILE);
+        let conn = Connection::open(&path)
+            .with_context(|| format!("Failed to open operator database {}", path.display()))?;
+        // Every commit is synced to disk, so that a recorded relay is never lost to a crash.
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL; PRAGMA synchronous = FULL; PRAGMA foreign_keys = ON;",
+        )?;
+        conn.execute_batch(SCHEMA)
+            .context("Failed to create the operator database schema")?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Run `f` on tokio's blocking thread pool, so that disk I/O does not stall the runtime.
+    pub async fn run<T, F>(self: &Arc<Self>, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&OperatorDb) -> Result<T> + Send + 'static,
+    {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || f(&db)).await?
+    }
+
+    /// Remove the records of proofs that finished relaying more than `retention` ago, and return
+    /// how many were removed.
+    pub fn prune(&self, retention: Duration) -> Result<usize> {
+        let cutoff = now().saturating_sub(retention.as_secs());
+        let pruned = self.lock().execute(
+            "DELETE FROM proofs WHERE state != ?1 AND finished_at <= ?2",
+            params![ProofState::Relaying.as_str(), cutoff as i64],
+        )?;
+
+        Ok(pruned)
+    }
+
+    /// The stored watchlist, empty if none was saved yet.
+    pub fn watchlist(&self) -> Result<Watchlist> {
+        let conn = self.lock();
+        let mut watchlist = Watchlist::default();
+
+        let contracts: Vec<Vec<u8>> = conn
+            .prepare("SELECT address FROM watched_contracts")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        for address in contracts {
+            watchlist
+                .storage_slots
+                .entry(address_from(&address)?)
+                .or_default();
+        }
+
+        let watched = slots(
+            &conn,
+            "SELECT address, storage_slot FROM watched_slots",
+            |_| Ok(()),
+        )?;
+        for (address, (storage_slot, ())) in watched {
+            watchlist
+                .storage_slots
+                .entry(address)
+                .or_default()
+                .insert(storage_slot);
+        }
+
+        for (address, (storage_slot, client)) in slots(
+            &conn,
+            "SELECT address, storage_slot, client FROM slot_owners",
+            |row| row.get::<_, Option<String>>(2),
+        )? {
+            watchlist.owners.insert((address, storage_slot), client);
+        }
+
+        for (address, (storage_slot, secs)) in slots(
+            &conn,
+            "SELECT address, storage_slot, max_staleness_secs FROM slot_max_staleness",
+            |row| row.get::<_, i64>(2),
+        )? {
+            watchlist
+                .max_staleness
+                .insert((address, storage_slot), Duration::from_secs(secs as u64));
+        }
+
+        Ok(watchlist)
+    }
+
+    /// Replace the stored watchlist.
+    pub fn save_watchlist(&self, watchlist: &Watchlist) -> Result<()> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        tx.execute_batch(
+            "DELETE FROM watched_contracts; DELETE FROM watched_slots; DELETE FROM slot_owners;
+             DELETE FROM slot_max_staleness;",
+        )?;
+
+        for (address, slots) in &watchlist.storage_slots {
+            tx.execute(
+                "INSERT INTO watched_contracts (address) VALUES (?1)",
+                params![address.as_slice()],
+            )?;
+            for slot in slots {
+                tx.execute(
+                    "INSERT INTO watched_slots (address, storage_slot) VALUES (?1, ?2)",
+                    params![address.as_slice(), slot.as_slice()],
+                )?;
+            }
+        }
+        for ((address, slot), client) in &watchlist.owners {
+            tx.execute(
+                "INSERT INTO slot_owners (address, storage_slot, client) VALUES (?1, ?2, ?3)",
+                params![address.as_slice(), slot.as_slice(), client],
+            )?;
+        }
+        for ((address, slot), max_staleness) in &watchlist.max_staleness {
+            tx.execute(
+                "INSERT INTO slot_max_staleness (address, storage_slot, max_staleness_secs)
+                 VALUES (?1, ?2, ?3)",
+                params![
+                    address.as_slice(),
+                    slot.as_slice(),
+                    max_staleness.as_secs() as i64
+                ],
+            )?;
+        }
+
+        tx.commit()
+            .context("Failed to save the storage slot watchlist")
+    }
+
+    /// Record a proof about to be relayed to `destinations`, along with an update's follow-up
+    /// storage, returning its id.
+    ///
+    /// Ids are never reused, even for records that were pruned.
+    pub fn insert_proof(
+        &self,
+        kind: ProofKind,
+        proof: &SP1ProofWithPublicValues,
+        inputs_hash: Option<B256>,
+        destinations: Vec<Address>,
+        follow_ups: &[Vec<ContractStorage>],
+    ) -> Result<u64> {
+        let conn = self.lock();
+        conn.execute(
+            "INSERT INTO proofs (kind, proof, inputs_hash, destinations, follow_ups, state,
+                                 created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                serde_json::to_string(&kind)?,
+                serde_cbor::to_vec(proof)?,
+                inputs_hash.as_ref().map(B256::as_slice),
+                serde_json::to_string(&destinations)?,
+                serde_cbor::to_vec(follow_ups)?,
+                ProofState::Relaying.as_str(),
+                now() as i64,
+            ],
+        )
+        .with_context(|| format!("Failed to record {kind:?} proof"))?;
+
+        Ok(conn.last_insert_rowid() as u64)
+    }
+
+    /// Record an attempt to relay a proof to a destination.
+    ///
+    /// The proof is marked relayed once it landed on every destination.
+    pub fn record_relay(
+        &self,
+        id: u64,
+        contract_address: Address,
+        result: Result<&TransactionReceipt, String>,
+    ) -> Result<()> {
+        let outcome = match result {
+            Ok(receipt) => RelayOutcome::Landed {
+                transaction_hash: receipt.transaction_hash,
+                block_number: receipt.block_number,
+                gas_used: receipt.gas_used,
+            },
+            Err(error) => RelayOutcome::Failed { error },
+        };
+        let attempt = RelayAttempt {
+            contract_address,
+            attempted_at: now(),
+            outcome,
+        };
+
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        let mut record = relaying_record(&tx, id)?;
+        tx.execute(
+            "INSERT INTO relay_attempts (proof_id, contract_address, attempted_at, outcome)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                id as i64,
+                contract_address.as_slice(),
+                attempt.attempted_at as i64,
+                serde_json::to_string(&attempt.outcome)?,
+            ],
+        )?;
+        record.relays.push(attempt);
+        if record.pending_destinations().is_empty() {
+            finish(&tx, id, ProofState::Relayed)?;
+        }
+
+        tx.commit()
+            .with_context(|| format!("Failed to record relay of proof {id}"))
+    }
+
+    /// Stop relaying a proof.
+    pub fn abandon(&self, id: u64) -> Result<()> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        relaying_record(&tx, id)?;
+        finish(&tx, id, ProofState::Abandoned)?;
+
+        tx.commit()
+            .with_context(|| format!("Failed to abandon proof {id}"))
+    }
+
+    /// The proofs that have not landed on every destination yet, oldest first.
+    ///
+    /// Records that cannot be read are skipped.
+    pub fn relaying(&self) -> Result<Vec<ProofRecord>> {
+        let conn = self.lock();
+        let ids: Vec<i64> = conn
+            .prepare("SELECT id FROM proofs WHERE state = ?1 ORDER BY id")?
+            .query_map(params![ProofState::Relaying.as_str()], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let mut records = Vec::new();
+        for id in ids {
+            match load_record(&conn, id as u64) {
+                Ok(Some(record)) => records.push(record),
+                Ok(None) => {}
+                Err(e) => warn!("Skipping unreadable proof record {id}: {:?}", e),
+            }
+        }
+
+        Ok(records)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().expect("Operator database lock poisoned")
+    }
+}
+
+/// Compute the hash identifying the inputs a proof was generated from.
+pub(crate) fn inputs_hash(inputs: &impl Serialize) -> Result<B256> {
+    Ok(keccak256(serde_cbor::to_vec(inputs)?))
+}
+
+/// Load a proof record and its relay attempts.
+fn load_record(conn: &Connection, id: u64) -> Result<Option<ProofRecord>> {
+    let row = conn
+        .query_row(
+            "SELECT kind, proof, inputs_hash, destinations, follow_ups, state, created_at
+             FROM proofs WHERE id = ?1",
+            params![id as i64],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Vec<u8>>(1)?,
+                    row.get::<_, Option<Vec<u8>>>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Vec<u8>>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, i64>(6)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((kind, proof, inputs_hash, destinations, follow_ups, state, created_at)) = row else {
+        return Ok(None);
+    };
+
+    let attempts: Vec<(Vec<u8>, i64, String)> = conn
+        .prepare(
+            "SELECT contract_address, attempted_at, outcome FROM relay_attempts
+             WHERE proof_id = ?1 ORDER BY rowid",
+        )?
+        .query_map(params![id as i64], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    let relays = attempts
+        .into_iter()
+        .map(|(contract_address, attempted_at, outcome)| {
+            Ok(RelayAttempt {
+                contract_address: address_from(&contract_address)?,
+                attempted_at: attempted_at as u64,
+                outcome: serde_json::from_str(&outcome)?,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Some(ProofRecord {
+        id,
+        kind: serde_json::from_str(&kind)?,
+        proof: serde_cbor::from_slice(&proof)?,
+        inputs_hash: inputs_hash.as_deref().map(b256_from).transpose()?,
+        destinations: serde_json::from_str(&destinations)?,
+        follow_ups: serde_cbor::from_slice(&follow_ups)?,
+        relays,
+        state: ProofState::parse(&state)?,
+        created_at: created_at as u64,
+    }))
+}
+
+/// Load a proof record, failing unless it is still being relayed.
+fn relaying_record(tx: &Transaction<'_>, id: u64) -> Result<ProofRecord> {
+    match load_record(tx, id)? {
+        Some(record) if record.state == ProofState::Relaying => Ok(record),
+        _ => anyhow::bail!("Proof {id} is not being relayed"),
+    }
+}
+
+fn finish(tx: &Transaction<'_>, id: u64, state: ProofState) -> Result<()> {
+    tx.execute(
+        "UPDATE proofs SET state = ?1, finished_at = ?2 WHERE id = ?3",
+        params![state.as_str(), now() as i64, id as i64],
+    )?;
+    Ok(())
+}
+
+/// Query rows keyed by contract and storage slot, in the first two columns, along with the value
+/// `value` reads from the rest of each row.
+fn slots<T>(
+    conn: &Connection,
+    sql: &str,
+    value: impl Fn(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+) -> Result<Vec<(Address, (B256, T))>> {
+    let rows: Vec<(Vec<u8>, Vec<u8>, T)> = conn
+        .prepare(sql)?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, value(row)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    rows.into_iter()
+        .map(|(address, slot, value)| Ok((address_from(&address)?, (b256_from(&slot)?, value))))
+        .collect()
+}
+
+fn address_from(bytes: &[u8]) -> Result<Address> {
+    anyhow::ensure!(
+        bytes.len() == 20,
+        "Invalid address in the operator database"
+    );
+    Ok(Address::from_slice(bytes))
+}
+
+fn b256_from(bytes: &[u8]) -> Result<B256> {
+    anyhow::ensure!(bytes.len() == 32, "Invalid hash in the operator database");
+    Ok(B256::from_slice(bytes))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -0,0 +1,526 @@
+use crate::db::ProofKind;
+use crate::error::OperatorError;
+use crate::events::{Events, OperatorEvent};
+use crate::programs::ProgramKind;
+use crate::retry::RetryPolicy;
+use crate::transaction::{RelayConfig, TxSender};
+use alloy::primitives::{Address, TxHash, B256, U256};
+use alloy::providers::{Provider, WalletProvider};
+use alloy::rpc::types::{TransactionReceipt, TransactionRequest};
+use alloy::sol_types::SolType;
+use anyhow::{Context, Result};
+use sp1_helios_primitives::types::ISP1Verifier::ISP1VerifierErrors;
+use sp1_helios_primitives::types::SP1Helios::SP1HeliosErrors;
+use sp1_helios_primitives::types::{ProofOutputs, SP1Helios, StorageProofOutputs};
+use sp1_sdk::SP1ProofWithPublicValues;
+use std::sync::{Arc, Mutex};
+use tracing::{error, field, info, instrument, warn, Span};
+
+/// The most blocks [`Destination::vkey_updates`] fetches logs for at once.
+const VKEY_LOG_PAGE_BLOCKS: u64 = 10_000;
+
+/// An `SP1Helios` contract kept up to date by the operator.
+pub struct Destination<P> {
+    pub(crate) provider: P,
+    pub(crate) contract_address: Address,
+    pub(crate) tx_sender: Arc<TxSender>,
+    pub(crate) events: Events,
+    /// How reads of the contract's state are retried.
+    pub(crate) retry: RetryPolicy,
+    vkeys: Mutex<WatchedVkeys>,
+}
+
+/// The vkeys a contract verifies proofs against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Vkeys {
+    pub light_client: B256,
+    pub storage_slots: B256,
+}
+
+/// The vkeys last read from a contract, and the block to look for updates to them from.
+#[derive(Default)]
+struct WatchedVkeys {
+    vkeys: Vkeys,
+    next_block: u64,
+}
+
+/// A relay transaction that was included but reverted.
+#[derive(Debug, thiserror::Error)]
+#[error("Transaction {0:?} reverted")]
+pub struct TransactionReverted(pub TxHash);
+
+/// The contract state an update proof is generated against.
+///
+/// The contract fills these in as the proof's `prevHead`, `prevHeader` and
+/// `prevSyncCommitteeHash`, so one proof can be relayed to every destination with the same anchor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Anchor {
+    pub head: u64,
+    pub header: B256,
+    pub sync_committee_hash: B256,
+}
+
+impl<P> Destination<P>
+where
+    P: Provider + WalletProvider,
+{
+    pub fn new(provider: P, contract_address: Address, relay_config: RelayConfig) -> Self {
+        Self {
+            provider,
+            contract_address,
+            tx_sender: Arc::new(TxSender::new(relay_config)),
+            events: Events::default(),
+            retry: RetryPolicy::default(),
+            vkeys: Mutex::default(),
+        }
+    }
+
+    pub fn contract_address(&self) -> Address {
+        self.contract_address
+    }
+
+    /// Read the anchor the next update to this contract must be proven against.
+    pub async fn anchor(&self) -> Result<Anchor> {
+        self.retry.retry("anchor", || self.read_anchor()).await
+    }
+
+    async fn read_anchor(&self) -> Result<Anchor> {
+        let contract = SP1Helios::new(self.contract_address, &self.provider);
+
+        let head = contract
+            .head()
+            .call()
+            .await
+            .context("Failed to get head from contract")?;
+        let header = contract
+            .headers(head)
+            .call()
+            .await
+            .context("Failed to get header from contract")?;
+        let slots_per_period = contract
+            .SLOTS_PER_PERIOD()
+            .call()
+            .await
+            .context("Failed to get slots per period from contract")?;
+        let sync_committee_hash = contract
+            .syncCommittees(head / slots_per_period)
+            .call()
+            .await
+            .context("Failed to get sync committee hash from contract")?;
+
+        Ok(Anchor {
+            head: head
+                .try_into()
+                .expect("Failed to convert head to u64, this is a bug."),
+            header,
+            sync_committee_hash,
+        })
+    }
+
+    /// Relay an update proof to the contract, returning the receipt.
+    ///
+    /// Before broadcasting, the proof's anchor is checked against the contract's current state and
+    /// the `update` call is simulated, so that a transaction which would revert is never sent.
+    #[instrument(skip_all, fields(contract = %self.contract_address, head = field::Empty))]
+    pub async fn relay_update(
+        &self,
+        proof: &SP1ProofWithPublicValues,
+    ) -> Result<TransactionReceipt> {
+        let contract = SP1Helios::new(self.contract_address, &self.provider);
+
+        let po = ProofOutputs::abi_decode(proof.public_values.as_slice())?;
+        Span::current().record("head", po.newHead.to::<u64>());
+
+        self.check_update_anchor(&po).await?;
+
+        let tx = contract.update(
+            proof.bytes().into(),
+            po.newHead,
+            po.newHeader,
+            po.executionStateRoot,
+            po.executionBlockNumber,
+            po.syncCommitteeHash,
+            po.nextSyncCommitteeHash,
+            po.storageSlots,
+        );
+
+        if let Err(e) = tx.call().await {
+            anyhow::bail!(
+                "Simulated update to head {} reverted: {}",
+                po.newHead,
+                revert_reason(&e)
+            );
+        }
+
+        let receipt = self
+            .send(
+                ProofKind::Update {
+                    head: po.prevHead.to(),
+                },
+                tx.into_transaction_request(),
+            )
+            .await?;
+
+        // If status is false, it reverted.
+        if !receipt.status() {
+            error!("Transaction reverted!");
+            return Err(TransactionReverted(receipt.transaction_hash).into());
+        }
+
+        info!(
+            "Successfully updated {:?} to new head block! Tx hash: {:?}",
+            self.contract_address, receipt.transaction_hash
+        );
+
+        Ok(receipt)
+    }
+
+    /// Relay a storage proof for `block_number` to the contract, returning the receipt.
+    ///
+    /// The contract must already have the execution state root of the block, from an earlier
+    /// update, and it must match the one the proof was generated against. As with updates, the
+    /// call is simulated before it is broadcast.
+    #[instrument(skip_all, fields(contract = %self.contract_address, block_number))]
+    pub async fn relay_storage_proof(
+        &self,
+        block_number: u64,
+        proof: &SP1ProofWithPublicValues,
+    ) -> Result<TransactionReceipt> {
+        let contract = SP1Helios::new(self.contract_address, &self.provider);
+
+        let outputs = StorageProofOutputs::abi_decode(proof.public_values.as_slice())?;
+
+        let state_root = contract
+            .executionStateRoots(U256::from(block_number))
+            .call()
+            .await
+            .context("Failed to get execution state root from contract")?;
+        if state_root.is_zero() {
+            anyhow::bail!(
+                "{:?} has no execution state root for block {block_number} yet",
+                self.contract_address
+            );
+        }
+        if state_root != outputs.stateRoot {
+            anyhow::bail!(
+                "Proof was generated against state root {:?}, but {:?} has {:?} for block {block_number}",
+                outputs.stateRoot,
+                self.contract_address,
+                state_root
+            );
+        }
+
+        let tx = contract.updateStorageSlot(
+            proof.bytes().into(),
+            outputs.storageSlots,
+            U256::from(block_number),
+        );
+
+        if let Err(e) = tx.call().await {
+            anyhow::bail!(
+                "Simulated storage slot update for block {block_number} reverted: {}",
+                revert_reason(&e)
+            );
+        }
+
+        let receipt = self
+            .send(
+                ProofKind::Storage { block_number },
+                tx.into_transaction_request(),
+            )
+            .await?;
+
+        if !receipt.status() {
+            error!("Transaction reverted!");
+            return Err(TransactionReverted(receipt.transaction_hash).into());
+        }
+
+        info!(
+            "Successfully updated storage slots of {:?} at block {block_number}! Tx hash: {:?}",
+            self.contract_address, receipt.transaction_hash
+        );
+
+        Ok(receipt)
+    }
+
+    /// Send a transaction relaying a proof, announcing each broadcast.
+    async fn send(&self, kind: ProofKind, tx: TransactionRequest) -> Result<TransactionReceipt> {
+        self.tx_sender
+            .send(&self.provider, tx, |transaction_hash| {
+                self.events.emit(OperatorEvent::RelaySent {
+                    kind,
+                    contract_address: self.contract_address,
+                    transaction_hash,
+                })
+            })
+            .await
+    }
+
+    /// Read the values the contract holds for the given slots as of its latest execution block,
+    /// along with that block.
+    ///
+    /// Slots the contract has no value for at that block are left out, since unset and zero read
+    /// the same. Slots last written at an earlier block read as unset too.
+    pub async fn written_storage_slots(
+        &self,
+        slots: &[(Address, B256)],
+    ) -> Result<(u64, Vec<(Address, B256, B256)>)> {
+        self.retry
+            .retry("written_storage_slots", || {
+                self.read_written_storage_slots(slots)
+            })
+            .await
+    }
+
+    async fn read_written_storage_slots(
+        &self,
+        slots: &[(Address, B256)],
+    ) -> Result<(u64, Vec<(Address, B256, B256)>)> {
+        let contract = SP1Helios::new(self.contract_address, &self.provider);
+
+        let block_number = contract
+            .executionBlockNumber()
+            .call()
+            .await
+            .context("Failed to get execution block number from contract")?;
+
+        let values = futures::future::try_join_all(slots.iter().map(|(address, key)| {
+            let contract = &contract;
+            async move {
+                contract
+                    .getStorageSlot(block_number, *address, *key)
+                    .call()
+                    .await
+                    .with_context(|| {
+                        format!("Failed to get storage slot {key:?} of {address:?} from contract")
+                    })
+            }
+        }))
+        .await?;
+
+        let written = slots
+            .iter()
+            .zip(values)
+            .filter(|(_, value)| !value.is_zero())
+            .map(|((address, key), value)| (*address, *key, value))
+            .collect();
+
+        Ok((block_number.to(), written))
+    }
+
+    /// Check that the `prevHead`, `prevHeader` and `prevSyncCommitteeHash` a proof was generated
+    /// against still match the contract, which fills them in from its own state when verifying.
+    async fn check_update_anchor(&self, po: &ProofOutputs) -> Result<()> {
+        let anchor = self.anchor().await?;
+
+        if po.prevHead != U256::from(anchor.head) {
+            anyhow::bail!(
+                "Proof was generated from head {}, but the contract is now at head {}",
+                po.prevHead,
+                anchor.head
+            );
+        }
+
+        if po.prevHeader != anchor.header {
+            anyhow::bail!(
+                "Proof was generated from header {:?}, but the contract has header {:?} at head {}",
+                po.prevHeader,
+                anchor.header,
+                anchor.head
+            );
+        }
+
+        if po.prevSyncCommitteeHash != anchor.sync_committee_hash {
+            anyhow::bail!(
+                "Proof was generated with sync committee {:?}, but the contract has {:?} at head {}",
+                po.prevSyncCommitteeHash,
+                anchor.sync_committee_hash,
+                anchor.head
+            );
+        }
+
+        Ok(())
+    }
+
+    /// The vkeys the contract was last seen to hold.
+    pub fn vkeys(&self) -> Vkeys {
+        self.lock_vkeys().vkeys
+    }
+
+    /// Read the contract's vkeys, and watch for updates to them from the current block on.
+    pub async fn watch_vkeys(&self) -> Result<Vkeys, OperatorError> {
+        let contract = SP1Helios::new(self.contract_address, &self.provider);
+
+        // Read the block first, so that an update between the reads is seen again, not missed.
+        let block_number = self
+            .provider
+            .get_block_number()
+            .await
+            .context("Failed to get block number")
+            .map_err(OperatorError::ContractState)?;
+        let light_client = contract
+            .lightClientVkey()
+            .call()
+            .await
+            .context("Failed to get light client vkey from contract")
+            .map_err(OperatorError::ContractState)?;
+        let storage_slots = contract
+            .storageSlotVkey()
+            .call()
+            .await
+            .context("Failed to get storage slot vkey from contract")
+            .map_err(OperatorError::ContractState)?;
+
+        let vkeys = Vkeys {
+            light_client,
+            storage_slots,
+        };
+        *self.lock_vkeys() = WatchedVkeys {
+            vkeys,
+            next_block: block_number + 1,
+        };
+        Ok(vkeys)
+    }
+
+    /// Look for `LightClientVkeyUpdate` and `StorageSlotVkeyUpdate` events since the last check,
+    /// returning the vkeys that changed.
+    ///
+    /// Logs are fetched in pages of up to 10,000 blocks, since RPCs cap the block range of
+    /// `eth_getLogs`. If a page fails after others succeeded, the changes found so far are
+    /// returned and the next check resumes from the failed page.
+    pub async fn vkey_updates(&self) -> Result<Vec<(ProgramKind, B256)>> {
+        let latest = self
+            .provider
+            .get_block_number()
+            .await
+            .context("Failed to get block number")?;
+        let before = self.lock_vkeys().vkeys;
+
+        let contract = SP1Helios::new(self.contract_address, &self.provider);
+        loop {
+            let from = self.lock_vkeys().next_block;
+            if latest < from {
+                break;
+            }
+            let to = latest.min(from.saturating_add(VKEY_LOG_PAGE_BLOCKS - 1));
+
+            let page = async {
+                let light_client = contract
+                    .LightClientVkeyUpdate_filter()
+                    .from_block(from)
+                    .to_block(to)
+                    .query()
+                    .await
+                    .context("Failed to get light client vkey updates from contract")?;
+                let storage_slots = contract
+                    .StorageSlotVkeyUpdate_filter()
+                    .from_block(from)
+                    .to_block(to)
+                    .query()
+                    .await
+                    .context("Failed to get storage slot vkey updates from contract")?;
+                Ok::<_, anyhow::Error>((light_client, storage_slots))
+            }
+            .await;
+            let (light_client, storage_slots) = match page {
+                Ok(page) => page,
+                Err(e) if self.lock_vkeys().vkeys == before => return Err(e),
+                Err(e) => {
+                    warn!(
+                        "Stopped looking for vkey updates on {:?} at block {from}: {:?}",
+                        self.contract_address, e
+                    );
+                    break;
+                }
+            };
+
+            let mut watched = self.lock_vkeys();
+            // Only the last update in the range is still in effect.
+            if let Some((event, _)) = light_client.last() {
+                watched.vkeys.light_client = event.newVkey;
+            }
+            if let Some((event, _)) = storage_slots.last() {
+                watched.vkeys.storage_slots = event.newVkey;
+            }
+            watched.next_block = to + 1;
+        }
+
+        let after = self.lock_vkeys().vkeys;
+        let mut updates = Vec::new();
+        if after.light_client != before.light_client {
+            updates.push((ProgramKind::LightClient, after.light_client));
+        }
+        if after.storage_slots != before.storage_slots {
+            updates.push((ProgramKind::StorageSlots, after.storage_slots));
+        }
+
+        Ok(updates)
+    }
+
+    fn lock_vkeys(&self) -> std::sync::MutexGuard<'_, WatchedVkeys> {
+        self.vkeys.lock().expect("Destination vkeys lock poisoned")
+    }
+}
+
+/// Describe why a call to the SP1 Helios contract reverted, decoding the contract's and the
+/// verifier's custom errors where possible.
+pub fn revert_reason(err: &alloy::contract::Error) -> String {
+    if let Some(e) = err.as_decoded_interface_error::<SP1HeliosErrors>() {
+        return match e {
+            SP1HeliosErrors::SlotBehindHead(e) => {
+                format!(
+                    "SlotBehindHead: slot {} is not ahead of the contract head",
+                    e.slot
+                )
+            }
+            SP1HeliosErrors::SyncCommitteeStartMismatch(e) => format!(
+                "SyncCommitteeStartMismatch: given {:?}, expected {:?}",
+                e.given, e.expected
+            ),
+            SP1HeliosErrors::SyncCommitteeNotSet(e) => format!(
+                "SyncCommitteeNotSet: no sync committee for period {}",
+                e.period
+            ),
+            SP1HeliosErrors::NextSyncCommitteeMismatch(e) => format!(
+                "NextSyncCommitteeMismatch: given {:?}, expected {:?}",
+                e.given, e.expected
+            ),
+            SP1HeliosErrors::NonCheckpointSlot(e) => {
+                format!(
+                    "NonCheckpointSlot: slot {} is not a checkpoint slot",
+                    e.slot
+                )
+            }
+            SP1HeliosErrors::MissingStateRoot(e) => format!(
+                "MissingStateRoot: no execution state root for block {}",
+                e.blockNumber
+            ),
+        };
+    }
+
+    if let Some(e) = err.as_decoded_interface_error::<ISP1VerifierErrors>() {
+        return match e {
+            ISP1VerifierErrors::InvalidProof(_) => {
+                "verifier rejected the proof (InvalidProof)".into()
+            }
+            ISP1VerifierErrors::WrongVerifierSelector(e) => format!(
+                "WrongVerifierSelector: proof selector {}, verifier expects {}",
+                e.received, e.expected
+            ),
+            ISP1VerifierErrors::RouteNotFound(e) => {
+                format!(
+                    "RouteNotFound: no verifier route for selector {}",
+                    e.selector
+                )
+            }
+            ISP1VerifierErrors::RouteIsFrozen(e) => {
+                format!(
+                    "RouteIsFrozen: verifier route for selector {} is frozen",
+                    e.selector
+                )
+            }
+        };
+    }
+
+    err.to_string()
+}
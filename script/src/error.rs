@@ -0,0 +1,75 @@
+use crate::programs::ProgramKind;
+use alloy::primitives::{Address, B256};
+
+/// Why the operator failed, classified by whether trying again can help.
+///
+/// Most functions in this crate return [`anyhow::Error`]s that carry an `OperatorError` where the
+/// failure originated. Use [`OperatorError::find`] to get it back.
+#[derive(Debug, thiserror::Error)]
+pub enum OperatorError {
+    /// A beacon node request failed or returned unusable data.
+    #[error("consensus RPC request failed")]
+    ConsensusRpc(#[source] anyhow::Error),
+    /// The light client could not be bootstrapped from a checkpoint.
+    #[error("failed to bootstrap the light client")]
+    Bootstrap(#[source] anyhow::Error),
+    /// Reading the execution state of the source chain failed.
+    #[error("execution RPC request failed")]
+    ExecutionRpc(#[source] anyhow::Error),
+    /// Reading the state of a destination contract failed.
+    #[error("failed to read contract state")]
+    ContractState(#[source] anyhow::Error),
+    /// Generating a proof failed.
+    #[error("failed to generate a proof")]
+    Proving(#[source] anyhow::Error),
+    /// A proof could not be relayed to one or more destinations.
+    #[error("failed to relay a proof")]
+    Relay(#[source] anyhow::Error),
+    /// A program could not be set up for proving.
+    #[error("failed to set up the {program} program {version}")]
+    Setup {
+        program: ProgramKind,
+        version: &'static str,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// A destination contract verifies proofs of a program build the operator does not embed.
+    #[error("{contract_address:?} expects {program} vkey {vkey:?}, which none of the operator's programs have")]
+    UnknownVkey {
+        contract_address: Address,
+        program: ProgramKind,
+        vkey: B256,
+    },
+    /// The source chain is not one helios knows.
+    #[error("unknown source chain {0}")]
+    UnknownNetwork(u64),
+    /// A setting is invalid, such as a malformed RPC URL.
+    #[error("invalid configuration: {0}")]
+    Config(String),
+}
+
+impl OperatorError {
+    /// Whether the operator cannot recover from the error without a change to its configuration,
+    /// the contracts or the programs.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Self::Setup { .. }
+                | Self::UnknownVkey { .. }
+                | Self::UnknownNetwork(_)
+                | Self::Config(_)
+        )
+    }
+
+    /// Whether the operation may succeed if tried again later, e.g. once an RPC recovers.
+    pub fn is_retryable(&self) -> bool {
+        !self.is_fatal()
+    }
+
+    /// The first `OperatorError` in an error's chain of causes, if any.
+    pub fn find(error: &anyhow::Error) -> Option<&OperatorError> {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<OperatorError>())
+    }
+}
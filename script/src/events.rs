@@ -0,0 +1,284 @@
+use crate::db::ProofKind;
+use crate::handle::ContractStatus;
+use crate::programs::ProgramKind;
+use crate::scheduler::QueueDepth;
+use alloy::primitives::{Address, TxHash, B256};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+/// How many events a subscriber can fall behind before it misses some.
+const EVENT_CAPACITY: usize = 256;
+
+/// Something the operator did or noticed, as broadcast by [`OperatorHandle::subscribe`].
+///
+/// [`OperatorHandle::subscribe`]: crate::handle::OperatorHandle::subscribe
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum OperatorEvent {
+    /// A proof started generating.
+    ProofStarted { kind: ProofKind },
+    /// A proof finished generating, or failed to.
+    ProofFinished {
+        kind: ProofKind,
+        error: Option<String>,
+    },
+    /// A transaction relaying a proof was broadcast. Replacements of a stuck transaction are sent
+    /// again under a new hash.
+    RelaySent {
+        kind: ProofKind,
+        contract_address: Address,
+        transaction_hash: TxHash,
+    },
+    /// A relay transaction was included and confirmed.
+    RelayConfirmed {
+        kind: ProofKind,
+        contract_address: Address,
+        transaction_hash: TxHash,
+        block_number: Option<u64>,
+        gas_used: u64,
+    },
+    /// A relay transaction was included but reverted.
+    RelayReverted {
+        kind: ProofKind,
+        contract_address: Address,
+        transaction_hash: TxHash,
+    },
+    /// A proof could not be relayed, e.g. because the simulated call reverted or the transaction
+    /// was never included.
+    RelayFailed {
+        kind: ProofKind,
+        contract_address: Address,
+        error: String,
+    },
+    /// An update landed on the destinations listed, emitted for each destination as it lands.
+    Updated {
+        previous_head: u64,
+        head: u64,
+        execution_block_number: u64,
+        contract_addresses: Vec<Address>,
+    },
+    /// A destination's head moved without the operator relaying an update to it.
+    ContractUpdatedExternally {
+        contract_address: Address,
+        previous_head: u64,
+        head: u64,
+    },
+    /// A destination switched to a new vkey for one of its programs. Unless `supported`, the
+    /// operator embeds no build of the program with that vkey, and cannot prove for the
+    /// destination until it is upgraded.
+    VkeyUpdated {
+        contract_address: Address,
+        program: ProgramKind,
+        vkey: B256,
+        supported: bool,
+    },
+}
+
+/// What the operator is doing and how it last went, as returned by
+/// [`OperatorHandle::status`].
+///
+/// [`OperatorHandle::status`]: crate::handle::OperatorHandle::status
+#[derive(Debug, Clone, Serialize)]
+pub struct OperatorStatus {
+    pub last_update: Option<LastUpdate>,
+    pub last_error: Option<LastError>,
+    /// The state of each destination contract, including its current head.
+    pub contracts: Vec<ContractStatus>,
+    /// The proofs being generated.
+    pub proofs_in_flight: Vec<ProofKind>,
+    /// The relay transactions sent and not yet confirmed.
+    pub relays_in_flight: Vec<InFlightRelay>,
+    pub proving_queue: QueueDepth,
+}
+
+/// The last update the operator landed, and the destinations it landed on so far.
+#[derive(Debug, Clone, Serialize)]
+pub struct LastUpdate {
+    pub head: u64,
+    pub execution_block_number: u64,
+    pub contract_addresses: Vec<Address>,
+    /// When the update landed, in seconds since the Unix epoch.
+    pub landed_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LastError {
+    pub message: String,
+    /// When the error happened, in seconds since the Unix epoch.
+    pub at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InFlightRelay {
+    pub kind: ProofKind,
+    pub contract_address: Address,
+}
+
+/// Broadcasts [`OperatorEvent`]s to subscribers, and keeps track of the status they add up to.
+#[derive(Clone)]
+pub(crate) struct Events {
+    tx: broadcast::Sender<OperatorEvent>,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    last_update: Option<LastUpdate>,
+    last_error: Option<LastError>,
+    proofs: Vec<ProofKind>,
+    relays: Vec<InFlightRelay>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            tx: broadcast::channel(EVENT_CAPACITY).0,
+            state: Arc::default(),
+        }
+    }
+}
+
+impl Events {
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<OperatorEvent> {
+        self.tx.subscribe()
+    }
+
+    pub(crate) fn emit(&self, event: OperatorEvent) {
+        let mut state = self.lock();
+        match &event {
+            OperatorEvent::ProofStarted { kind } => state.proofs.push(*kind),
+            OperatorEvent::ProofFinished { kind, error } => {
+                if let Some(i) = state.proofs.iter().position(|proof| proof == kind) {
+                    state.proofs.remove(i);
+                }
+                if let Some(error) = error {
+                    state.set_error(format!("{kind:?} proof failed: {error}"));
+                }
+            }
+            OperatorEvent::RelaySent {
+                kind,
+                contract_address,
+                ..
+            } => {
+                let relay = InFlightRelay {
+                    kind: *kind,
+                    contract_address: *contract_address,
+                };
+                if !state.relays.contains(&relay) {
+                    state.relays.push(relay);
+                }
+            }
+            OperatorEvent::RelayConfirmed {
+                kind,
+                contract_address,
+                ..
+            } => state.finish_relay(*kind, *contract_address),
+            OperatorEvent::RelayReverted {
+                kind,
+                contract_address,
+                transaction_hash,
+            } => {
+                state.finish_relay(*kind, *contract_address);
+                state.set_error(format!(
+                    "{kind:?} relay to {contract_address:?} reverted in {transaction_hash:?}"
+                ));
+            }
+            OperatorEvent::RelayFailed {
+                kind,
+                contract_address,
+                error,
+            } => {
+                state.finish_relay(*kind, *contract_address);
+                state.set_error(format!(
+                    "{kind:?} relay to {contract_address:?} failed: {error}"
+                ));
+            }
+            OperatorEvent::Updated {
+                head,
+                execution_block_number,
+                contract_addresses,
+                ..
+            } => match &mut state.last_update {
+                Some(last)
+                    if last.head == *head && last.execution_block_number == *execution_block_number =>
+                {
+                    last.contract_addresses.extend(contract_addresses);
+                    last.landed_at = now();
+                }
+                last => {
+                    *last = Some(LastUpdate {
+                        head: *head,
+                        execution_block_number: *execution_block_number,
+                        contract_addresses: contract_addresses.clone(),
+                        landed_at: now(),
+                    })
+                }
+            },
+            OperatorEvent::VkeyUpdated {
+                contract_address,
+                program,
+                vkey,
+                supported: false,
+            } => state.set_error(format!(
+                "{contract_address:?} switched to {program} vkey {vkey:?}, which none of the operator's programs have"
+            )),
+            OperatorEvent::ContractUpdatedExternally { .. } | OperatorEvent::VkeyUpdated { .. } => {
+            }
+        }
+        drop(state);
+
+        // Nobody may be listening.
+        let _ = self.tx.send(event);
+    }
+
+    /// Record an error that has no event of its own, such as a failure to read the destinations.
+    pub(crate) fn error(&self, message: String) {
+        self.lock().set_error(message);
+    }
+
+    pub(crate) fn status(
+        &self,
+        contracts: Vec<ContractStatus>,
+        proving_queue: QueueDepth,
+    ) -> OperatorStatus {
+        let state = self.lock();
+        OperatorStatus {
+            last_update: state.last_update.clone(),
+            last_error: state.last_error.clone(),
+            contracts,
+            proofs_in_flight: state.proofs.clone(),
+            relays_in_flight: state.relays.clone(),
+            proving_queue,
+        }
+    }
+
+    /// The proofs being generated and the relays awaiting confirmation.
+    pub(crate) fn in_flight(&self) -> (Vec<ProofKind>, Vec<InFlightRelay>) {
+        let state = self.lock();
+        (state.proofs.clone(), state.relays.clone())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("Operator events lock poisoned")
+    }
+}
+
+impl State {
+    fn finish_relay(&mut self, kind: ProofKind, contract_address: Address) {
+        self.relays
+            .retain(|relay| relay.kind != kind || relay.contract_address != contract_address);
+    }
+
+    fn set_error(&mut self, message: String) {
+        self.last_error = Some(LastError { message, at: now() });
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
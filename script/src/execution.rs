@@ -0,0 +1,245 @@
+use crate::metrics::metrics;
+use crate::retry::{CircuitBreakers, RetryPolicy};
+use alloy::primitives::{Address, B256};
+use alloy::providers::Provider;
+use alloy::rpc::types::EIP1186AccountProofResponse;
+use anyhow::{anyhow, Context, Result};
+use futures::{StreamExt, TryStreamExt};
+use sp1_helios_primitives::types::{ContractStorage, StorageSlotWithProof};
+use sp1_helios_primitives::verify_storage_slot_proofs;
+use std::future::Future;
+use std::time::Instant;
+use tracing::{instrument, warn};
+
+/// Limits on how storage proofs are fetched from execution RPCs.
+#[derive(Debug, Clone)]
+pub struct StorageFetchConfig {
+    /// The maximum number of storage keys requested in a single `eth_getProof` call.
+    pub max_keys_per_request: usize,
+    /// The maximum number of `eth_getProof` calls in flight at once.
+    pub max_concurrent_requests: usize,
+}
+
+impl Default for StorageFetchConfig {
+    fn default() -> Self {
+        Self {
+            max_keys_per_request: 50,
+            max_concurrent_requests: 4,
+        }
+    }
+}
+
+/// Fetches storage proofs from a list of execution RPCs, failing over between them in order.
+///
+/// Each request is given the retry policy's timeout on each RPC. Requests that fail on every RPC
+/// are retried with backoff, and RPCs that keep failing are skipped for a while. RPCs are told
+/// apart by their position, so `breakers` must only be shared by fetchers over the same list.
+pub struct StorageFetcher<'a> {
+    providers: Vec<&'a dyn Provider>,
+    config: &'a StorageFetchConfig,
+    retry: &'a RetryPolicy,
+    breakers: &'a CircuitBreakers,
+}
+
+impl<'a> StorageFetcher<'a> {
+    pub fn new(
+        providers: Vec<&'a dyn Provider>,
+        config: &'a StorageFetchConfig,
+        retry: &'a RetryPolicy,
+        breakers: &'a CircuitBreakers,
+    ) -> Self {
+        Self {
+            providers,
+            config,
+            retry,
+            breakers,
+        }
+    }
+
+    /// Get the execution state root of a block.
+    pub async fn state_root(&self, block_number: u64) -> Result<B256> {
+        self.with_failover("eth_getBlockByNumber", |provider| async move {
+            let Some(block) = provider.get_block(block_number.into()).await? else {
+                anyhow::bail!("Failed to get block {block_number} from provider, this was expected to valid since the store claimed to have this block finalized.");
+            };
+
+            Ok(block.header.state_root)
+        })
+        .await
+    }
+
+    /// Fetch and verify the storage proofs of the given contracts and keys against `state_root`.
+    ///
+    /// Large key sets are split into several `eth_getProof` calls, and the results are merged
+    /// into a single [`ContractStorage`] per contract.
+    #[instrument(skip_all, fields(block_number, contracts = contracts.len()))]
+    pub async fn contract_storage(
+        &self,
+        state_root: B256,
+        block_number: u64,
+        contracts: Vec<(Address, Vec<B256>)>,
+    ) -> Result<Vec<ContractStorage>> {
+        let requests = proof_requests(&contracts, self.config.max_keys_per_request);
+
+        let responses: Vec<(usize, EIP1186AccountProofResponse)> = futures::stream::iter(requests)
+            .map(|(i, address, keys)| async move {
+                let proof = self
+                    .with_failover("eth_getProof", |provider| {
+                        let keys = keys.clone();
+                        async move {
+                            Ok(provider
+                                .get_proof(address, keys)
+                                .number(block_number)
+                                .await?)
+                        }
+                    })
+                    .await?;
+
+                Ok::<_, anyhow::Error>((i, proof))
+            })
+            .buffered(self.config.max_concurrent_requests.max(1))
+            .try_collect()
+            .await?;
+
+        let mut merged: Vec<Option<ContractStorage>> = contracts.iter().map(|_| None).collect();
+        for (i, proof) in responses {
+            match &mut merged[i] {
+                Some(contract_storage) => {
+                    if contract_storage.value.storage_root != proof.storage_hash {
+                        anyhow::bail!(
+                            "Received inconsistent storage roots for contract {:?} at block {block_number}",
+                            proof.address
+                        );
+                    }
+
+                    contract_storage
+                        .storage_slots
+                        .extend(
+                            proof
+                                .storage_proof
+                                .into_iter()
+                                .map(|p| StorageSlotWithProof {
+                                    key: p.key.as_b256(),
+                                    value: p.value,
+                                    mpt_proof: p.proof,
+                                }),
+                        );
+                }
+                None => merged[i] = Some(to_contract_storage(proof)),
+            }
+        }
+
+        merged
+            .into_iter()
+            .map(|contract_storage| {
+                let contract_storage =
+                    contract_storage.expect("Every contract has at least one proof request");
+
+                verify_storage_slot_proofs(state_root, &contract_storage).context(format!(
+                    "Preflight storage slot proofs failed to verify for contract {:?}",
+                    contract_storage.address
+                ))?;
+
+                Ok(contract_storage)
+            })
+            .collect()
+    }
+
+    /// Run a request against each provider in order, returning the first success, and retry
+    /// with backoff if every provider fails.
+    async fn with_failover<T, F, Fut>(&self, method: &str, f: F) -> Result<T>
+    where
+        F: Fn(&'a dyn Provider) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let f = &f;
+        self.retry.retry(method, || self.try_each(method, f)).await
+    }
+
+    async fn try_each<T, F, Fut>(&self, method: &str, f: &F) -> Result<T>
+    where
+        F: Fn(&'a dyn Provider) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        // Unhealthy providers are skipped, unless every provider is.
+        let providers = self.breakers.available(
+            self.providers
+                .iter()
+                .enumerate()
+                .map(|(i, provider)| (format!("execution#{i}"), (i, *provider)))
+                .collect(),
+        );
+
+        let mut errors = Vec::new();
+        for (endpoint, (i, provider)) in providers {
+            let started = Instant::now();
+            // A provider that hangs would otherwise never fail over.
+            let response = tokio::time::timeout(self.retry.timeout, f(provider))
+                .await
+                .unwrap_or_else(|_| Err(anyhow!("timed out after {:?}", self.retry.timeout)));
+            metrics().observe_rpc(&endpoint, method, started.elapsed(), response.is_ok());
+            self.breakers
+                .record(&endpoint, response.is_ok(), self.retry);
+
+            match response {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    warn!("{method} failed on execution RPC #{i}: {e:?}");
+                    errors.push(format!("#{i}: {e}"));
+                }
+            }
+        }
+
+        Err(anyhow!(
+            "{method} failed on every execution RPC: {}",
+            errors.join("; ")
+        ))
+    }
+}
+
+/// Split the keys of each contract into `eth_getProof` calls of at most `max_keys_per_request`
+/// keys, tagged with the position of their contract.
+///
+/// Every contract needs at least one call for its account proof, even without keys.
+pub fn proof_requests(
+    contracts: &[(Address, Vec<B256>)],
+    max_keys_per_request: usize,
+) -> Vec<(usize, Address, Vec<B256>)> {
+    let chunk_size = max_keys_per_request.max(1);
+
+    contracts
+        .iter()
+        .enumerate()
+        .flat_map(|(i, (address, keys))| {
+            let chunks: Vec<Vec<B256>> = if keys.is_empty() {
+                vec![vec![]]
+            } else {
+                keys.chunks(chunk_size).map(<[B256]>::to_vec).collect()
+            };
+
+            chunks.into_iter().map(move |chunk| (i, *address, chunk))
+        })
+        .collect()
+}
+
+fn to_contract_storage(proof: EIP1186AccountProofResponse) -> ContractStorage {
+    ContractStorage {
+        address: proof.address,
+        value: alloy_trie::TrieAccount {
+            nonce: proof.nonce,
+            balance: proof.balance,
+            storage_root: proof.storage_hash,
+            code_hash: proof.code_hash,
+        },
+        mpt_proof: proof.account_proof,
+        storage_slots: proof
+            .storage_proof
+            .into_iter()
+            .map(|p| StorageSlotWithProof {
+                key: p.key.as_b256(),
+                value: p.value,
+                mpt_proof: p.proof,
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,177 @@
+use crate::metrics::endpoint_label;
+use anyhow::{Context, Result};
+use eventsource_stream::Eventsource;
+use futures::StreamExt;
+use reqwest::header::ACCEPT;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// The beacon API event topics that signal a new finalized checkpoint.
+const TOPICS: &str = "finalized_checkpoint,light_client_finality_update";
+
+/// The delay before the first reconnect attempt, doubled after each failure.
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+const SLOTS_PER_EPOCH: u64 = 32;
+
+/// How long a stream may go without events before it is considered stale and reconnected.
+///
+/// A checkpoint finalizes every epoch while the chain is healthy, so a silent stream for much
+/// longer than that is more likely a stuck connection than a quiet chain.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(SLOTS_PER_EPOCH * 12 + 30);
+
+#[derive(Deserialize)]
+struct FinalizedCheckpointEvent {
+    epoch: String,
+}
+
+#[derive(Deserialize)]
+struct LightClientFinalityUpdateEvent {
+    data: FinalityUpdateData,
+}
+
+#[derive(Deserialize)]
+struct FinalityUpdateData {
+    finalized_header: FinalizedHeader,
+}
+
+#[derive(Deserialize)]
+struct FinalizedHeader {
+    beacon: BeaconHeader,
+}
+
+#[derive(Deserialize)]
+struct BeaconHeader {
+    slot: String,
+}
+
+/// Follow the beacon node's finality events, sending the slot of each newly finalized checkpoint.
+///
+/// Reconnects whenever the stream fails, ends, or goes without events for `idle_timeout`, moving
+/// on to the next endpoint and backing off exponentially once every endpoint has failed in a row,
+/// or right away after a stream that ended before delivering any event.
+/// Returns once the receiver is dropped.
+pub async fn watch_finality(
+    consensus_rpcs: Vec<String>,
+    slots: mpsc::Sender<u64>,
+    idle_timeout: Duration,
+) {
+    let mut last_slot = 0;
+    let mut delay = INITIAL_RECONNECT_DELAY;
+    let mut failures = 0;
+
+    for consensus_rpc in consensus_rpcs.iter().cycle() {
+        if slots.is_closed() {
+            break;
+        }
+
+        let result = follow_events(consensus_rpc, &slots, idle_timeout, &mut last_slot).await;
+        // The URLs usually carry API keys.
+        let endpoint = endpoint_label(consensus_rpc);
+
+        match result {
+            // A clean end after events means the stream was up, so start backing off from scratch.
+            Ok(true) => {
+                warn!("Finality event stream from {endpoint} ended, reconnecting");
+                delay = INITIAL_RECONNECT_DELAY;
+                failures = 0;
+            }
+            // A node, or a proxy in front of it, may accept connections only to close them right
+            // away, which must not turn into a reconnect loop.
+            Ok(false) => {
+                warn!(
+                    "Finality event stream from {endpoint} ended without events, reconnecting in \
+                     {delay:?}"
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+            Err(e) => {
+                warn!("Finality event stream from {endpoint} failed: {e:?}");
+                failures += 1;
+                if failures % consensus_rpcs.len() == 0 {
+                    warn!("Every finality event stream failed, retrying in {delay:?}");
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+            }
+        }
+    }
+}
+
+/// Follow one connection's finality events until it ends, returning whether any arrived.
+async fn follow_events(
+    consensus_rpc: &str,
+    slots: &mpsc::Sender<u64>,
+    idle_timeout: Duration,
+    last_slot: &mut u64,
+) -> Result<bool> {
+    let url = format!(
+        "{}/eth/v1/events?topics={TOPICS}",
+        consensus_rpc.trim_end_matches('/')
+    );
+
+    let response = reqwest::Client::new()
+        .get(&url)
+        .header(ACCEPT, "text/event-stream")
+        .send()
+        .await
+        .map_err(reqwest::Error::without_url)
+        .context("Failed to connect to finality event stream")?
+        .error_for_status()
+        .map_err(reqwest::Error::without_url)?;
+
+    info!(
+        "Subscribed to finality events from {}",
+        endpoint_label(consensus_rpc)
+    );
+
+    let mut events = response
+        .bytes_stream()
+        .map(|chunk| chunk.map_err(reqwest::Error::without_url))
+        .eventsource();
+    let mut received = false;
+    loop {
+        // A node can keep the connection open without sending anything, which would otherwise
+        // stall event-driven updates for as long as it does.
+        let Ok(event) = tokio::time::timeout(idle_timeout, events.next()).await else {
+            anyhow::bail!("No finality events for {idle_timeout:?}");
+        };
+        let Some(event) = event else {
+            break;
+        };
+        let event = event.map_err(|e| anyhow::anyhow!("Failed to read finality event: {e}"))?;
+
+        let slot = match event.event.as_str() {
+            "finalized_checkpoint" => {
+                let checkpoint: FinalizedCheckpointEvent = serde_json::from_str(&event.data)?;
+                checkpoint.epoch.parse::<u64>()? * SLOTS_PER_EPOCH
+            }
+            "light_client_finality_update" => {
+                let update: LightClientFinalityUpdateEvent = serde_json::from_str(&event.data)?;
+                update.data.finalized_header.beacon.slot.parse()?
+            }
+            other => {
+                debug!("Ignoring beacon event {other}");
+                continue;
+            }
+        };
+        received = true;
+
+        // Both topics fire for the same checkpoint, only signal it once.
+        if slot <= *last_slot {
+            continue;
+        }
+        *last_slot = slot;
+
+        debug!("New finalized checkpoint at slot {slot}");
+        if slots.send(slot).await.is_err() {
+            break;
+        }
+    }
+
+    Ok(received)
+}
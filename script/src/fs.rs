@@ -0,0 +1,26 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::Path;
+
+/// The extension of the temporary file [`write_atomic`] writes next to its target.
+pub(crate) const TMP_EXTENSION: &str = "tmp";
+
+/// Write a file through a temporary one, so a crash never leaves it half written.
+///
+/// The file is synced before it replaces the old one, and the directory after, so that neither
+/// the contents nor the rename are lost on power failure. This blocks on disk I/O, so async code
+/// runs it with [`tokio::task::spawn_blocking`].
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp = path.with_extension(TMP_EXTENSION);
+    let write = || {
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        if let Some(dir) = path.parent() {
+            std::fs::File::open(dir)?.sync_all()?;
+        }
+        Ok::<_, std::io::Error>(())
+    };
+    write().with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -1,63 +1,488 @@
+use crate::cache::{CacheStats, StorageProofCache, StorageProofCacheConfig};
+use crate::db::{OperatorDb, ProofKind, Watchlist};
+use crate::events::{Events, InFlightRelay, OperatorEvent, OperatorStatus};
+use crate::inclusion::{SlotTracker, WrittenStorageSlot};
+use crate::jobs::{JobId, JobInfo, StorageProof, StorageProofJobs, SubmitError};
+use crate::scheduler::{ProvingQueue, QueueDepth};
+use crate::transaction::{RelayConfig, SharedRelayConfig};
 use alloy::primitives::{Address, B256};
+use alloy::rpc::types::TransactionReceipt;
 use anyhow::Result;
-use sp1_sdk::SP1ProofWithPublicValues;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use std::{future::Future, sync::Arc};
-use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Mutex, RwLock};
+use tracing::warn;
 
+#[derive(Clone)]
 pub struct OperatorHandle {
     storage_slot_config: Arc<Mutex<HashMap<Address, HashSet<B256>>>>,
-    shutdown: oneshot::Sender<()>,
-    storage_proof_tx: mpsc::UnboundedSender<StorageProofRequest>,
+    /// Locked after `storage_slot_config`.
+    watchlist_owners: Arc<Mutex<WatchlistOwners>>,
+    pending_update_slots: Arc<Mutex<Vec<PendingUpdateSlots>>>,
+    slot_tracker: Arc<Mutex<SlotTracker>>,
+    contract_status: Arc<RwLock<HashMap<Address, ContractStatus>>>,
+    shutdown: Arc<Mutex<Option<oneshot::Sender<ShutdownRequest>>>>,
+    storage_proof_tx: mpsc::Sender<StorageProofRequest>,
+    storage_proof_jobs: StorageProofJobs,
+    proving_queue: Arc<ProvingQueue>,
+    storage_proof_cache: Arc<StorageProofCache>,
+    db: Option<Arc<OperatorDb>>,
+    /// Bumped under the watchlist locks on every change, so that saves finishing out of order
+    /// never replace a newer watchlist in the database.
+    watchlist_version: Arc<AtomicU64>,
+    /// The version of the watchlist last saved to the database.
+    saved_watchlist_version: Arc<Mutex<u64>>,
+    relay_config: SharedRelayConfig,
+    loop_delay: Arc<watch::Sender<Duration>>,
+    events: Events,
 }
 
-pub(crate) struct StorageProofRequest {
-    pub(crate) block_number: u64,
+/// The state an operator shares with its handles, built once when it starts running.
+#[derive(Clone)]
+pub(crate) struct OperatorShared {
+    pub(crate) storage_slot_config: Arc<Mutex<HashMap<Address, HashSet<B256>>>>,
+    pub(crate) watchlist_owners: Arc<Mutex<WatchlistOwners>>,
+    pub(crate) pending_update_slots: Arc<Mutex<Vec<PendingUpdateSlots>>>,
+    pub(crate) slot_tracker: Arc<Mutex<SlotTracker>>,
+    pub(crate) contract_status: Arc<RwLock<HashMap<Address, ContractStatus>>>,
+    pub(crate) storage_proof_jobs: StorageProofJobs,
+    pub(crate) proving_queue: Arc<ProvingQueue>,
+    pub(crate) storage_proof_cache: Arc<StorageProofCache>,
+    pub(crate) db: Option<Arc<OperatorDb>>,
+    pub(crate) relay_config: SharedRelayConfig,
+    pub(crate) events: Events,
+}
+
+/// The storage slots added to the watchlist at runtime, with the name of the API client that
+/// added each, or `None` if it was added without one.
+///
+/// Unlike configured slots, these are kept when the configured watchlist is replaced.
+pub type WatchlistOwners = HashMap<(Address, B256), Option<String>>;
+
+/// A storage proof job, as queued for the operator to prove.
+pub struct StorageProofRequest {
+    pub job: JobId,
+    pub block_number: u64,
+    pub contract_keys: Vec<ContractKeys>,
+    /// Whether to relay the proof to the destination contracts once generated.
+    pub relay: bool,
+}
+
+/// Storage slots to prove with the next update only, and who to tell once it lands.
+pub(crate) struct PendingUpdateSlots {
     pub(crate) contract_keys: Vec<ContractKeys>,
-    pub(crate) tx: oneshot::Sender<Result<SP1ProofWithPublicValues>>,
+    pub(crate) tx: oneshot::Sender<Result<IncludedUpdate>>,
+}
+
+/// Asks the operator to shut down, waiting up to `deadline` for in-flight work.
+pub(crate) struct ShutdownRequest {
+    pub(crate) deadline: Duration,
+    pub(crate) reply: oneshot::Sender<ShutdownSummary>,
+}
+
+/// The work the operator abandoned when it shut down.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ShutdownSummary {
+    /// Whether in-flight work was still running at the deadline, and cancelled.
+    pub timed_out: bool,
+    /// Proofs that were still being generated at the deadline.
+    pub abandoned_proofs: Vec<ProofKind>,
+    /// Relay transactions that were sent but not confirmed at the deadline. They may still land,
+    /// and with a database, their proofs are relayed again on restart.
+    pub abandoned_relays: Vec<InFlightRelay>,
+    /// Storage proof jobs that failed with a shutdown error, because they were still queued or
+    /// did not finish by the deadline.
+    pub abandoned_jobs: Vec<JobId>,
+    /// Requests to prove storage slots with the next update that failed with a shutdown error.
+    pub abandoned_next_update_requests: usize,
+}
+
+/// An update that landed on-chain with storage slots attached through
+/// [`OperatorHandle::include_in_next_update`].
+#[derive(Debug, Clone)]
+pub struct IncludedUpdate {
+    pub head: u64,
+    /// The execution block the storage slots were read at.
+    pub execution_block_number: u64,
+    pub receipts: Vec<UpdateReceipt>,
+}
+
+/// The receipt of an update relayed to a destination contract.
+#[derive(Debug, Clone)]
+pub struct UpdateReceipt {
+    pub contract_address: Address,
+    pub receipt: TransactionReceipt,
 }
 
 /// A list of contract and storage slots.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContractKeys {
     pub address: Address,
     pub storage_slots: Vec<B256>,
 }
 
+/// The state of a destination contract, as of the operator's last check.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContractStatus {
+    pub contract_address: Address,
+    pub head: u64,
+    pub header: B256,
+    /// When the contract was last checked, in seconds since the Unix epoch.
+    pub checked_at: u64,
+}
+
 impl OperatorHandle {
     pub(crate) fn new(
-        storage_slot_config: Arc<Mutex<HashMap<Address, HashSet<B256>>>>,
-        shutdown: oneshot::Sender<()>,
-        storage_proof_tx: mpsc::UnboundedSender<StorageProofRequest>,
+        shared: OperatorShared,
+        shutdown: oneshot::Sender<ShutdownRequest>,
+        storage_proof_tx: mpsc::Sender<StorageProofRequest>,
+        loop_delay: watch::Sender<Duration>,
     ) -> Self {
+        let OperatorShared {
+            storage_slot_config,
+            watchlist_owners,
+            pending_update_slots,
+            slot_tracker,
+            contract_status,
+            storage_proof_jobs,
+            proving_queue,
+            storage_proof_cache,
+            db,
+            relay_config,
+            events,
+        } = shared;
+
         Self {
             storage_slot_config,
-            shutdown,
+            watchlist_owners,
+            pending_update_slots,
+            slot_tracker,
+            contract_status,
+            shutdown: Arc::new(Mutex::new(Some(shutdown))),
             storage_proof_tx,
+            storage_proof_jobs,
+            proving_queue,
+            storage_proof_cache,
+            db,
+            watchlist_version: Arc::default(),
+            saved_watchlist_version: Arc::default(),
+            relay_config,
+            loop_delay: Arc::new(loop_delay),
+            events,
+        }
+    }
+
+    /// A handle that is not attached to a running operator, for testing services built on it.
+    ///
+    /// It keeps a watchlist of its own, persisted to `db` if given, and fails storage proof
+    /// requests as if the operator had stopped.
+    pub fn detached(db: Option<OperatorDb>) -> Result<Self> {
+        let watchlist = match &db {
+            Some(db) => db.watchlist()?,
+            None => Watchlist::default(),
+        };
+        let mut slot_tracker = SlotTracker::default();
+        for ((address, slot), max_staleness) in watchlist.max_staleness {
+            slot_tracker.set_max_staleness(address, slot, Some(max_staleness));
         }
+        let (storage_proof_tx, _) = mpsc::channel(1);
+
+        let shared = OperatorShared {
+            storage_slot_config: Arc::new(Mutex::new(watchlist.storage_slots)),
+            watchlist_owners: Arc::new(Mutex::new(watchlist.owners)),
+            pending_update_slots: Arc::new(Mutex::new(Vec::new())),
+            slot_tracker: Arc::new(Mutex::new(slot_tracker)),
+            contract_status: Arc::new(RwLock::new(HashMap::new())),
+            storage_proof_jobs: StorageProofJobs::default(),
+            proving_queue: ProvingQueue::new(1),
+            storage_proof_cache: Arc::new(StorageProofCache::new(
+                StorageProofCacheConfig::default(),
+            )?),
+            db: db.map(Arc::new),
+            relay_config: SharedRelayConfig::default(),
+            events: Events::default(),
+        };
+        let handle = Self::new(
+            shared,
+            oneshot::channel().0,
+            storage_proof_tx,
+            watch::channel(Duration::ZERO).0,
+        );
+
+        Ok(Self {
+            shutdown: Arc::new(Mutex::new(None)),
+            ..handle
+        })
+    }
+
+    /// What the operator is doing: its last update and error, the head of each destination and
+    /// the work in flight.
+    pub async fn status(&self) -> OperatorStatus {
+        self.events
+            .status(self.contract_status().await, self.proving_queue())
+    }
+
+    /// Subscribe to the operator's events from now on.
+    ///
+    /// A subscriber that falls too far behind misses the oldest events, and is told how many with
+    /// [`broadcast::error::RecvError::Lagged`].
+    pub fn subscribe(&self) -> broadcast::Receiver<OperatorEvent> {
+        self.events.subscribe()
+    }
+
+    /// Get the storage slots proven with every update.
+    pub async fn storage_slots(&self) -> HashMap<Address, HashSet<B256>> {
+        self.storage_slot_config.lock().await.clone()
+    }
+
+    /// Get the storage slot values the destinations hold, with the block each was written at.
+    ///
+    /// Slots left out of later updates are only readable at the block they were last written at.
+    pub async fn written_storage_slots(&self) -> Vec<WrittenStorageSlot> {
+        self.slot_tracker.lock().await.written()
+    }
+
+    /// Get the state of each destination contract, as of the operator's last check.
+    pub async fn contract_status(&self) -> Vec<ContractStatus> {
+        self.contract_status
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect()
     }
 
     /// Add a storage slot to the operator.
     pub async fn add_storage_slot(&self, address: Address, storage_slot: B256) {
+        self.insert_storage_slot(address, storage_slot, None, usize::MAX)
+            .await;
+    }
+
+    /// Add a storage slot to the operator on behalf of an API client, which owns it until it is
+    /// removed.
+    ///
+    /// Returns `false`, leaving the watchlist as is, if the client already owns `max_slots`
+    /// watched slots. A slot that is already watched keeps its owner.
+    pub async fn add_client_storage_slot(
+        &self,
+        address: Address,
+        storage_slot: B256,
+        client: &str,
+        max_slots: usize,
+    ) -> bool {
+        self.insert_storage_slot(address, storage_slot, Some(client), max_slots)
+            .await
+    }
+
+    async fn insert_storage_slot(
+        &self,
+        address: Address,
+        storage_slot: B256,
+        client: Option<&str>,
+        max_slots: usize,
+    ) -> bool {
         let mut storage_slot_config = self.storage_slot_config.lock().await;
+        if storage_slot_config
+            .get(&address)
+            .is_some_and(|slots| slots.contains(&storage_slot))
+        {
+            return true;
+        }
+
+        let mut owners = self.watchlist_owners.lock().await;
+        if let Some(client) = client {
+            let owned = owners
+                .values()
+                .filter(|owner| owner.as_deref() == Some(client))
+                .count();
+            if owned >= max_slots {
+                return false;
+            }
+        }
+
         storage_slot_config
             .entry(address)
             .or_insert_with(HashSet::new)
             .insert(storage_slot);
+        owners.insert((address, storage_slot), client.map(str::to_owned));
+        let snapshot = self.watchlist_snapshot(&storage_slot_config, &owners).await;
+        drop(owners);
+        drop(storage_slot_config);
+
+        self.save_watchlist(snapshot).await;
+        true
     }
 
     /// Remove a storage slot from the operator.
     pub async fn remove_storage_slot(&self, address: Address, storage_slot: B256) {
-        let mut storage_slot_config = self.storage_slot_config.lock().await;
-        storage_slot_config
-            .entry(address)
-            .or_insert_with(HashSet::new)
-            .remove(&storage_slot);
+        self.remove_storage_slots(address, Some(storage_slot), None)
+            .await;
+    }
+
+    /// Remove a storage slot added by the given API client.
+    ///
+    /// Returns `false`, leaving the watchlist as is, if the slot is watched but the client does
+    /// not own it.
+    pub async fn remove_client_storage_slot(
+        &self,
+        address: Address,
+        storage_slot: B256,
+        client: &str,
+    ) -> bool {
+        self.remove_storage_slots(address, Some(storage_slot), Some(client))
+            .await
+    }
+
+    /// Set how long an unchanged storage slot may go without being included in an update, or
+    /// fall back to the operator's default with `None`.
+    ///
+    /// Only applies when the operator only includes changed slots.
+    pub async fn set_storage_slot_max_staleness(
+        &self,
+        address: Address,
+        storage_slot: B256,
+        max_staleness: Option<Duration>,
+    ) {
+        self.update_max_staleness(address, storage_slot, None, max_staleness)
+            .await;
+    }
+
+    /// Set how long an unchanged storage slot may go without being included in an update on
+    /// behalf of an API client, like [`Self::set_storage_slot_max_staleness`].
+    ///
+    /// Returns `false`, leaving it as is, if the client does not own the slot.
+    pub async fn set_client_storage_slot_max_staleness(
+        &self,
+        address: Address,
+        storage_slot: B256,
+        client: &str,
+        max_staleness: Option<Duration>,
+    ) -> bool {
+        self.update_max_staleness(address, storage_slot, Some(client), max_staleness)
+            .await
+    }
+
+    /// Set a storage slot's maximum staleness, as long as `client` owns it, if given, and save it
+    /// with the watchlist.
+    async fn update_max_staleness(
+        &self,
+        address: Address,
+        storage_slot: B256,
+        client: Option<&str>,
+        max_staleness: Option<Duration>,
+    ) -> bool {
+        let storage_slot_config = self.storage_slot_config.lock().await;
+        let owners = self.watchlist_owners.lock().await;
+        if let Some(client) = client {
+            if owners
+                .get(&(address, storage_slot))
+                .and_then(Option::as_deref)
+                != Some(client)
+            {
+                return false;
+            }
+        }
+
+        let mut slot_tracker = self.slot_tracker.lock().await;
+        slot_tracker.set_max_staleness(address, storage_slot, max_staleness);
+        let snapshot = self.snapshot_with(&storage_slot_config, &owners, &slot_tracker);
+        drop(slot_tracker);
+        drop(owners);
+        drop(storage_slot_config);
+
+        self.save_watchlist(snapshot).await;
+        true
     }
 
     /// Remove an address from the operator.
     pub async fn remove_address(&self, address: Address) {
+        self.remove_storage_slots(address, None, None).await;
+    }
+
+    /// Remove an address whose watched storage slots were all added by the given API client.
+    ///
+    /// Returns `false`, leaving the watchlist as is, if the client does not own every slot.
+    pub async fn remove_client_address(&self, address: Address, client: &str) -> bool {
+        self.remove_storage_slots(address, None, Some(client)).await
+    }
+
+    /// Remove a storage slot, or with `None` the whole address, as long as `client` owns every
+    /// slot removed, if given.
+    async fn remove_storage_slots(
+        &self,
+        address: Address,
+        storage_slot: Option<B256>,
+        client: Option<&str>,
+    ) -> bool {
         let mut storage_slot_config = self.storage_slot_config.lock().await;
-        storage_slot_config.remove(&address);
+        let mut owners = self.watchlist_owners.lock().await;
+        let watched = storage_slot_config
+            .entry(address)
+            .or_insert_with(HashSet::new);
+        let removed: Vec<B256> = match storage_slot {
+            Some(slot) => watched.get(&slot).copied().into_iter().collect(),
+            None => watched.iter().copied().collect(),
+        };
+
+        if let Some(client) = client {
+            let owns = |slot: &B256| {
+                owners.get(&(address, *slot)).and_then(Option::as_deref) == Some(client)
+            };
+            if !removed.iter().all(owns) {
+                return false;
+            }
+        }
+
+        for slot in &removed {
+            watched.remove(slot);
+            owners.remove(&(address, *slot));
+        }
+        if storage_slot.is_none() {
+            storage_slot_config.remove(&address);
+        }
+        let mut slot_tracker = self.slot_tracker.lock().await;
+        slot_tracker.forget(address, storage_slot);
+        let snapshot = self.snapshot_with(&storage_slot_config, &owners, &slot_tracker);
+        drop(slot_tracker);
+        drop(owners);
+        drop(storage_slot_config);
+
+        self.save_watchlist(snapshot).await;
+        true
+    }
+
+    /// Replace the storage slots proven with every update, keeping those added at runtime with
+    /// [`Self::add_storage_slot`] or by API clients.
+    pub async fn set_storage_slots(&self, mut storage_slots: HashMap<Address, HashSet<B256>>) {
+        let mut storage_slot_config = self.storage_slot_config.lock().await;
+        let owners = self.watchlist_owners.lock().await;
+        for (address, slot) in owners.keys() {
+            storage_slots
+                .entry(*address)
+                .or_insert_with(HashSet::new)
+                .insert(*slot);
+        }
+        let previous = std::mem::replace(&mut *storage_slot_config, storage_slots);
+
+        let mut slot_tracker = self.slot_tracker.lock().await;
+        for (address, slots) in previous {
+            let Some(kept) = storage_slot_config.get(&address) else {
+                slot_tracker.forget(address, None);
+                continue;
+            };
+            for slot in slots.difference(kept) {
+                slot_tracker.forget(address, Some(*slot));
+            }
+        }
+        let snapshot = self.snapshot_with(&storage_slot_config, &owners, &slot_tracker);
+        drop(slot_tracker);
+        drop(owners);
+        drop(storage_slot_config);
+
+        self.save_watchlist(snapshot).await;
     }
 
     /// Modify the storage slot config in place.
@@ -67,53 +492,282 @@ impl OperatorHandle {
         Fut: Future<Output = Res>,
     {
         let mut storage_slot_config = self.storage_slot_config.lock().await;
-        func(&mut storage_slot_config).await
+        let res = func(&mut storage_slot_config).await;
+        let mut owners = self.watchlist_owners.lock().await;
+        owners.retain(|(address, slot), _| {
+            storage_slot_config
+                .get(address)
+                .is_some_and(|slots| slots.contains(slot))
+        });
+        let snapshot = self.watchlist_snapshot(&storage_slot_config, &owners).await;
+        drop(owners);
+        drop(storage_slot_config);
+
+        self.save_watchlist(snapshot).await;
+        res
+    }
+
+    /// Get how transactions are priced, replaced and confirmed.
+    pub fn relay_config(&self) -> RelayConfig {
+        self.relay_config
+            .read()
+            .expect("Relay config lock poisoned")
+            .clone()
+    }
+
+    /// Change how transactions are priced, replaced and confirmed, from the next transaction on.
+    pub fn set_relay_config(&self, config: RelayConfig) {
+        *self
+            .relay_config
+            .write()
+            .expect("Relay config lock poisoned") = config;
+    }
+
+    /// Change the delay between operator runs, restarting the current wait.
+    pub fn set_loop_delay(&self, loop_delay: Duration) {
+        self.loop_delay.send_replace(loop_delay);
+    }
+
+    /// Take a versioned copy of the watchlist to save, if the operator has a database.
+    ///
+    /// Taken under the watchlist locks, which are released before the copy is saved with
+    /// [`Self::save_watchlist`].
+    async fn watchlist_snapshot(
+        &self,
+        storage_slot_config: &HashMap<Address, HashSet<B256>>,
+        owners: &WatchlistOwners,
+    ) -> Option<(u64, Watchlist)> {
+        self.db.as_ref()?;
+        let slot_tracker = self.slot_tracker.lock().await;
+        self.snapshot_with(storage_slot_config, owners, &slot_tracker)
+    }
+
+    fn snapshot_with(
+        &self,
+        storage_slot_config: &HashMap<Address, HashSet<B256>>,
+        owners: &WatchlistOwners,
+        slot_tracker: &SlotTracker,
+    ) -> Option<(u64, Watchlist)> {
+        self.db.as_ref()?;
+        let mut max_staleness = slot_tracker.max_staleness().clone();
+        max_staleness.retain(|(address, slot), _| {
+            storage_slot_config
+                .get(address)
+                .is_some_and(|slots| slots.contains(slot))
+        });
+
+        let version = self.watchlist_version.fetch_add(1, Ordering::SeqCst) + 1;
+        Some((
+            version,
+            Watchlist {
+                storage_slots: storage_slot_config.clone(),
+                owners: owners.clone(),
+                max_staleness,
+            },
+        ))
+    }
+
+    /// Persist a copy of the watchlist to the operator's database, unless a newer one was saved
+    /// already.
+    async fn save_watchlist(&self, snapshot: Option<(u64, Watchlist)>) {
+        let (Some(db), Some((version, watchlist))) = (&self.db, snapshot) else {
+            return;
+        };
+
+        let mut saved = self.saved_watchlist_version.lock().await;
+        if *saved > version {
+            return;
+        }
+        match db.run(move |db| db.save_watchlist(&watchlist)).await {
+            Ok(()) => *saved = version,
+            Err(e) => warn!("Failed to save the storage slot watchlist: {:?}", e),
+        }
+    }
+
+    /// Prove the given storage slots with the next update only, rather than every update or a
+    /// separate storage proof.
+    ///
+    /// Resolves once the update lands on every destination, or fails if proving or relaying it
+    /// fails. Either way, the slots are not included in later updates.
+    pub async fn include_in_next_update(
+        &self,
+        contract_keys: Vec<ContractKeys>,
+    ) -> Result<IncludedUpdate> {
+        // Checked under the lock the operator takes the slots with on shutdown, so they are
+        // either answered or never added.
+        let mut pending = self.pending_update_slots.lock().await;
+        if self.shutdown.lock().await.is_none() {
+            return Err(SubmitError::Stopped.into());
+        }
+        let (tx, rx) = oneshot::channel();
+        pending.push(PendingUpdateSlots { contract_keys, tx });
+        drop(pending);
+
+        rx.await
+            .map_err(|_| anyhow::anyhow!("The operator stopped before the update landed"))?
     }
 
     /// Get a proof for a given address and storage slots.
+    ///
+    /// The proof may be shared with other requests for the same block, see [`StorageProof`].
     pub async fn get_proof_for(
         &self,
         block_number: u64,
         address: Address,
         storage_slot: &[B256],
-    ) -> Result<SP1ProofWithPublicValues> {
-        let (tx, rx) = oneshot::channel();
-        if let Err(e) = self.storage_proof_tx.send(StorageProofRequest {
+    ) -> Result<StorageProof> {
+        self.get_proofs_for(
             block_number,
-            contract_keys: vec![ContractKeys {
+            vec![ContractKeys {
                 address,
                 storage_slots: storage_slot.to_vec(),
             }],
-            tx,
-        }) {
-            tracing::error!("Failed to send storage proof request: {:?}", e);
-        }
-
-        rx.await?
+        )
+        .await
     }
 
     /// Get proofs for a given block number and a list of contract keys.
+    ///
+    /// Waits for room if the proving queue is full. The proof may be shared with other requests
+    /// for the same block, see [`StorageProof`].
     pub async fn get_proofs_for(
         &self,
         block_number: u64,
         contract_keys: Vec<ContractKeys>,
-    ) -> Result<SP1ProofWithPublicValues> {
-        let (tx, rx) = oneshot::channel();
-        if let Err(e) = self.storage_proof_tx.send(StorageProofRequest {
+    ) -> Result<StorageProof> {
+        self.prove_and_wait(block_number, contract_keys, false)
+            .await
+    }
+
+    /// Prove the given contract keys and relay the proof to every destination contract with
+    /// `updateStorageSlot`, returning the proof along with the receipts.
+    ///
+    /// Each destination must already have the execution state root of `block_number`.
+    pub async fn relay_proofs_for(
+        &self,
+        block_number: u64,
+        contract_keys: Vec<ContractKeys>,
+    ) -> Result<StorageProof> {
+        self.prove_and_wait(block_number, contract_keys, true).await
+    }
+
+    async fn prove_and_wait(
+        &self,
+        block_number: u64,
+        contract_keys: Vec<ContractKeys>,
+        relay: bool,
+    ) -> Result<StorageProof> {
+        let job = self
+            .storage_proof_jobs
+            .submit(block_number, contract_keys.clone(), relay, None);
+
+        let request = StorageProofRequest {
+            job,
             block_number,
             contract_keys,
-            tx,
-        }) {
-            tracing::error!("Failed to send storage proof request: {:?}", e);
+            relay,
+        };
+        if self.storage_proof_tx.send(request).await.is_err() {
+            self.storage_proof_jobs.remove(job);
+            return Err(SubmitError::Stopped.into());
         }
 
-        rx.await?
+        self.storage_proof_jobs.wait(job).await
     }
 
-    /// Shutdown the operator.
-    pub async fn shutdown(self) {
-        if self.shutdown.send(()).is_err() {
+    /// Submit a storage proof request without waiting for it, returning the ID of its job.
+    ///
+    /// With `relay`, the proof is also submitted to every destination contract once generated.
+    /// `client` names the API client the job is submitted for, if any. Fails right away if the
+    /// proving queue is full.
+    pub fn submit_storage_proof(
+        &self,
+        block_number: u64,
+        contract_keys: Vec<ContractKeys>,
+        relay: bool,
+        client: Option<String>,
+    ) -> Result<JobId, SubmitError> {
+        let job =
+            self.storage_proof_jobs
+                .submit(block_number, contract_keys.clone(), relay, client);
+
+        let request = StorageProofRequest {
+            job,
+            block_number,
+            contract_keys,
+            relay,
+        };
+        if let Err(e) = self.storage_proof_tx.try_send(request) {
+            self.storage_proof_jobs.remove(job);
+            return Err(match e {
+                mpsc::error::TrySendError::Full(_) => SubmitError::QueueFull,
+                mpsc::error::TrySendError::Closed(_) => SubmitError::Stopped,
+            });
+        }
+
+        Ok(job)
+    }
+
+    /// The number of proofs being generated and waiting to be generated.
+    pub fn proving_queue(&self) -> QueueDepth {
+        QueueDepth {
+            // Storage proof requests are batched before they reach the proving queue, so count
+            // the jobs instead.
+            queued_storage_proofs: self.storage_proof_jobs.queued(),
+            ..self.proving_queue.depth()
+        }
+    }
+
+    /// Hit and miss counts of the storage proof cache.
+    pub fn storage_proof_cache(&self) -> CacheStats {
+        self.storage_proof_cache.stats()
+    }
+
+    /// Get a storage proof job and its status.
+    pub fn storage_proof_job(&self, job: JobId) -> Option<JobInfo> {
+        self.storage_proof_jobs.get(job)
+    }
+
+    /// List the storage proof jobs that are running or finished recently.
+    pub fn storage_proof_jobs(&self) -> Vec<JobInfo> {
+        self.storage_proof_jobs.list()
+    }
+
+    /// Get the proof of a finished storage proof job.
+    pub fn storage_proof_result(&self, job: JobId) -> Option<StorageProof> {
+        self.storage_proof_jobs.result(job)
+    }
+
+    /// Wait for a storage proof job to finish and return its proof.
+    pub async fn wait_for_storage_proof(&self, job: JobId) -> Result<StorageProof> {
+        self.storage_proof_jobs.wait(job).await
+    }
+
+    /// Cancel a storage proof job.
+    ///
+    /// Returns `false` if the job is unknown or has already finished.
+    pub fn cancel_storage_proof(&self, job: JobId) -> bool {
+        self.storage_proof_jobs.cancel(job)
+    }
+
+    /// Shut the operator down and return what it abandoned.
+    ///
+    /// New storage proof requests are rejected right away, and queued ones fail. Running updates
+    /// and storage proof jobs get up to `deadline` to finish before they are cancelled.
+    ///
+    /// Only the first call on any clone of the handle has an effect; later calls return an empty
+    /// summary right away.
+    pub async fn shutdown(self, deadline: Duration) -> ShutdownSummary {
+        let Some(shutdown) = self.shutdown.lock().await.take() else {
+            return ShutdownSummary::default();
+        };
+
+        let (reply, summary) = oneshot::channel();
+        if shutdown.send(ShutdownRequest { deadline, reply }).is_err() {
             tracing::error!("Failed to send shutdown signal");
+            return ShutdownSummary::default();
         }
+
+        summary.await.unwrap_or_default()
     }
 }
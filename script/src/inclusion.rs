@@ -0,0 +1,164 @@
+use alloy::primitives::{Address, B256};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Which watched storage slots are proven with each update.
+#[derive(Debug, Clone, Default)]
+pub enum InclusionPolicy {
+    /// Prove every watched slot with every update.
+    #[default]
+    Always,
+    /// Only prove slots whose value differs from the one last written to the destinations.
+    ///
+    /// Unchanged slots are still included once they were last written more than `max_staleness`
+    /// ago, unless the slot has its own limit.
+    ChangedOnly { max_staleness: Option<Duration> },
+}
+
+/// Tracks the storage slot values written to each destination, to decide which watched slots an
+/// update needs to include.
+///
+/// The contracts store slot values by the block they were proven at, so a slot left out of an
+/// update reads as zero at that update's block. The block each slot was last written at is kept
+/// so that consumers can find its value.
+#[derive(Default)]
+pub struct SlotTracker {
+    policy: InclusionPolicy,
+    /// Per-slot overrides of the policy's maximum staleness.
+    max_staleness: HashMap<(Address, B256), Duration>,
+    /// The last value written to each destination, by destination, contract and slot.
+    written: HashMap<(Address, Address, B256), WrittenSlot>,
+}
+
+struct WrittenSlot {
+    value: B256,
+    block_number: u64,
+    written_at: Instant,
+}
+
+/// A storage slot value held by a destination, and the execution block it was written at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WrittenStorageSlot {
+    pub contract_address: Address,
+    pub address: Address,
+    pub slot: B256,
+    pub value: B256,
+    /// The block to read the slot at with the contract's `getStorageSlot`.
+    pub block_number: u64,
+}
+
+impl SlotTracker {
+    pub fn new(policy: InclusionPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Change the policy, keeping the per-slot overrides and the values known to be written.
+    pub fn set_policy(&mut self, policy: InclusionPolicy) {
+        self.policy = policy;
+    }
+
+    /// Whether updates skip unchanged slots, so written values need to be known.
+    pub fn skips_unchanged(&self) -> bool {
+        matches!(self.policy, InclusionPolicy::ChangedOnly { .. })
+    }
+
+    pub fn set_max_staleness(
+        &mut self,
+        address: Address,
+        slot: B256,
+        max_staleness: Option<Duration>,
+    ) {
+        match max_staleness {
+            Some(max_staleness) => self.max_staleness.insert((address, slot), max_staleness),
+            None => self.max_staleness.remove(&(address, slot)),
+        };
+    }
+
+    /// The per-slot overrides of the policy's maximum staleness.
+    pub fn max_staleness(&self) -> &HashMap<(Address, B256), Duration> {
+        &self.max_staleness
+    }
+
+    /// Forget everything about a slot, or every slot of a contract.
+    pub fn forget(&mut self, address: Address, slot: Option<B256>) {
+        let matches = |a: &Address, s: &B256| *a == address && slot.is_none_or(|slot| slot == *s);
+        self.max_staleness.retain(|(a, s), _| !matches(a, s));
+        self.written.retain(|(_, a, s), _| !matches(a, s));
+    }
+
+    /// Whether the value last written to a destination for a slot is known.
+    pub fn is_known(&self, destination: Address, address: Address, slot: B256) -> bool {
+        self.written.contains_key(&(destination, address, slot))
+    }
+
+    /// Record slot values that landed on a destination at an execution block.
+    pub fn record(
+        &mut self,
+        destination: Address,
+        block_number: u64,
+        slots: impl IntoIterator<Item = (Address, B256, B256)>,
+    ) {
+        let written_at = Instant::now();
+        for (address, slot, value) in slots {
+            self.written.insert(
+                (destination, address, slot),
+                WrittenSlot {
+                    value,
+                    block_number,
+                    written_at,
+                },
+            );
+        }
+    }
+
+    /// Every known slot value held by the destinations, with the block it was written at.
+    pub fn written(&self) -> Vec<WrittenStorageSlot> {
+        let mut written: Vec<WrittenStorageSlot> = self
+            .written
+            .iter()
+            .map(
+                |((contract_address, address, slot), written)| WrittenStorageSlot {
+                    contract_address: *contract_address,
+                    address: *address,
+                    slot: *slot,
+                    value: written.value,
+                    block_number: written.block_number,
+                },
+            )
+            .collect();
+        written.sort_by_key(|w| (w.contract_address, w.address, w.slot));
+        written
+    }
+
+    /// Whether an update to the given destinations needs to include a slot, given its current
+    /// value.
+    pub fn needs_update(
+        &self,
+        destinations: &[Address],
+        address: Address,
+        slot: B256,
+        value: B256,
+    ) -> bool {
+        let InclusionPolicy::ChangedOnly { max_staleness } = &self.policy else {
+            return true;
+        };
+        let max_staleness = self
+            .max_staleness
+            .get(&(address, slot))
+            .or(max_staleness.as_ref());
+
+        destinations.iter().any(|destination| {
+            let Some(written) = self.written.get(&(*destination, address, slot)) else {
+                return true;
+            };
+
+            written.value != value
+                || max_staleness
+                    .is_some_and(|max_staleness| written.written_at.elapsed() >= *max_staleness)
+        })
+    }
+}
@@ -0,0 +1,358 @@
+use crate::handle::ContractKeys;
+use alloy::primitives::Address;
+use alloy::rpc::types::TransactionReceipt;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use sp1_sdk::SP1ProofWithPublicValues;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use tokio::task::AbortHandle;
+
+/// How long finished jobs, and their proofs, are kept around for callers to fetch.
+const FINISHED_JOB_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// Identifies a storage proof job.
+pub type JobId = u64;
+
+/// The progress of a storage proof job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for the operator to pick the job up.
+    Queued,
+    /// Fetching the storage proofs from the execution RPCs.
+    Fetching,
+    /// Generating the storage slots proof.
+    Proving,
+    /// Submitting the proof to the destination contracts.
+    Relaying,
+    /// The proof is ready.
+    Done,
+    Failed {
+        error: String,
+    },
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Whether the job has stopped, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Done | JobStatus::Failed { .. } | JobStatus::Cancelled
+        )
+    }
+}
+
+/// Why a storage proof request was not accepted.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum SubmitError {
+    #[error("the proving queue is full")]
+    QueueFull,
+    #[error("the operator is not running")]
+    Stopped,
+}
+
+/// A storage proof, possibly shared with other requests for the same block.
+#[derive(Debug, Clone)]
+pub struct StorageProof {
+    pub proof: SP1ProofWithPublicValues,
+    /// The position of each requested storage slot in the proof's `StorageProofOutputs`
+    /// `storageSlots`, in the order they were requested.
+    pub slot_indices: Vec<usize>,
+    /// The `updateStorageSlot` transactions, if the proof was relayed to the destinations.
+    pub receipts: Vec<StorageSlotsReceipt>,
+}
+
+/// The receipt of a storage proof relayed to a destination contract.
+#[derive(Debug, Clone)]
+pub struct StorageSlotsReceipt {
+    pub contract_address: Address,
+    pub receipt: TransactionReceipt,
+}
+
+/// A storage proof job and its current status.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: JobId,
+    pub block_number: u64,
+    pub contracts: Vec<ContractKeys>,
+    /// Whether the proof is relayed to the destination contracts once generated.
+    pub relay: bool,
+    pub status: JobStatus,
+    /// When the job was submitted, in seconds since the Unix epoch.
+    pub submitted_at: u64,
+    /// The name of the API client that submitted the job, which only it can see.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+}
+
+struct Job {
+    block_number: u64,
+    contracts: Vec<ContractKeys>,
+    relay: bool,
+    status: watch::Sender<JobStatus>,
+    result: Option<StorageProof>,
+    /// The task working on the job, once the operator has picked it up.
+    ///
+    /// Jobs proven together share the task, which is only aborted once all of them are cancelled.
+    task: Option<Arc<AbortHandle>>,
+    submitted_at: u64,
+    client: Option<String>,
+    finished_at: Option<SystemTime>,
+}
+
+impl Job {
+    fn info(&self, id: JobId) -> JobInfo {
+        JobInfo {
+            id,
+            block_number: self.block_number,
+            contracts: self.contracts.clone(),
+            relay: self.relay,
+            status: self.status.borrow().clone(),
+            submitted_at: self.submitted_at,
+            client: self.client.clone(),
+        }
+    }
+
+    /// Move the job to `status`, unless it has already finished.
+    fn transition(&mut self, status: JobStatus) -> bool {
+        if self.status.borrow().is_finished() {
+            return false;
+        }
+
+        if status.is_finished() {
+            self.finished_at = Some(SystemTime::now());
+            self.task = None;
+        }
+        self.status.send_replace(status);
+        true
+    }
+}
+
+/// The storage proof jobs of an operator, shared by every clone of its handle.
+///
+/// Jobs are submitted through the handle, and moved along by the operator as it works on them.
+#[derive(Clone, Default)]
+pub struct StorageProofJobs {
+    next_id: Arc<AtomicU64>,
+    jobs: Arc<Mutex<HashMap<JobId, Job>>>,
+}
+
+impl StorageProofJobs {
+    /// Register a new job in the queued state, on behalf of `client` if an API client submitted
+    /// it.
+    pub fn submit(
+        &self,
+        block_number: u64,
+        contracts: Vec<ContractKeys>,
+        relay: bool,
+        client: Option<String>,
+    ) -> JobId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let submitted_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut jobs = self.lock();
+        prune_finished(&mut jobs);
+        jobs.insert(
+            id,
+            Job {
+                block_number,
+                contracts,
+                relay,
+                status: watch::channel(JobStatus::Queued).0,
+                result: None,
+                task: None,
+                submitted_at,
+                client,
+                finished_at: None,
+            },
+        );
+
+        id
+    }
+
+    /// Forget a job that was never queued.
+    pub(crate) fn remove(&self, id: JobId) {
+        self.lock().remove(&id);
+    }
+
+    /// Get a job and its status.
+    pub fn get(&self, id: JobId) -> Option<JobInfo> {
+        self.lock().get(&id).map(|job| job.info(id))
+    }
+
+    /// List every job that is still running or was finished recently.
+    pub fn list(&self) -> Vec<JobInfo> {
+        let mut jobs: Vec<JobInfo> = self.lock().iter().map(|(id, job)| job.info(*id)).collect();
+        jobs.sort_by_key(|job| job.id);
+        jobs
+    }
+
+    /// Get the proof of a finished job.
+    ///
+    /// Returns `None` if the job is unknown or has no proof (yet).
+    pub fn result(&self, id: JobId) -> Option<StorageProof> {
+        self.lock().get(&id).and_then(|job| job.result.clone())
+    }
+
+    /// The number of jobs waiting to be picked up.
+    pub fn queued(&self) -> usize {
+        self.lock()
+            .values()
+            .filter(|job| *job.status.borrow() == JobStatus::Queued)
+            .count()
+    }
+
+    /// Cancel a job, stopping its task if it has started and no other job shares it.
+    ///
+    /// Returns `false` if the job is unknown or has already finished.
+    pub fn cancel(&self, id: JobId) -> bool {
+        self.end(id, JobStatus::Cancelled)
+    }
+
+    /// Fail a job because the operator is shutting down, stopping its task if no other job shares
+    /// it.
+    ///
+    /// Returns `false` if the job is unknown or has already finished.
+    pub(crate) fn stop(&self, id: JobId) -> bool {
+        self.end(
+            id,
+            JobStatus::Failed {
+                error: "the operator shut down".to_string(),
+            },
+        )
+    }
+
+    /// The jobs that have not finished, split into those the operator has not picked up yet and
+    /// those it is working on.
+    pub(crate) fn unfinished(&self) -> (Vec<JobId>, Vec<JobId>) {
+        let (started, queued): (Vec<_>, Vec<_>) = self
+            .lock()
+            .iter()
+            .filter(|(_, job)| !job.status.borrow().is_finished())
+            .partition(|(_, job)| job.task.is_some());
+
+        (
+            queued.into_iter().map(|(id, _)| *id).collect(),
+            started.into_iter().map(|(id, _)| *id).collect(),
+        )
+    }
+
+    /// Wait for a job to finish and return its proof.
+    pub async fn wait(&self, id: JobId) -> Result<StorageProof> {
+        let mut status = self
+            .lock()
+            .get(&id)
+            .map(|job| job.status.subscribe())
+            .ok_or_else(|| anyhow!("Unknown storage proof job {id}"))?;
+
+        let status = status
+            .wait_for(JobStatus::is_finished)
+            .await
+            .map_err(|_| anyhow!("Storage proof job {id} was dropped"))?
+            .clone();
+
+        match status {
+            JobStatus::Done => self
+                .result(id)
+                .ok_or_else(|| anyhow!("Storage proof job {id} has no proof")),
+            JobStatus::Failed { error } => Err(anyhow!(error)),
+            JobStatus::Cancelled => Err(anyhow!("Storage proof job {id} was cancelled")),
+            _ => unreachable!("Waited for a finished status"),
+        }
+    }
+
+    /// Record the task working on a set of jobs, aborting it right away if they were all
+    /// cancelled in the meantime.
+    pub fn set_task(&self, ids: &[JobId], task: AbortHandle) {
+        let task = Arc::new(task);
+
+        let mut jobs = self.lock();
+        for id in ids {
+            if let Some(job) = jobs.get_mut(id) {
+                if !job.status.borrow().is_finished() {
+                    job.task = Some(task.clone());
+                }
+            }
+        }
+
+        if Arc::strong_count(&task) == 1 {
+            task.abort();
+        }
+    }
+
+    /// Whether a job is still waiting to be worked on, rather than cancelled or pruned.
+    pub(crate) fn is_pending(&self, id: JobId) -> bool {
+        self.lock()
+            .get(&id)
+            .is_some_and(|job| !job.status.borrow().is_finished())
+    }
+
+    /// Move a job along, unless it has already finished.
+    pub fn set_status(&self, id: JobId, status: JobStatus) {
+        if let Some(job) = self.lock().get_mut(&id) {
+            job.transition(status);
+        }
+    }
+
+    /// Record the outcome of a job.
+    pub fn finish(&self, id: JobId, result: Result<StorageProof>) {
+        let mut jobs = self.lock();
+        let Some(job) = jobs.get_mut(&id) else {
+            return;
+        };
+
+        match result {
+            Ok(proof) => {
+                if job.transition(JobStatus::Done) {
+                    job.result = Some(proof);
+                }
+            }
+            Err(e) => {
+                job.transition(JobStatus::Failed {
+                    error: format!("{e:#}"),
+                });
+            }
+        }
+    }
+
+    /// Move a job to a finished `status`, aborting its task once no other job shares it.
+    fn end(&self, id: JobId, status: JobStatus) -> bool {
+        let mut jobs = self.lock();
+        let Some(job) = jobs.get_mut(&id) else {
+            return false;
+        };
+
+        let task = job.task.take();
+        if !job.transition(status) {
+            return false;
+        }
+        if let Some(task) = task {
+            if Arc::strong_count(&task) == 1 {
+                task.abort();
+            }
+        }
+
+        true
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<JobId, Job>> {
+        self.jobs.lock().expect("Storage proof jobs lock poisoned")
+    }
+}
+
+/// Drop jobs that finished longer than the retention period ago.
+fn prune_finished(jobs: &mut HashMap<JobId, Job>) {
+    jobs.retain(|_, job| {
+        job.finished_at
+            .and_then(|finished_at| finished_at.elapsed().ok())
+            .is_none_or(|elapsed| elapsed < FINISHED_JOB_RETENTION)
+    });
+}
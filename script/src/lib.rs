@@ -1,4 +1,5 @@
 use alloy_primitives::B256;
+use error::OperatorError;
 use helios_consensus_core::{
     calc_sync_period,
     consensus_spec::MainnetConsensusSpec,
@@ -8,64 +9,101 @@ use helios_ethereum::rpc::ConsensusRpc;
 use helios_ethereum::{
     config::{checkpoints, networks::Network, Config},
     consensus::Inner,
-    rpc::http_rpc::HttpRpc,
 };
+use rpc::{ConsensusEndpoints, FallbackRpc};
 
-use anyhow::{anyhow, Result};
+use anyhow::anyhow;
 use std::sync::Arc;
 use tokio::sync::{mpsc::channel, watch};
 use tree_hash::TreeHash;
 
+pub mod auth;
+pub mod batch;
+pub mod budget;
+pub mod cache;
+pub mod config;
+pub mod db;
+pub mod destination;
+pub mod error;
+pub mod events;
+pub mod execution;
+pub mod finality;
+mod fs;
 pub mod handle;
+pub mod inclusion;
+pub mod jobs;
+pub mod metrics;
 pub mod operator;
+pub mod programs;
+pub mod retry;
+pub mod rpc;
+pub mod scheduler;
+pub mod server;
+pub mod telemetry;
+pub mod transaction;
 
 pub const MAX_REQUEST_LIGHT_CLIENT_UPDATES: u8 = 128;
 
 /// Fetch updates for client
 pub async fn get_updates(
-    client: &Inner<MainnetConsensusSpec, HttpRpc>,
-) -> Vec<Update<MainnetConsensusSpec>> {
+    client: &Inner<MainnetConsensusSpec, FallbackRpc>,
+) -> Result<Vec<Update<MainnetConsensusSpec>>, OperatorError> {
     let period =
         calc_sync_period::<MainnetConsensusSpec>(client.store.finalized_header.beacon().slot);
 
-    let updates = client
+    client
         .rpc
         .get_updates(period, MAX_REQUEST_LIGHT_CLIENT_UPDATES)
         .await
-        .unwrap();
-
-    updates.clone()
+        .map_err(|e| {
+            OperatorError::ConsensusRpc(anyhow!("error getting updates: {}", e.to_string()))
+        })
 }
 
 /// Fetch latest checkpoint from chain to bootstrap client to the latest state.
-pub async fn get_latest_checkpoint(chain_id: u64) -> Result<B256> {
+pub async fn get_latest_checkpoint(chain_id: u64) -> Result<B256, OperatorError> {
+    let network =
+        Network::from_chain_id(chain_id).map_err(|_| OperatorError::UnknownNetwork(chain_id))?;
+
     let cf = checkpoints::CheckpointFallback::new()
         .build()
         .await
-        .map_err(|e| anyhow!("error building checkpoint fallback: {}", e.to_string()))?;
-
-    let network = Network::from_chain_id(chain_id).unwrap_or_else(|_| {
-        panic!("unknown network: {chain_id}");
-    });
-
-    cf.fetch_latest_checkpoint(&network)
-        .await
-        .map_err(|e| anyhow!("error fetching latest checkpoint: {}", e.to_string()))
+        .map_err(|e| {
+            OperatorError::Bootstrap(anyhow!(
+                "error building checkpoint fallback: {}",
+                e.to_string()
+            ))
+        })?;
+
+    cf.fetch_latest_checkpoint(&network).await.map_err(|e| {
+        OperatorError::Bootstrap(anyhow!(
+            "error fetching latest checkpoint: {}",
+            e.to_string()
+        ))
+    })
 }
 
 /// Setup a client from a checkpoint slot.
 ///
 /// This method will also bootstrap the client to the given slot, or the latest checkpoint if no slot is provided.
+///
+/// Consensus requests fail over between the given endpoints, and are cross-checked if a quorum is
+/// set.
+#[tracing::instrument(skip_all, fields(slot = ?slot))]
 pub async fn get_client(
     slot: Option<u64>,
-    consensus_rpc: &str,
+    consensus_rpc: &ConsensusEndpoints,
     chain_id: u64,
-) -> Result<Inner<MainnetConsensusSpec, HttpRpc>> {
-    let network = Network::from_chain_id(chain_id).unwrap();
+) -> Result<Inner<MainnetConsensusSpec, FallbackRpc>, OperatorError> {
+    let network =
+        Network::from_chain_id(chain_id).map_err(|_| OperatorError::UnknownNetwork(chain_id))?;
     let base_config = network.to_base_config();
 
+    let primary = consensus_rpc.primary()?;
     let config = Config {
-        consensus_rpc: consensus_rpc.parse()?,
+        consensus_rpc: primary.parse().map_err(|e| {
+            OperatorError::Config(format!("invalid consensus RPC URL {primary}: {e}"))
+        })?,
         execution_rpc: None,
         chain: base_config.chain,
         forks: base_config.forks,
@@ -77,31 +115,33 @@ pub async fn get_client(
     let (finalized_block_send, _) = watch::channel(None);
     let (channel_send, _) = watch::channel(None);
 
-    let mut client = Inner::<MainnetConsensusSpec, HttpRpc>::new(
-        consensus_rpc,
+    // Helios only creates its RPC from a URL string, which cannot carry the timeout, quorum and
+    // shared circuit breakers. Start it without endpoints, which sets nothing up, and hand it the
+    // configured RPC.
+    let mut client = Inner::<MainnetConsensusSpec, FallbackRpc>::new(
+        "",
         block_send,
         finalized_block_send,
         channel_send,
         Arc::new(config),
     );
+    client.rpc = FallbackRpc::from_endpoints(consensus_rpc);
 
     let root = match slot {
         Some(slot) => {
-            let block: BeaconBlock<MainnetConsensusSpec> = client
-                .rpc
-                .get_block(slot)
-                .await
-                .map_err(|e| anyhow!("error getting block: {}", e.to_string()))?;
+            let block: BeaconBlock<MainnetConsensusSpec> =
+                client.rpc.get_block(slot).await.map_err(|e| {
+                    OperatorError::ConsensusRpc(anyhow!("error getting block: {}", e.to_string()))
+                })?;
 
             block.tree_hash_root()
         }
         None => get_latest_checkpoint(chain_id).await?,
     };
 
-    client
-        .bootstrap(root)
-        .await
-        .map_err(|e| anyhow!("error bootstrapping client: {}", e.to_string()))?;
+    client.bootstrap(root).await.map_err(|e| {
+        OperatorError::Bootstrap(anyhow!("error bootstrapping client: {}", e.to_string()))
+    })?;
 
     Ok(client)
 }
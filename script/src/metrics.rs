@@ -0,0 +1,501 @@
+use crate::db::ProofKind;
+use crate::handle::OperatorHandle;
+use alloy::primitives::{keccak256, Address};
+use alloy::rpc::types::TransactionReceipt;
+use anyhow::Result;
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use helios_consensus_core::calc_sync_period;
+use helios_consensus_core::consensus_spec::MainnetConsensusSpec;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tracing::info;
+
+/// Slots per sync committee period on the source chain.
+const SLOTS_PER_PERIOD: u64 = 8192;
+
+/// Bucket bounds of proof durations, in seconds.
+const PROOF_DURATION_BUCKETS: &[f64] = &[
+    30.0, 60.0, 120.0, 300.0, 600.0, 900.0, 1200.0, 1800.0, 3600.0,
+];
+/// Bucket bounds of RPC request durations, in seconds.
+const RPC_DURATION_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// The operator's metrics, shared by everything that records them.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// Serve the operator's metrics in the Prometheus text format at `GET /metrics`, until the
+/// listener fails.
+pub async fn serve(handle: OperatorHandle, addr: SocketAddr) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Serving metrics on {addr}");
+
+    axum::serve(listener, router(handle)).await?;
+
+    Ok(())
+}
+
+pub fn router(handle: OperatorHandle) -> Router {
+    Router::new()
+        .route("/metrics", get(scrape))
+        .with_state(handle)
+}
+
+async fn scrape(State(handle): State<OperatorHandle>) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics().render(&handle),
+    )
+}
+
+/// Counters, gauges and histograms of the operator's progress, proving, relays and RPCs.
+///
+/// Queue depths and cache hits are read from the [`OperatorHandle`] when rendered.
+pub struct Metrics {
+    contract_head: Family<f64>,
+    source_finalized_slot: Family<f64>,
+    source_current_slot: Family<f64>,
+    proof_duration: Histogram,
+    proof_failures: Family<f64>,
+    relay_gas_used: Family<f64>,
+    relay_cost: Family<f64>,
+    relay_failures: Family<f64>,
+    rpc_duration: Histogram,
+    rpc_errors: Family<f64>,
+    rpc_endpoint_up: Family<f64>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            contract_head: Family::new(
+                "sp1_helios_contract_head_slot",
+                "The head slot of each light client contract.",
+                &["contract"],
+            ),
+            source_finalized_slot: Family::new(
+                "sp1_helios_source_finalized_slot",
+                "The latest finalized slot of the source chain.",
+                &[],
+            ),
+            source_current_slot: Family::new(
+                "sp1_helios_source_current_slot",
+                "The current slot of the source chain.",
+                &[],
+            ),
+            proof_duration: Histogram::new(
+                "sp1_helios_proof_duration_seconds",
+                "How long successful proofs took to generate, by type.",
+                &["kind"],
+                PROOF_DURATION_BUCKETS,
+            ),
+            proof_failures: Family::new(
+                "sp1_helios_proof_failures_total",
+                "Proofs that failed to generate, by type.",
+                &["kind"],
+            ),
+            relay_gas_used: Family::new(
+                "sp1_helios_relay_gas_used_total",
+                "Gas used by relayed proofs, by type and contract.",
+                &["kind", "contract"],
+            ),
+            relay_cost: Family::new(
+                "sp1_helios_relay_cost_wei_total",
+                "Fees paid for relayed proofs in wei, by type and contract.",
+                &["kind", "contract"],
+            ),
+            relay_failures: Family::new(
+                "sp1_helios_relay_failures_total",
+                "Proofs that failed to relay, by type and contract.",
+                &["kind", "contract"],
+            ),
+            rpc_duration: Histogram::new(
+                "sp1_helios_rpc_request_duration_seconds",
+                "How long RPC requests took, by endpoint and method.",
+                &["endpoint", "method"],
+                RPC_DURATION_BUCKETS,
+            ),
+            rpc_errors: Family::new(
+                "sp1_helios_rpc_errors_total",
+                "RPC requests that failed or timed out, by endpoint and method.",
+                &["endpoint", "method"],
+            ),
+            rpc_endpoint_up: Family::new(
+                "sp1_helios_rpc_endpoint_up",
+                "Whether each RPC endpoint is healthy (1), or skipped after failing repeatedly (0).",
+                &["endpoint"],
+            ),
+        }
+    }
+}
+
+impl Metrics {
+    pub(crate) fn set_contract_head(&self, contract: Address, head: u64) {
+        self.contract_head
+            .set(&[contract.to_string().as_str()], head as f64);
+    }
+
+    pub(crate) fn set_source_finalized_slot(&self, slot: u64) {
+        self.source_finalized_slot.set(&[], slot as f64);
+    }
+
+    pub(crate) fn set_source_current_slot(&self, slot: u64) {
+        self.source_current_slot.set(&[], slot as f64);
+    }
+
+    /// Record a proof that was generated in `elapsed`, or failed.
+    pub(crate) fn observe_proof<T, E>(
+        &self,
+        kind: &str,
+        elapsed: Duration,
+        result: &std::result::Result<T, E>,
+    ) {
+        match result {
+            Ok(_) => self.proof_duration.observe(&[kind], elapsed.as_secs_f64()),
+            Err(_) => self.proof_failures.add(&[kind], 1.0),
+        }
+    }
+
+    /// Record the gas and fees of a relayed proof, or its failure.
+    pub(crate) fn observe_relay(
+        &self,
+        kind: ProofKind,
+        contract: Address,
+        result: &Result<TransactionReceipt>,
+    ) {
+        let kind = proof_kind_label(kind);
+        let contract = contract.to_string();
+        let labels = [kind, contract.as_str()];
+        match result {
+            Ok(receipt) => {
+                self.relay_gas_used.add(&labels, receipt.gas_used as f64);
+                self.relay_cost.add(
+                    &labels,
+                    receipt.gas_used as f64 * receipt.effective_gas_price as f64,
+                );
+            }
+            Err(_) => self.relay_failures.add(&labels, 1.0),
+        }
+    }
+
+    /// Record an RPC request to an endpoint that took `elapsed`, and whether it failed.
+    pub(crate) fn observe_rpc(&self, endpoint: &str, method: &str, elapsed: Duration, ok: bool) {
+        self.rpc_duration
+            .observe(&[endpoint, method], elapsed.as_secs_f64());
+        if !ok {
+            self.rpc_errors.add(&[endpoint, method], 1.0);
+        }
+    }
+
+    pub(crate) fn set_rpc_endpoint_up(&self, endpoint: &str, up: bool) {
+        self.rpc_endpoint_up
+            .set(&[endpoint], if up { 1.0 } else { 0.0 });
+    }
+
+    /// Render every metric in the Prometheus text format.
+    pub fn render(&self, handle: &OperatorHandle) -> String {
+        let mut out = String::new();
+
+        self.contract_head.render_gauge(&mut out);
+        self.source_finalized_slot.render_gauge(&mut out);
+        self.source_current_slot.render_gauge(&mut out);
+        self.render_head_margins(&mut out);
+
+        self.proof_duration.render(&mut out);
+        self.proof_failures.render_counter(&mut out);
+        self.relay_gas_used.render_counter(&mut out);
+        self.relay_cost.render_counter(&mut out);
+        self.relay_failures.render_counter(&mut out);
+        self.rpc_duration.render(&mut out);
+        self.rpc_errors.render_counter(&mut out);
+        self.rpc_endpoint_up.render_gauge(&mut out);
+
+        let depth = handle.proving_queue();
+        let queue = Family::new(
+            "sp1_helios_proving_queue_depth",
+            "Proofs being generated or waiting for a proving slot, by type and state.",
+            &["kind", "state"],
+        );
+        queue.set(&["update", "running"], depth.running_update_proofs as f64);
+        queue.set(&["update", "queued"], depth.queued_update_proofs as f64);
+        queue.set(&["storage", "running"], depth.running_storage_proofs as f64);
+        queue.set(&["storage", "queued"], depth.queued_storage_proofs as f64);
+        queue.render_gauge(&mut out);
+
+        let cache = handle.storage_proof_cache();
+        let entries = Family::new(
+            "sp1_helios_storage_proof_cache_entries",
+            "Storage proofs in the cache.",
+            &[],
+        );
+        entries.set(&[], cache.entries as f64);
+        entries.render_gauge(&mut out);
+        let lookups = Family::new(
+            "sp1_helios_storage_proof_cache_lookups_total",
+            "Storage proof cache lookups, by result.",
+            &["result"],
+        );
+        lookups.set(&["hit"], cache.hits as f64);
+        lookups.set(&["miss"], cache.misses as f64);
+        lookups.render_counter(&mut out);
+
+        out
+    }
+
+    /// Render how far each contract head trails the source chain: its lag behind the finalized
+    /// slot, and the slots left before the source chain moves two sync committee periods past it
+    /// and the contract can no longer be updated.
+    fn render_head_margins(&self, out: &mut String) {
+        let finalized = self.source_finalized_slot.get(&[]);
+        let current = self.source_current_slot.get(&[]);
+        let heads = self.contract_head.lock().clone();
+
+        let lag = Family::new(
+            "sp1_helios_contract_head_lag_slots",
+            "Slots between each contract head and the source chain's finalized slot.",
+            &["contract"],
+        );
+        let margin = Family::new(
+            "sp1_helios_sync_period_margin_slots",
+            "Slots left before the source chain moves two sync committee periods past each \
+             contract head.",
+            &["contract"],
+        );
+        for (labels, head) in heads {
+            let head = head as u64;
+            if let Some(finalized) = finalized {
+                lag.set(&[labels[0].as_str()], finalized - head as f64);
+            }
+            if let Some(current) = current {
+                let deadline =
+                    (calc_sync_period::<MainnetConsensusSpec>(head) + 2) * SLOTS_PER_PERIOD;
+                margin.set(&[labels[0].as_str()], deadline as f64 - current);
+            }
+        }
+
+        lag.render_gauge(out);
+        margin.render_gauge(out);
+    }
+}
+
+/// The label for a proof kind.
+fn proof_kind_label(kind: ProofKind) -> &'static str {
+    match kind {
+        ProofKind::Update { .. } => "update",
+        ProofKind::Storage { .. } => "storage",
+    }
+}
+
+/// A metric's values, by the values of its labels.
+struct Family<V> {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, V>>,
+}
+
+/// A histogram's observations, by the values of its labels.
+struct Histogram {
+    family: Family<HistogramValue>,
+    /// The upper bounds of the buckets, ascending.
+    bounds: &'static [f64],
+}
+
+#[derive(Default)]
+struct HistogramValue {
+    /// The number of observations in each bucket, not cumulative.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl<V> Family<V> {
+    fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<Vec<String>, V>> {
+        self.values.lock().expect("Metrics lock poisoned")
+    }
+
+    fn key(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn write_header(&self, out: &mut String, kind: &str) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {kind}", self.name);
+    }
+
+    /// Format labels as `{name="value",...}`, with `extra` appended.
+    fn format_labels(&self, values: &[String], extra: Option<(&str, &str)>) -> String {
+        let labels: Vec<String> = self
+            .labels
+            .iter()
+            .zip(values)
+            .map(|(name, value)| (*name, value.as_str()))
+            .chain(extra)
+            .map(|(name, value)| format!("{name}=\"{}\"", escape(value)))
+            .collect();
+
+        if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels.join(","))
+        }
+    }
+}
+
+impl Family<f64> {
+    fn set(&self, labels: &[&str], value: f64) {
+        self.lock().insert(Self::key(labels), value);
+    }
+
+    fn add(&self, labels: &[&str], value: f64) {
+        *self.lock().entry(Self::key(labels)).or_default() += value;
+    }
+
+    fn get(&self, labels: &[&str]) -> Option<f64> {
+        self.lock().get(&Self::key(labels)).copied()
+    }
+
+    fn render_counter(&self, out: &mut String) {
+        self.render(out, "counter");
+    }
+
+    fn render_gauge(&self, out: &mut String) {
+        self.render(out, "gauge");
+    }
+
+    fn render(&self, out: &mut String, kind: &str) {
+        self.write_header(out, kind);
+        for (labels, value) in self.lock().iter() {
+            let _ = writeln!(
+                out,
+                "{}{} {value}",
+                self.name,
+                self.format_labels(labels, None)
+            );
+        }
+    }
+}
+
+impl Histogram {
+    fn new(
+        name: &'static str,
+        help: &'static str,
+        labels: &'static [&'static str],
+        bounds: &'static [f64],
+    ) -> Self {
+        Self {
+            family: Family::new(name, help, labels),
+            bounds,
+        }
+    }
+
+    fn observe(&self, labels: &[&str], value: f64) {
+        let mut values = self.family.lock();
+        let histogram = values
+            .entry(Family::<HistogramValue>::key(labels))
+            .or_insert_with(|| HistogramValue {
+                buckets: vec![0; self.bounds.len()],
+                ..Default::default()
+            });
+
+        if let Some(i) = self.bounds.iter().position(|bound| value <= *bound) {
+            histogram.buckets[i] += 1;
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+
+    fn render(&self, out: &mut String) {
+        let family = &self.family;
+        family.write_header(out, "histogram");
+
+        for (labels, histogram) in family.lock().iter() {
+            let mut cumulative = 0;
+            for (bound, count) in self.bounds.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{} {cumulative}",
+                    family.name,
+                    family.format_labels(labels, Some(("le", &bound.to_string())))
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{} {}",
+                family.name,
+                family.format_labels(labels, Some(("le", "+Inf"))),
+                histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "{}_sum{} {}",
+                family.name,
+                family.format_labels(labels, None),
+                histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{} {}",
+                family.name,
+                family.format_labels(labels, None),
+                histogram.count
+            );
+        }
+    }
+}
+
+/// Escape a label value for the text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The scheme, host and port of an RPC URL, leaving out paths and credentials that often carry
+/// API keys.
+///
+/// URLs with a path, query or credentials get a short hash of the full URL appended instead, so
+/// that endpoints on the same host keep apart.
+pub(crate) fn endpoint_label(url: &str) -> String {
+    let Ok(parsed) = reqwest::Url::parse(url) else {
+        return "invalid".to_string();
+    };
+
+    let base = match (parsed.host_str(), parsed.port()) {
+        (Some(host), Some(port)) => format!("{}://{host}:{port}", parsed.scheme()),
+        (Some(host), None) => format!("{}://{host}", parsed.scheme()),
+        _ => parsed.scheme().to_string(),
+    };
+
+    let redacted = !matches!(parsed.path(), "" | "/")
+        || parsed.query().is_some()
+        || !parsed.username().is_empty()
+        || parsed.password().is_some();
+    if !redacted {
+        return base;
+    }
+
+    let hash = keccak256(url.as_bytes());
+    format!("{base}/#{}", alloy::primitives::hex::encode(&hash[..4]))
+}
@@ -1,69 +1,169 @@
+use crate::batch::StorageProofBatch;
+use crate::budget::{split_storage, SplitStorage, StorageCycleBudget};
+use crate::cache::{StorageProofCache, StorageProofCacheConfig};
+use crate::db::{
+    inputs_hash, OperatorDb, ProofKind, ProofRecord, Watchlist, FINISHED_PROOF_RETENTION,
+};
+use crate::destination::{Anchor, Destination, TransactionReverted, Vkeys};
+use crate::error::OperatorError;
+use crate::events::{Events, OperatorEvent};
+use crate::execution::{StorageFetchConfig, StorageFetcher};
 use crate::handle::ContractKeys;
-use crate::handle::{OperatorHandle, StorageProofRequest};
-use crate::{get_client, get_updates};
-use alloy::primitives::{Address, B256};
-use alloy::providers::{Provider, WalletProvider};
+use crate::handle::{
+    ContractStatus, IncludedUpdate, OperatorHandle, OperatorShared, PendingUpdateSlots,
+    ShutdownRequest, ShutdownSummary, StorageProofRequest, UpdateReceipt, WatchlistOwners,
+};
+use crate::inclusion::{InclusionPolicy, SlotTracker};
+use crate::jobs::{JobId, JobStatus, StorageProof, StorageProofJobs, StorageSlotsReceipt};
+use crate::metrics::metrics;
+use crate::programs::Programs;
+use crate::retry::{CircuitBreakers, RetryPolicy};
+use crate::rpc::{ConsensusEndpoints, FallbackRpc};
+use crate::scheduler::{Priority, ProvingConfig, ProvingQueue};
+use crate::transaction::{RelayConfig, SharedRelayConfig, TxSender};
+use crate::{finality, get_client, get_updates};
+use alloy::primitives::{keccak256, Address, B256};
+use alloy::providers::{DynProvider, Provider, WalletProvider};
+use alloy::rpc::types::TransactionReceipt;
 use alloy::sol_types::SolType;
 use anyhow::{Context, Result};
+use helios_consensus_core::calc_sync_period;
 use helios_consensus_core::consensus_spec::MainnetConsensusSpec;
 use helios_ethereum::consensus::Inner;
-use helios_ethereum::rpc::http_rpc::HttpRpc;
 use helios_ethereum::rpc::ConsensusRpc;
 use sp1_helios_primitives::types::{
-    ContractStorage, ProofInputs, ProofOutputs, SP1Helios, StorageSlotWithProof,
+    ContractStorage, ProofInputs, ProofOutputs, StorageProofOutputs,
 };
-use sp1_helios_primitives::verify_storage_slot_proofs;
 use sp1_sdk::env::{EnvProver, EnvProvingKey};
-use sp1_sdk::{
-    HashableKey, ProveRequest, Prover, ProverClient, ProvingKey, SP1ProofWithPublicValues, SP1Stdin,
-};
+use sp1_sdk::{ProveRequest, Prover, ProverClient, SP1ProofWithPublicValues, SP1Stdin};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tracing::{error, info};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, field, info, info_span, instrument, warn, Instrument, Span};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use tokio::sync::{mpsc, oneshot, watch, Mutex, RwLock};
+use tokio::task::{JoinHandle, JoinSet};
+
+/// An update proof, and the watched storage left out of it to prove separately.
+struct UpdateProof {
+    proof: SP1ProofWithPublicValues,
+    /// The keccak256 hash of the encoded [`ProofInputs`].
+    inputs_hash: B256,
+    follow_ups: Vec<Vec<ContractStorage>>,
+}
+
+/// The one-off storage slots included in the updates of an iteration.
+struct AttachedSlots {
+    contract_keys: Vec<ContractKeys>,
+    /// Whether any update got as far as reading the slots.
+    included: AtomicBool,
+}
+
+/// An update that landed on every destination of a group, and the watched storage left out of it
+/// to prove separately.
+struct LandedUpdate {
+    po: ProofOutputs,
+    receipts: Vec<UpdateReceipt>,
+    follow_ups: Vec<Vec<ContractStorage>>,
+}
 
-use std::collections::{HashMap, HashSet};
-use tokio::sync::{mpsc, oneshot, Mutex};
+/// How close to the end of the contract's sync committee period an update becomes urgent.
+///
+/// Once the source chain moves two periods past the contract head, the contract can no longer be
+/// updated, so updates near a period rollover are proven ahead of everything else.
+const URGENT_ROLLOVER_MARGIN_SLOTS: u64 = 1024;
 
-const LIGHTCLIENT_ELF: &[u8] = include_bytes!("../../elf/light_client");
-const STORAGE_ELF: &[u8] = include_bytes!("../../elf/storage");
+/// How often the records of proofs that finished relaying long ago are removed from the database.
+const DB_PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 pub struct SP1HeliosOperator<P> {
     client: Arc<EnvProver>,
-    provider: P,
-    lightclient_pk: Arc<EnvProvingKey>,
-    storage_slots_pk: Arc<EnvProvingKey>,
-    contract_address: Address,
+    /// Every embedded build of the programs, of which each destination's vkeys pick one.
+    programs: Programs,
+    /// The contracts kept up to date by this operator.
+    ///
+    /// Without dedicated execution RPCs, the first destination's provider is also used to read
+    /// execution state for storage proofs.
+    destinations: Vec<Destination<P>>,
+    /// Execution RPCs of the source chain, tried in order when fetching storage proofs.
+    execution_rpcs: Vec<DynProvider>,
+    /// The health of the execution RPCs, by position.
+    execution_breakers: CircuitBreakers,
+    storage_fetch_config: StorageFetchConfig,
+    /// How failed RPC requests are retried, for the consensus and execution RPCs and the
+    /// destinations.
+    retry_policy: RetryPolicy,
+    /// Limits on the storage proven in each update, with the rest proven separately.
+    storage_cycle_budget: StorageCycleBudget,
+    proving_config: ProvingConfig,
+    /// Limits how many update and storage proofs are generated at once.
+    proving_queue: Arc<ProvingQueue>,
+    storage_proof_cache: Arc<StorageProofCache>,
+    /// Where the watchlist, generated proofs and their relays are persisted, if anywhere.
+    db: Option<Arc<OperatorDb>>,
+    /// How transactions are sent, shared by every destination and changeable at runtime.
+    relay_config: SharedRelayConfig,
+    /// One sender per chain id and signer, shared by the destinations that use them, so that
+    /// their transactions take turns with the signer's nonces.
+    tx_senders: HashMap<(u64, Address), Arc<TxSender>>,
     storage_slots_to_fetch: Arc<Mutex<HashMap<Address, HashSet<B256>>>>,
+    /// Who added each storage slot added to the watchlist at runtime.
+    watchlist_owners: Arc<Mutex<WatchlistOwners>>,
+    /// Storage slots to include in the next update only.
+    pending_update_slots: Arc<Mutex<Vec<PendingUpdateSlots>>>,
+    /// The watched slot values written to the destinations, to skip unchanged ones.
+    slot_tracker: Arc<Mutex<SlotTracker>>,
+    /// The state of each destination contract, as of the last check.
+    contract_status: Arc<RwLock<HashMap<Address, ContractStatus>>>,
+    /// Broadcasts what the operator does, and tracks its status.
+    events: Events,
     source_chain_id: u64,
-    source_consensus_rpc: String,
+    source_consensus_rpc: ConsensusEndpoints,
+    /// Whether to trigger updates from the beacon node's finality events instead of polling.
+    finality_events: bool,
+    /// Held while an iteration runs, so that iterations never overlap.
+    iteration: Mutex<()>,
+    /// Whether another iteration was requested while one was running.
+    rerun: AtomicBool,
 }
 
 impl<P> SP1HeliosOperator<P>
 where
     P: Provider + WalletProvider,
 {
-    /// Fetch values and generate an 'update' proof for the SP1 Helios contract.
+    /// Fetch values and generate an 'update' proof from the given contract head.
+    #[instrument(
+        skip_all,
+        fields(head, target_slot = field::Empty, block_number = field::Empty)
+    )]
     async fn request_update(
         &self,
-        client: Inner<MainnetConsensusSpec, HttpRpc>,
-    ) -> Result<Option<SP1ProofWithPublicValues>> {
-        let contract = SP1Helios::new(self.contract_address, &self.provider);
-        let head: u64 = contract
-            .head()
-            .call()
-            .await
-            .context("Failed to get head from contract")?
-            .try_into()
-            .expect("Failed to convert head to u64, this is a bug.");
-
-        let mut stdin = SP1Stdin::new();
-
+        client: Inner<MainnetConsensusSpec, FallbackRpc>,
+        head: u64,
+        priority: Priority,
+        destinations: &[&Destination<P>],
+        pk: &EnvProvingKey,
+        attached: &AttachedSlots,
+    ) -> Result<Option<UpdateProof>> {
         // Setup client.
-        let updates = get_updates(&client).await;
-        let finality_update = client.rpc.get_finality_update().await.unwrap();
+        let (updates, finality_update) = async {
+            let updates = get_updates(&client).await?;
+            let finality_update = client.rpc.get_finality_update().await.map_err(|e| {
+                OperatorError::ConsensusRpc(anyhow::anyhow!(
+                    "error getting finality update: {}",
+                    e.to_string()
+                ))
+            })?;
+            Ok::<_, OperatorError>((updates, finality_update))
+        }
+        .instrument(info_span!("fetch_consensus_updates"))
+        .await?;
 
         // Check if contract is up to date
         let latest_block = finality_update.finalized_header().beacon().slot;
+        metrics().set_source_finalized_slot(latest_block);
+        Span::current().record("target_slot", latest_block);
         if latest_block <= head {
             info!("Contract is up to date. Nothing to update.");
             return Ok(None);
@@ -81,12 +181,23 @@ where
             .store
             .finalized_header
             .execution()
-            .expect("Failed to get (finalized) execution header from store")
+            .map_err(|_| {
+                OperatorError::Bootstrap(anyhow::anyhow!(
+                    "the finalized header at slot {head} has no execution payload"
+                ))
+            })?
             .block_number();
+        Span::current().record("block_number", *latest_execution_block_number);
+
+        attached.included.store(true, Ordering::SeqCst);
+        let extra = attached.contract_keys.clone();
 
         // Fetch the contract storage, if any.
-        let contract_storage = self
-            .get_storage_slots(*latest_execution_block_number)
+        let SplitStorage {
+            update: contract_storage,
+            follow_ups,
+        } = self
+            .get_storage_slots(*latest_execution_block_number, extra, destinations)
             .await?;
 
         // Create program inputs
@@ -100,146 +211,213 @@ where
             forks: client.config.forks.clone(),
             contract_storage,
         };
-        let encoded_proof_inputs = serde_cbor::to_vec(&inputs)?;
-        stdin.write_slice(&encoded_proof_inputs);
-
-        // Generate proof.
-        let proof = self
-            .client
-            .prove(&self.lightclient_pk, stdin)
-            .plonk()
-            .await?;
+        let (proof, inputs_hash) = self.prove_update(&inputs, head, priority, pk).await?;
 
         info!("Attempting to update to new head block: {:?}", latest_block);
-        Ok(Some(proof))
+        Ok(Some(UpdateProof {
+            proof,
+            inputs_hash,
+            follow_ups,
+        }))
     }
 
-    /// Relay an update proof to the SP1 Helios contract.
-    async fn relay_update(&self, proof: SP1ProofWithPublicValues) -> Result<()> {
-        let contract = SP1Helios::new(self.contract_address, &self.provider);
-
-        let nonce = self
-            .provider
-            .get_transaction_count(self.provider.default_signer_address())
-            .await?;
+    /// Generate an update proof from the given program inputs, returning it along with the
+    /// inputs hash.
+    #[instrument(skip_all)]
+    async fn prove_update(
+        &self,
+        inputs: &ProofInputs,
+        head: u64,
+        priority: Priority,
+        pk: &EnvProvingKey,
+    ) -> Result<(SP1ProofWithPublicValues, B256)> {
+        let encoded_proof_inputs = serde_cbor::to_vec(inputs)?;
+        let inputs_hash = keccak256(&encoded_proof_inputs);
+        let mut stdin = SP1Stdin::new();
+        stdin.write_slice(&encoded_proof_inputs);
 
-        // Wait for 3 required confirmations with a timeout of 60 seconds.
-        const NUM_CONFIRMATIONS: u64 = 3;
-        const TIMEOUT_SECONDS: u64 = 60;
+        let _permit = self.proving_queue.acquire(priority).await;
+        let kind = ProofKind::Update { head };
+        self.events.emit(OperatorEvent::ProofStarted { kind });
+        let started = Instant::now();
+        let proof = self.client.prove(pk, stdin).plonk().await;
+        metrics().observe_proof("update", started.elapsed(), &proof);
+        self.events.emit(OperatorEvent::ProofFinished {
+            kind,
+            error: proof.as_ref().err().map(|e| format!("{e:#}")),
+        });
+        let proof = proof.map_err(|e| OperatorError::Proving(e.into()))?;
 
-        let po = ProofOutputs::abi_decode(proof.public_values.as_slice())?;
+        Ok((proof, inputs_hash))
+    }
 
-        let tx = contract.update(
-            proof.bytes().into(),
-            po.newHead,
-            po.newHeader,
-            po.executionStateRoot,
-            po.executionBlockNumber,
-            po.syncCommitteeHash,
-            po.nextSyncCommitteeHash,
-            po.storageSlots,
-        );
+    /// Fetch the watched storage slots, along with `extra` ones, at the given block.
+    ///
+    /// Watched slots the inclusion policy does not call for on any of `destinations` are left out,
+    /// and those beyond the cycle budget are split off into follow-up storage proofs. `extra` slots
+    /// are always included in the update.
+    #[instrument(skip_all, fields(block_number))]
+    async fn get_storage_slots(
+        &self,
+        block_number: u64,
+        extra: Vec<ContractKeys>,
+        destinations: &[&Destination<P>],
+    ) -> Result<SplitStorage> {
+        let mut slots = self.storage_slots_to_fetch.lock().await.clone();
+        let watched: HashSet<Address> = slots.keys().copied().collect();
+        let required: HashSet<(Address, B256)> = extra
+            .iter()
+            .flat_map(|keys| {
+                keys.storage_slots
+                    .iter()
+                    .map(move |slot| (keys.address, *slot))
+            })
+            .collect();
+        for keys in extra {
+            slots
+                .entry(keys.address)
+                .or_default()
+                .extend(keys.storage_slots);
+        }
 
-        let receipt = tx
-            .nonce(nonce)
-            .send()
-            .await?
-            .with_required_confirmations(NUM_CONFIRMATIONS)
-            .with_timeout(Some(Duration::from_secs(TIMEOUT_SECONDS)))
-            .get_receipt()
-            .await?;
+        let contracts: Vec<(Address, Vec<B256>)> = slots
+            .into_iter()
+            .map(|(contract, keys)| (contract, keys.into_iter().collect()))
+            .collect();
+        if contracts.is_empty() {
+            return Ok(SplitStorage {
+                update: vec![],
+                follow_ups: vec![],
+            });
+        }
 
-        // If status is false, it reverted.
-        if !receipt.status() {
-            error!("Transaction reverted!");
-            return Err(anyhow::anyhow!("Transaction reverted!"));
+        if self.slot_tracker.lock().await.skips_unchanged() {
+            for destination in destinations {
+                self.load_written_slots(destination, &contracts).await;
+            }
         }
 
-        info!(
-            "Successfully updated to new head block! Tx hash: {:?}",
-            receipt.transaction_hash
-        );
+        let fetcher = self.storage_fetcher();
+        let state_root = fetcher
+            .state_root(block_number)
+            .await
+            .map_err(OperatorError::ExecutionRpc)?;
 
-        Ok(())
-    }
+        let mut contract_storage = fetcher
+            .contract_storage(state_root, block_number, contracts)
+            .await
+            .map_err(OperatorError::ExecutionRpc)?;
 
-    async fn get_storage_slots(&self, block_number: u64) -> Result<Vec<ContractStorage>> {
-        let storage_slots_to_fetch = self.storage_slots_to_fetch.lock().await;
-        if storage_slots_to_fetch.is_empty() {
-            return Ok(vec![]);
-        }
+        let tracker = self.slot_tracker.lock().await;
+        if tracker.skips_unchanged() {
+            let destinations: Vec<Address> =
+                destinations.iter().map(|d| d.contract_address).collect();
+            let watched_slots: usize = contract_storage
+                .iter()
+                .map(|contract| contract.storage_slots.len())
+                .sum();
 
-        let Some(block) = self.provider.get_block(block_number.into()).await? else {
-            anyhow::bail!("Failed to get block {block_number} from provider, this was expected to valid since the store claimed to have this block finalized.");
-        };
+            contract_storage.retain_mut(|contract| {
+                if contract.storage_slots.is_empty() {
+                    return true;
+                }
+                let address = contract.address;
+                contract.storage_slots.retain(|slot| {
+                    required.contains(&(address, slot.key))
+                        || tracker.needs_update(
+                            &destinations,
+                            address,
+                            slot.key,
+                            B256::from(slot.value.to_be_bytes::<32>()),
+                        )
+                });
+                !contract.storage_slots.is_empty() || !watched.contains(&address)
+            });
 
-        let futs = storage_slots_to_fetch.iter().map(|(contract, keys)| {
-            self.get_storage_slot_proof_for_contract(
-                block.header.state_root,
-                block_number,
-                *contract,
-                keys.iter().copied().collect(),
-            )
-        });
+            let included: usize = contract_storage
+                .iter()
+                .map(|contract| contract.storage_slots.len())
+                .sum();
+            info!("Including {included} of {watched_slots} storage slot(s) in the update");
+        }
+        drop(tracker);
+
+        let split = split_storage(
+            contract_storage,
+            &self.storage_cycle_budget,
+            |address, slot| required.contains(&(address, slot)),
+        );
+        if !split.follow_ups.is_empty() {
+            info!(
+                "Proving {} storage slot(s) beyond the update's cycle budget in {} follow-up proof(s)",
+                split
+                    .follow_ups
+                    .iter()
+                    .flatten()
+                    .map(|contract| contract.storage_slots.len())
+                    .sum::<usize>(),
+                split.follow_ups.len()
+            );
+        }
 
-        futures::future::try_join_all(futs).await
+        Ok(split)
     }
 
-    async fn get_storage_slot_proof_for_contract(
+    /// Record the values a destination holds for slots whose last written value is not known
+    /// yet, such as after a restart.
+    ///
+    /// Slots that cannot be read are left unknown, so they are included in the next update.
+    async fn load_written_slots(
         &self,
-        state_root: B256,
-        block_number: u64,
-        contract_address: Address,
-        keys: Vec<B256>,
-    ) -> Result<ContractStorage> {
-        let proof = self
-            .provider
-            .get_proof(contract_address, keys)
-            .number(block_number)
-            .await?;
-
-        let contract_storage = ContractStorage {
-            address: proof.address,
-            value: alloy_trie::TrieAccount {
-                nonce: proof.nonce,
-                balance: proof.balance,
-                storage_root: proof.storage_hash,
-                code_hash: proof.code_hash,
-            },
-            mpt_proof: proof.account_proof,
-            storage_slots: proof
-                .storage_proof
-                .into_iter()
-                .map(|p| StorageSlotWithProof {
-                    key: p.key.as_b256(),
-                    value: p.value,
-                    mpt_proof: p.proof,
+        destination: &Destination<P>,
+        contracts: &[(Address, Vec<B256>)],
+    ) {
+        let unknown: Vec<(Address, B256)> = {
+            let tracker = self.slot_tracker.lock().await;
+            contracts
+                .iter()
+                .flat_map(|(address, slots)| slots.iter().map(move |slot| (*address, *slot)))
+                .filter(|(address, slot)| {
+                    !tracker.is_known(destination.contract_address, *address, *slot)
                 })
-                .collect(),
+                .collect()
         };
-
-        verify_storage_slot_proofs(state_root, &contract_storage).context(format!(
-            "Preflight storage slot proofs failed to verify for contract {contract_address:?}"
-        ))?;
-
-        Ok(contract_storage)
-    }
-
-    /// Check if the vkeys of the light client and storage slot programs are correct and match the ones in the contract.
-    async fn check_vkeys(&self) -> Result<()> {
-        let contract = SP1Helios::new(self.contract_address, &self.provider);
-        let contract_lightclient_vkey = contract.lightClientVkey().call().await?;
-        let contract_storage_slot_vkey = contract.storageSlotVkey().call().await?;
-
-        if self.lightclient_pk.verifying_key().bytes32_raw() != contract_lightclient_vkey {
-            return Err(anyhow::anyhow!("Light client vkey mismatch"));
+        if unknown.is_empty() {
+            return;
         }
 
-        if self.storage_slots_pk.verifying_key().bytes32_raw() != contract_storage_slot_vkey {
-            return Err(anyhow::anyhow!("Storage slot vkey mismatch"));
+        match destination.written_storage_slots(&unknown).await {
+            Ok((block_number, written)) => self.slot_tracker.lock().await.record(
+                destination.contract_address,
+                block_number,
+                written,
+            ),
+            Err(e) => error!(
+                "Failed to read written storage slots of {:?}: {:?}",
+                destination.contract_address, e
+            ),
         }
+    }
 
-        Ok(())
+    /// A fetcher over the execution RPCs of the source chain.
+    ///
+    /// Without dedicated execution RPCs, the first destination's provider is used.
+    fn storage_fetcher(&self) -> StorageFetcher<'_> {
+        let providers: Vec<&dyn Provider> = if self.execution_rpcs.is_empty() {
+            vec![&self.destinations[0].provider]
+        } else {
+            self.execution_rpcs
+                .iter()
+                .map(|provider| provider as &dyn Provider)
+                .collect()
+        };
+
+        StorageFetcher::new(
+            providers,
+            &self.storage_fetch_config,
+            &self.retry_policy,
+            &self.execution_breakers,
+        )
     }
 }
 
@@ -248,113 +426,1331 @@ where
     P: Provider + WalletProvider,
 {
     /// Create a new SP1 Helios operator.
+    ///
+    /// `consensus_rpc` is either a single beacon node URL or a set of [`ConsensusEndpoints`].
+    ///
+    /// Fails if a program cannot be set up, or if the contract expects a vkey of a program build
+    /// the operator does not embed.
     pub async fn new(
         provider: P,
         contract_address: Address,
-        consensus_rpc: String,
+        consensus_rpc: impl Into<ConsensusEndpoints>,
         chain_id: u64,
-    ) -> Self {
+    ) -> Result<Self, OperatorError> {
+        let consensus_rpc: ConsensusEndpoints = consensus_rpc.into();
         let client = ProverClient::from_env().await;
 
-        tracing::info!("Setting up light client program...");
-        let lightclient_pk = client
-            .setup(LIGHTCLIENT_ELF.into())
-            .await
-            .expect("Failed to setup light client program");
-        tracing::info!("Setting up storage slots program...");
-        let storage_slots_pk = client
-            .setup(STORAGE_ELF.into())
-            .await
-            .expect("Failed to setup storage slots program");
+        let programs = Programs::setup(&client).await?;
+
+        let relay_config = SharedRelayConfig::default();
+        let proving_config = ProvingConfig::default();
+        let destination = Destination::new(provider, contract_address, RelayConfig::default());
 
-        let this = Self {
+        Self {
             client: Arc::new(client),
-            provider,
-            lightclient_pk: Arc::new(lightclient_pk),
-            storage_slots_pk: Arc::new(storage_slots_pk),
-            contract_address,
+            programs,
+            destinations: vec![],
+            execution_rpcs: vec![],
+            execution_breakers: CircuitBreakers::default(),
+            storage_fetch_config: StorageFetchConfig::default(),
+            retry_policy: consensus_rpc.retry,
+            storage_cycle_budget: StorageCycleBudget::default(),
+            proving_queue: ProvingQueue::new(proving_config.max_concurrent_proofs),
+            proving_config,
+            storage_proof_cache: Arc::new(
+                StorageProofCache::new(StorageProofCacheConfig::default())
+                    .expect("An in-memory cache cannot fail to load"),
+            ),
+            db: None,
+            relay_config,
+            tx_senders: HashMap::new(),
             storage_slots_to_fetch: Arc::new(Mutex::new(HashMap::new())),
+            watchlist_owners: Arc::new(Mutex::new(HashMap::new())),
+            pending_update_slots: Arc::new(Mutex::new(Vec::new())),
+            slot_tracker: Arc::new(Mutex::new(SlotTracker::default())),
+            contract_status: Arc::new(RwLock::new(HashMap::new())),
+            events: Events::default(),
             source_chain_id: chain_id,
             source_consensus_rpc: consensus_rpc,
-        };
+            finality_events: false,
+            iteration: Mutex::new(()),
+            rerun: AtomicBool::new(false),
+        }
+        .with_checked_destination(destination)
+        .await
+    }
 
-        this.check_vkeys()
+    /// Add another `SP1Helios` contract, possibly on another chain, to keep up to date.
+    ///
+    /// The contract must track the same source chain, and expect vkeys of program builds the
+    /// operator embeds. It need not use the same vkeys as the other destinations.
+    pub async fn with_destination(
+        self,
+        provider: P,
+        contract_address: Address,
+    ) -> Result<Self, OperatorError> {
+        let destination = Destination::new(provider, contract_address, RelayConfig::default());
+        self.with_checked_destination(destination).await
+    }
+
+    async fn with_checked_destination(
+        mut self,
+        mut destination: Destination<P>,
+    ) -> Result<Self, OperatorError> {
+        let vkeys = destination.watch_vkeys().await?;
+        self.programs
+            .light_client
+            .require(destination.contract_address, vkeys.light_client)?;
+        self.programs
+            .storage_slots
+            .require(destination.contract_address, vkeys.storage_slots)?;
+
+        let chain_id = destination
+            .provider
+            .get_chain_id()
             .await
-            .expect("Failed to create operator: vkeys mismatch");
+            .context("Failed to get chain id of destination")
+            .map_err(OperatorError::ContractState)?;
+        let signer = destination.provider.default_signer_address();
+        destination.tx_sender = self
+            .tx_senders
+            .entry((chain_id, signer))
+            .or_insert_with(|| Arc::new(TxSender::with_shared_config(self.relay_config.clone())))
+            .clone();
+        destination.events = self.events.clone();
+        destination.retry = self.retry_policy;
+        self.destinations.push(destination);
+        Ok(self)
+    }
+
+    /// Set how update transactions are priced, replaced and confirmed, for every destination.
+    pub fn with_relay_config(self, config: RelayConfig) -> Self {
+        *self
+            .relay_config
+            .write()
+            .expect("Relay config lock poisoned") = config;
+        self
+    }
+
+    /// Read storage proofs from the given execution RPCs of the source chain, in order of
+    /// preference, instead of the first destination's provider.
+    pub fn with_execution_rpcs(mut self, providers: Vec<DynProvider>) -> Self {
+        self.execution_rpcs = providers;
+        self.execution_breakers = CircuitBreakers::default();
+        self
+    }
+
+    /// Set how storage proof requests are split up and how many run concurrently.
+    pub fn with_storage_fetch_config(mut self, config: StorageFetchConfig) -> Self {
+        self.storage_fetch_config = config;
+        self
+    }
+
+    /// Limit the storage proven in each update, proving watched slots beyond the budget in
+    /// follow-up storage proofs against the update's execution state root.
+    pub fn with_storage_cycle_budget(mut self, budget: StorageCycleBudget) -> Self {
+        self.storage_cycle_budget = budget;
+        self
+    }
 
-        this
+    /// Set how failed requests to the consensus and execution RPCs and the destinations are
+    /// retried, and when an RPC endpoint is skipped as unhealthy.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self.source_consensus_rpc.retry = policy;
+        for destination in &mut self.destinations {
+            destination.retry = policy;
+        }
+        self
+    }
+
+    /// Set how many proofs are generated at once and how many storage proof requests may queue.
+    pub fn with_proving_config(mut self, config: ProvingConfig) -> Self {
+        self.proving_queue = ProvingQueue::new(config.max_concurrent_proofs);
+        self.proving_config = config;
+        self
+    }
+
+    /// Serve storage proof requests from the given cache, and cache the proofs generated.
+    pub fn with_storage_proof_cache(mut self, cache: StorageProofCache) -> Self {
+        self.storage_proof_cache = Arc::new(cache);
+        self
+    }
+
+    /// Persist the watchlist, generated proofs and their relays to the given database, starting
+    /// from the watchlist, its owners and the storage slots' maximum staleness stored in it.
+    ///
+    /// Proofs that had not landed on every destination before a restart are relayed again when the
+    /// operator starts.
+    pub fn with_db(mut self, db: OperatorDb) -> Result<Self> {
+        let watchlist = db.watchlist()?;
+        let mut slot_tracker = self
+            .slot_tracker
+            .try_lock()
+            .expect("The slot tracker is only locked once the operator runs");
+        for ((address, slot), max_staleness) in watchlist.max_staleness {
+            slot_tracker.set_max_staleness(address, slot, Some(max_staleness));
+        }
+        drop(slot_tracker);
+
+        self.storage_slots_to_fetch = Arc::new(Mutex::new(watchlist.storage_slots));
+        self.watchlist_owners = Arc::new(Mutex::new(watchlist.owners));
+        self.db = Some(Arc::new(db));
+        Ok(self)
+    }
+
+    /// Prove the given storage slots with every update from the first run on, replacing the
+    /// watchlist restored from the database, if any, but for the slots added at runtime.
+    pub fn with_storage_slots(
+        mut self,
+        mut storage_slots: HashMap<Address, HashSet<B256>>,
+    ) -> Result<Self> {
+        for (address, slot) in self
+            .watchlist_owners
+            .try_lock()
+            .expect("Watchlist owners are only locked once the operator runs")
+            .keys()
+        {
+            storage_slots
+                .entry(*address)
+                .or_insert_with(HashSet::new)
+                .insert(*slot);
+        }
+        if let Some(db) = &self.db {
+            let owners = self
+                .watchlist_owners
+                .try_lock()
+                .expect("Watchlist owners are only locked once the operator runs")
+                .clone();
+            let mut max_staleness = self
+                .slot_tracker
+                .try_lock()
+                .expect("The slot tracker is only locked once the operator runs")
+                .max_staleness()
+                .clone();
+            max_staleness.retain(|(address, slot), _| {
+                storage_slots
+                    .get(address)
+                    .is_some_and(|slots| slots.contains(slot))
+            });
+            db.save_watchlist(&Watchlist {
+                storage_slots: storage_slots.clone(),
+                owners,
+                max_staleness,
+            })?;
+        }
+        self.storage_slots_to_fetch = Arc::new(Mutex::new(storage_slots));
+        Ok(self)
+    }
+
+    /// Set which watched storage slots are included in each update.
+    ///
+    /// The storage slots' maximum staleness restored from the database, if any, is kept.
+    pub fn with_inclusion_policy(self, policy: InclusionPolicy) -> Self {
+        self.slot_tracker
+            .try_lock()
+            .expect("The slot tracker is only locked once the operator runs")
+            .set_policy(policy);
+        self
+    }
+
+    /// Trigger updates as soon as the beacon node reports a new finalized checkpoint.
+    ///
+    /// The operator still polls on its loop delay, in case the event stream silently stops
+    /// delivering events.
+    pub fn with_finality_events(mut self, enabled: bool) -> Self {
+        self.finality_events = enabled;
+        self
     }
 
     /// Run a single iteration of the operator, possibly posting a new update on chain.
+    ///
+    /// Destinations are grouped by their current anchor and vkeys, so that one proof is generated
+    /// per group and relayed to every destination in it.
+    #[instrument(skip_all)]
     pub async fn run_once(&self) -> Result<()> {
-        let contract = SP1Helios::new(self.contract_address, &self.provider);
+        self.sync_vkeys().await;
 
-        // Get the current slot from the contract
-        let slot = contract
-            .head()
-            .call()
-            .await
-            .context("Failed to get head from contract")?
-            .try_into()
-            .expect("Failed to convert head to u64, this is a bug.");
-
-        // Fetch the checkpoint at that slot
-        let client =
-            get_client(Some(slot), &self.source_consensus_rpc, self.source_chain_id).await?;
-
-        assert_eq!(
-            client.store.finalized_header.beacon().slot,
-            slot,
-            "Bootstrapped client has mismatched finalized slot, this is a bug!"
-        );
+        let anchors = match futures::future::try_join_all(self.destinations.iter().map(|d| async {
+            d.anchor().await.map_err(|e| {
+                OperatorError::ContractState(e.context(format!("{:?}", d.contract_address)))
+            })
+        }))
+        .await
+        {
+            Ok(anchors) => anchors,
+            Err(e) => {
+                let e = anyhow::Error::from(e);
+                self.events
+                    .error(format!("Failed to read the destinations: {e:#}"));
+                return Err(e);
+            }
+        };
 
-        // Request an update
-        match self.request_update(client).await {
-            Ok(Some(proof)) => {
-                self.relay_update(proof).await?;
+        let checked_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut contract_status = self.contract_status.write().await;
+        for (anchor, destination) in anchors.iter().zip(&self.destinations) {
+            metrics().set_contract_head(destination.contract_address, anchor.head);
+            // Updates the operator relays are recorded as they land, so any other change to the
+            // head came from someone else.
+            if let Some(previous) = contract_status.get(&destination.contract_address) {
+                if previous.head != anchor.head {
+                    self.events.emit(OperatorEvent::ContractUpdatedExternally {
+                        contract_address: destination.contract_address,
+                        previous_head: previous.head,
+                        head: anchor.head,
+                    });
+                }
+            }
+            contract_status.insert(
+                destination.contract_address,
+                ContractStatus {
+                    contract_address: destination.contract_address,
+                    head: anchor.head,
+                    header: anchor.header,
+                    checked_at,
+                },
+            );
+        }
+        drop(contract_status);
+
+        let mut groups: Vec<((Anchor, Vkeys), Vec<&Destination<P>>)> = Vec::new();
+        for (anchor, destination) in anchors.into_iter().zip(&self.destinations) {
+            let key = (anchor, destination.vkeys());
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, group)) => group.push(destination),
+                None => groups.push((key, vec![destination])),
             }
+        }
+
+        // One-off storage slots ride along with the update of every group, and are answered once
+        // the updates land on every destination.
+        let pending = std::mem::take(&mut *self.pending_update_slots.lock().await);
+        let attached = AttachedSlots {
+            contract_keys: pending
+                .iter()
+                .flat_map(|pending| pending.contract_keys.iter().cloned())
+                .collect(),
+            included: AtomicBool::new(false),
+        };
+
+        let results = futures::future::join_all(groups.iter().map(|((anchor, vkeys), group)| {
+            self.update_destinations(*anchor, *vkeys, group, &attached)
+        }))
+        .await;
+
+        let mut errors = Vec::new();
+        let mut landed = Vec::new();
+        for (result, ((_, vkeys), group)) in results.into_iter().zip(&groups) {
+            match result {
+                Ok(Some(update)) => landed.push((update, vkeys.storage_slots, group)),
+                Ok(None) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+        self.answer_attached(pending, &attached, &errors, &landed)
+            .await;
+
+        let follow_ups = futures::future::join_all(landed.into_iter().map(
+            |(update, storage_vkey, group)| async move {
+                if update.follow_ups.is_empty() {
+                    return Ok(());
+                }
+                self.prove_follow_ups(&update.po, update.follow_ups, group, storage_vkey)
+                    .await
+            },
+        ))
+        .await;
+        errors.extend(follow_ups.into_iter().filter_map(Result::err));
+
+        let failed = errors.len();
+        let mut errors = errors.into_iter();
+        let Some(first) = errors.next() else {
+            return Ok(());
+        };
+        for e in errors {
+            error!("Update failed: {:?}", e);
+        }
+
+        // The first failure is kept as the cause, so it can be classified.
+        Err(first.context(format!("{failed} of {} update(s) failed", groups.len())))
+    }
+
+    /// Answer the requests to include storage slots in this iteration's updates, once every
+    /// group's update landed or one failed.
+    ///
+    /// If no update got as far as reading the slots, the requests wait for the next iteration.
+    async fn answer_attached(
+        &self,
+        pending: Vec<PendingUpdateSlots>,
+        attached: &AttachedSlots,
+        errors: &[anyhow::Error],
+        landed: &[(LandedUpdate, B256, &Vec<&Destination<P>>)],
+    ) {
+        if pending.is_empty() {
+            return;
+        }
+
+        let first = landed.first().map(|(update, ..)| &update.po);
+        match (errors.first(), first) {
+            (Some(e), _) if attached.included.load(Ordering::SeqCst) => {
+                notify_attached(pending, Err(anyhow::anyhow!("{e:#}")))
+            }
+            (None, Some(po)) => notify_attached(
+                pending,
+                Ok(IncludedUpdate {
+                    head: po.newHead.to(),
+                    execution_block_number: po.executionBlockNumber.to(),
+                    receipts: landed
+                        .iter()
+                        .flat_map(|(update, ..)| update.receipts.iter().cloned())
+                        .collect(),
+                }),
+            ),
+            _ => self.pending_update_slots.lock().await.extend(pending),
+        }
+    }
+
+    /// Run an iteration, or if one is already running, another one once it is done.
+    ///
+    /// Overlapping iterations would prove and relay the same update from the same anchors twice,
+    /// so ticks and finality events arriving while a proof is generated are coalesced into one
+    /// follow-up iteration.
+    ///
+    /// Returns whether the last iteration run failed with an error that retrying may fix.
+    async fn run_iteration(&self) -> bool {
+        self.rerun.store(true, Ordering::SeqCst);
+        let mut retryable = false;
+        loop {
+            let Ok(running) = self.iteration.try_lock() else {
+                return retryable;
+            };
+            while self.rerun.swap(false, Ordering::SeqCst) {
+                retryable = match self.run_once().await {
+                    Ok(()) => false,
+                    Err(e) => {
+                        log_run_error(&e);
+                        OperatorError::find(&e).is_none_or(OperatorError::is_retryable)
+                    }
+                };
+            }
+            drop(running);
+
+            // A request may have come in after the last check, but before the lock was released.
+            if !self.rerun.load(Ordering::SeqCst) {
+                return retryable;
+            }
+        }
+    }
+
+    /// Run an iteration for a finality event, retrying it with backoff if it fails rather than
+    /// leaving the update to the next event.
+    async fn run_event_iteration(&self) {
+        for retry in 0..self.retry_policy.max_retries {
+            if !self.run_iteration().await {
+                return;
+            }
+            let delay = self.retry_policy.backoff(retry);
+            info!("Retrying the update for the finalized checkpoint in {delay:?}");
+            tokio::time::sleep(delay).await;
+        }
+        self.run_iteration().await;
+    }
+
+    /// Prove an update from the given anchor, along with the attached storage slots, and relay it
+    /// to every destination at that anchor.
+    ///
+    /// Returns `None` if the destinations are up to date.
+    #[instrument(skip_all, fields(head = anchor.head, destinations = destinations.len()))]
+    async fn update_destinations(
+        &self,
+        anchor: Anchor,
+        vkeys: Vkeys,
+        destinations: &[&Destination<P>],
+        attached: &AttachedSlots,
+    ) -> Result<Option<LandedUpdate>> {
+        // The destinations share their vkeys, so one build of each program proves for all of them.
+        let program = self
+            .programs
+            .light_client
+            .require(destinations[0].contract_address, vkeys.light_client)?;
+
+        // Fetch the checkpoint at the contract head
+        let client = get_client(
+            Some(anchor.head),
+            &self.source_consensus_rpc,
+            self.source_chain_id,
+        )
+        .await?;
+
+        let bootstrapped_slot = client.store.finalized_header.beacon().slot;
+        if bootstrapped_slot != anchor.head {
+            return Err(OperatorError::Bootstrap(anyhow::anyhow!(
+                "bootstrapped to slot {bootstrapped_slot} instead of the contract head {}",
+                anchor.head
+            ))
+            .into());
+        }
+
+        metrics().set_source_current_slot(client.expected_current_slot());
+
+        let priority = if is_rollover_near(anchor.head, client.expected_current_slot()) {
+            Priority::UrgentUpdate
+        } else {
+            Priority::Update
+        };
+
+        // Request an update
+        let UpdateProof {
+            proof,
+            inputs_hash,
+            follow_ups,
+        } = match self
+            .request_update(
+                client,
+                anchor.head,
+                priority,
+                destinations,
+                &program.pk,
+                attached,
+            )
+            .await
+        {
+            Ok(Some(proof)) => proof,
             Ok(None) => {
-                // Contract is up to date. Nothing to update.
+                // Contracts are up to date. Nothing to update.
+                return Ok(None);
             }
             Err(e) => {
-                error!("Header range request failed: {}", e);
+                self.events
+                    .error(format!("Update from head {} failed: {e:#}", anchor.head));
+                return Err(e.context(format!("Update from head {} failed", anchor.head)));
+            }
+        };
+
+        let record = self
+            .record_proof(
+                ProofKind::Update { head: anchor.head },
+                &proof,
+                Some(inputs_hash),
+                destinations,
+                &follow_ups,
+            )
+            .await;
+
+        let po = ProofOutputs::abi_decode(proof.public_values.as_slice())?;
+        // Each destination is recorded as the update lands on it, so that a failure on another
+        // does not hide it.
+        let results = futures::future::join_all(destinations.iter().map(|destination| async {
+            let result = destination.relay_update(&proof).await;
+            self.record_relay(
+                record,
+                ProofKind::Update { head: anchor.head },
+                destination.contract_address,
+                &result,
+            )
+            .await;
+            if result.is_ok() {
+                self.record_update(destination.contract_address, anchor.head, &po)
+                    .await;
+            }
+            result
+        }))
+        .await;
+
+        let mut receipts = Vec::new();
+        let mut failures = Vec::new();
+        for (destination, result) in destinations.iter().zip(results) {
+            match result {
+                Ok(receipt) => receipts.push(UpdateReceipt {
+                    contract_address: destination.contract_address,
+                    receipt,
+                }),
+                Err(e) => {
+                    error!(
+                        "Failed to relay update to {:?}: {:?}",
+                        destination.contract_address, e
+                    );
+                    failures.push(format!("{:?}: {e:#}", destination.contract_address));
+                }
             }
         }
 
+        if !failures.is_empty() {
+            // The next update is proven afresh from the destinations' new state.
+            self.abandon_record(record).await;
+            return Err(OperatorError::Relay(anyhow::anyhow!(
+                "Failed to relay update from head {} to {} of {} destination(s): {}",
+                anchor.head,
+                failures.len(),
+                destinations.len(),
+                failures.join("; ")
+            ))
+            .into());
+        }
+
+        Ok(Some(LandedUpdate {
+            po,
+            receipts,
+            follow_ups,
+        }))
+    }
+
+    /// Record an update that landed on a destination: its new head, the storage slot values it
+    /// wrote, and an [`OperatorEvent::Updated`].
+    async fn record_update(
+        &self,
+        contract_address: Address,
+        previous_head: u64,
+        po: &ProofOutputs,
+    ) {
+        self.set_contract_head(contract_address, po).await;
+        self.slot_tracker.lock().await.record(
+            contract_address,
+            po.executionBlockNumber.to(),
+            po.storageSlots
+                .iter()
+                .map(|slot| (slot.contractAddress, slot.key, slot.value)),
+        );
+        self.events.emit(OperatorEvent::Updated {
+            previous_head,
+            head: po.newHead.to(),
+            execution_block_number: po.executionBlockNumber.to(),
+            contract_addresses: vec![contract_address],
+        });
+    }
+
+    /// Prove the watched storage left out of an update against its execution state root, and
+    /// relay it to the destinations the update landed on.
+    #[instrument(
+        skip_all,
+        fields(block_number = %po.executionBlockNumber, proofs = follow_ups.len())
+    )]
+    async fn prove_follow_ups(
+        &self,
+        po: &ProofOutputs,
+        follow_ups: Vec<Vec<ContractStorage>>,
+        destinations: &[&Destination<P>],
+        storage_vkey: B256,
+    ) -> Result<()> {
+        let block_number: u64 = po.executionBlockNumber.to();
+        let total = follow_ups.len();
+
+        let results =
+            futures::future::join_all(follow_ups.into_iter().map(|contracts| async move {
+                let (stdin, inputs_hash) = storage_stdin(&contracts, po.executionStateRoot)?;
+                let proof = {
+                    let _permit = self.proving_queue.acquire(Priority::Update).await;
+                    self.prove_storage_stdin(
+                        stdin,
+                        block_number,
+                        destinations[0].contract_address,
+                        storage_vkey,
+                    )
+                    .await?
+                };
+
+                self.relay_storage_proof_to(destinations, block_number, &proof, Some(inputs_hash))
+                    .await?;
+
+                let outputs = StorageProofOutputs::abi_decode(proof.public_values.as_slice())?;
+                let mut tracker = self.slot_tracker.lock().await;
+                for destination in destinations {
+                    tracker.record(
+                        destination.contract_address,
+                        block_number,
+                        outputs
+                            .storageSlots
+                            .iter()
+                            .map(|slot| (slot.contractAddress, slot.key, slot.value)),
+                    );
+                }
+
+                Ok::<_, anyhow::Error>(())
+            }))
+            .await;
+
+        let failures: Vec<String> = results
+            .into_iter()
+            .filter_map(|result| result.err())
+            .map(|e| format!("{e:#}"))
+            .collect();
+        if !failures.is_empty() {
+            anyhow::bail!(
+                "{} of {total} follow-up storage proof(s) for block {block_number} failed: {}",
+                failures.len(),
+                failures.join("; ")
+            );
+        }
+
         Ok(())
     }
 
-    pub async fn prove_storage_slots(
+    /// Prove a batch of storage proof jobs together, recording their progress as it goes.
+    #[instrument(
+        name = "prove_storage_slots",
+        skip_all,
+        fields(block_number = batch.block_number, jobs = ?batch.jobs())
+    )]
+    async fn run_storage_proof_batch(&self, jobs: &StorageProofJobs, batch: StorageProofBatch) {
+        let ids = batch.jobs();
+        let set_status = |status: JobStatus| {
+            for id in &ids {
+                jobs.set_status(*id, status.clone());
+            }
+        };
+
+        let primary = self.storage_proof_destination();
+        let storage_vkey = primary.vkeys().storage_slots;
+
+        set_status(JobStatus::Fetching);
+        let result = async {
+            let (stdin, inputs_hash) = self
+                .storage_slots_stdin(batch.block_number, batch.contract_keys())
+                .await?;
+            set_status(JobStatus::Proving);
+            let proof = self
+                .prove_storage_stdin(
+                    stdin,
+                    batch.block_number,
+                    primary.contract_address,
+                    storage_vkey,
+                )
+                .await?;
+            Ok::<_, anyhow::Error>((proof, inputs_hash))
+        }
+        .await;
+
+        match result {
+            Ok((proof, inputs_hash)) => {
+                self.storage_proof_cache
+                    .insert(
+                        batch.block_number,
+                        storage_vkey,
+                        batch.contract_keys(),
+                        proof.clone(),
+                    )
+                    .await;
+
+                // Hand out the proof to the jobs that only asked for it first, then relay it once
+                // for the others.
+                let mut relayed = Vec::new();
+                for (id, slot_indices) in batch.slot_indices() {
+                    let proof = StorageProof {
+                        proof: proof.clone(),
+                        slot_indices,
+                        receipts: vec![],
+                    };
+                    if batch.relays(id) {
+                        relayed.push((id, proof));
+                    } else {
+                        jobs.finish(id, Ok(proof));
+                    }
+                }
+
+                if !relayed.is_empty() {
+                    self.relay_and_finish(
+                        jobs,
+                        batch.block_number,
+                        batch.contract_keys(),
+                        relayed,
+                        (storage_vkey, Some(inputs_hash)),
+                    )
+                    .await;
+                }
+            }
+            Err(e) => {
+                error!("Error proving storage slots for jobs {ids:?}: {:?}", e);
+                for id in ids {
+                    jobs.finish(id, Err(anyhow::anyhow!("{e:#}")));
+                }
+            }
+        }
+    }
+
+    /// Relay a storage proof to every destination, then finish the jobs waiting on it with the
+    /// receipts.
+    ///
+    /// The proof, of `contract_keys`, was generated with the storage slots program of `proven`'s
+    /// vkey. Destinations expecting another build get a proof of their own.
+    async fn relay_and_finish(
         &self,
+        jobs: &StorageProofJobs,
         block_number: u64,
         contract_keys: Vec<ContractKeys>,
-    ) -> Result<SP1ProofWithPublicValues> {
-        let Some(block) = self.provider.get_block(block_number.into()).await? else {
-            anyhow::bail!("Failed to get block {block_number} from provider, this was expected to valid since the store claimed to have this block finalized.");
+        relayed: Vec<(JobId, StorageProof)>,
+        proven: (B256, Option<B256>),
+    ) {
+        for (id, _) in &relayed {
+            jobs.set_status(*id, JobStatus::Relaying);
+        }
+
+        let (storage_vkey, inputs_hash) = proven;
+        let proof = &relayed[0].1.proof;
+        let contract_keys = &contract_keys;
+        let groups = self.storage_vkey_groups();
+        let total = groups.len();
+        let results = futures::future::join_all(groups.into_iter().map(|group| async move {
+            if group[0].vkeys().storage_slots == storage_vkey {
+                return self
+                    .relay_storage_proof_to(&group, block_number, proof, inputs_hash)
+                    .await;
+            }
+
+            let (proof, inputs_hash) = self
+                .storage_proof_for(block_number, contract_keys.clone(), group[0])
+                .await?;
+            self.relay_storage_proof_to(&group, block_number, &proof, inputs_hash)
+                .await
+        }))
+        .await;
+
+        let mut receipts = Vec::new();
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok(group_receipts) => receipts.extend(group_receipts),
+                Err(e) => errors.push(e),
+            }
+        }
+        let failed = errors.len();
+        let mut errors = errors.into_iter();
+        let result = match errors.next() {
+            None => Ok(receipts),
+            Some(first) => {
+                for e in errors {
+                    error!(
+                        "Failed to relay storage proof for block {block_number}: {:?}",
+                        e
+                    );
+                }
+                Err(first.context(format!(
+                    "{failed} of {total} storage slots program(s) failed"
+                )))
+            }
         };
 
-        let proofs = contract_keys.into_iter().map(|keys| {
-            self.get_storage_slot_proof_for_contract(
-                block.header.state_root,
+        match result {
+            Ok(receipts) => {
+                for (id, proof) in relayed {
+                    jobs.finish(
+                        id,
+                        Ok(StorageProof {
+                            receipts: receipts.clone(),
+                            ..proof
+                        }),
+                    );
+                }
+            }
+            Err(e) => {
+                error!(
+                    "Failed to relay storage proof for block {block_number}: {:?}",
+                    e
+                );
+                for (id, _) in relayed {
+                    jobs.finish(id, Err(anyhow::anyhow!("{e:#}")));
+                }
+            }
+        }
+    }
+
+    /// Relay a storage proof to every destination with `updateStorageSlot`.
+    pub async fn relay_storage_proof(
+        &self,
+        block_number: u64,
+        proof: &SP1ProofWithPublicValues,
+    ) -> Result<Vec<StorageSlotsReceipt>> {
+        let destinations: Vec<&Destination<P>> = self.destinations.iter().collect();
+        self.relay_storage_proof_to(&destinations, block_number, proof, None)
+            .await
+    }
+
+    /// Relay a storage proof to the given destinations, recording it and its relays in the
+    /// database.
+    #[instrument(skip_all, fields(block_number))]
+    async fn relay_storage_proof_to(
+        &self,
+        destinations: &[&Destination<P>],
+        block_number: u64,
+        proof: &SP1ProofWithPublicValues,
+        inputs_hash: Option<B256>,
+    ) -> Result<Vec<StorageSlotsReceipt>> {
+        let record = self
+            .record_proof(
+                ProofKind::Storage { block_number },
+                proof,
+                inputs_hash,
+                destinations,
+                &[],
+            )
+            .await;
+
+        let results = futures::future::join_all(
+            destinations
+                .iter()
+                .map(|d| d.relay_storage_proof(block_number, proof)),
+        )
+        .await;
+
+        let mut receipts = Vec::new();
+        let mut failures = Vec::new();
+        for (destination, result) in destinations.iter().zip(results) {
+            self.record_relay(
+                record,
+                ProofKind::Storage { block_number },
+                destination.contract_address,
+                &result,
+            )
+            .await;
+            match result {
+                Ok(receipt) => receipts.push(StorageSlotsReceipt {
+                    contract_address: destination.contract_address,
+                    receipt,
+                }),
+                Err(e) => failures.push(format!("{:?}: {e:#}", destination.contract_address)),
+            }
+        }
+        if !failures.is_empty() {
+            self.abandon_record(record).await;
+            return Err(OperatorError::Relay(anyhow::anyhow!(
+                "Failed to relay storage proof to {} of {} destination(s): {}",
+                failures.len(),
+                destinations.len(),
+                failures.join("; ")
+            ))
+            .into());
+        }
+
+        Ok(receipts)
+    }
+
+    /// Fetch the storage proofs of the given contracts and write them as program inputs.
+    #[instrument(skip_all, fields(block_number))]
+    async fn storage_slots_stdin(
+        &self,
+        block_number: u64,
+        contract_keys: Vec<ContractKeys>,
+    ) -> Result<(SP1Stdin, B256)> {
+        let fetcher = self.storage_fetcher();
+        let state_root = fetcher
+            .state_root(block_number)
+            .await
+            .map_err(OperatorError::ExecutionRpc)?;
+
+        let proofs = fetcher
+            .contract_storage(
+                state_root,
                 block_number,
-                keys.address,
-                keys.storage_slots,
+                contract_keys
+                    .into_iter()
+                    .map(|keys| (keys.address, keys.storage_slots))
+                    .collect(),
             )
+            .await
+            .map_err(OperatorError::ExecutionRpc)?;
+
+        storage_stdin(&proofs, state_root)
+    }
+
+    /// Prove storage slots with the build of the storage slots program that `contract_address`
+    /// expects, by its `storage_vkey`.
+    #[instrument(skip_all, fields(block_number))]
+    async fn prove_storage_stdin(
+        &self,
+        stdin: SP1Stdin,
+        block_number: u64,
+        contract_address: Address,
+        storage_vkey: B256,
+    ) -> Result<SP1ProofWithPublicValues> {
+        let program = self
+            .programs
+            .storage_slots
+            .require(contract_address, storage_vkey)?;
+
+        let kind = ProofKind::Storage { block_number };
+        self.events.emit(OperatorEvent::ProofStarted { kind });
+        let started = Instant::now();
+        let proof = self.client.prove(&program.pk, stdin).plonk().await;
+        metrics().observe_proof("storage", started.elapsed(), &proof);
+        self.events.emit(OperatorEvent::ProofFinished {
+            kind,
+            error: proof.as_ref().err().map(|e| format!("{e:#}")),
         });
 
-        let proofs = futures::future::try_join_all(proofs).await?;
+        Ok(proof.map_err(|e| OperatorError::Proving(e.into()))?)
+    }
 
-        let mut stdin = SP1Stdin::new();
-        stdin.write(&proofs);
-        stdin.write(&block.header.state_root);
+    /// A storage proof of `contract_keys` with the build of the storage slots program that
+    /// `destination` expects, from the cache if there is one, along with its inputs hash if it was
+    /// generated.
+    async fn storage_proof_for(
+        &self,
+        block_number: u64,
+        contract_keys: Vec<ContractKeys>,
+        destination: &Destination<P>,
+    ) -> Result<(SP1ProofWithPublicValues, Option<B256>)> {
+        let storage_vkey = destination.vkeys().storage_slots;
+        // The request's lookup was already counted when it was first served.
+        self.storage_proof_cache
+            .find_or_prove(
+                block_number,
+                storage_vkey,
+                contract_keys,
+                |contract_keys| async move {
+                    let (stdin, inputs_hash) = self
+                        .storage_slots_stdin(block_number, contract_keys)
+                        .await?;
+                    let proof = self
+                        .prove_storage_stdin(
+                            stdin,
+                            block_number,
+                            destination.contract_address,
+                            storage_vkey,
+                        )
+                        .await?;
+                    Ok((proof, inputs_hash))
+                },
+            )
+            .await
+    }
 
-        let proof = self
-            .client
-            .prove(&self.storage_slots_pk, stdin)
-            .plonk()
-            .await?;
+    /// The destinations grouped by storage slots vkey, starting with the first destination's.
+    ///
+    /// Contracts reject storage proofs of another build than the one they expect, so each group
+    /// is relayed a proof of its own.
+    fn storage_vkey_groups(&self) -> Vec<Vec<&Destination<P>>> {
+        let mut groups: Vec<Vec<&Destination<P>>> = Vec::new();
+        for destination in &self.destinations {
+            let vkey = destination.vkeys().storage_slots;
+            match groups
+                .iter_mut()
+                .find(|group| group[0].vkeys().storage_slots == vkey)
+            {
+                Some(group) => group.push(destination),
+                None => groups.push(vec![destination]),
+            }
+        }
+        groups
+    }
 
-        Ok(proof)
+    /// The destination whose build of the storage slots program the storage proofs handed out
+    /// are for: the first, or if the operator does not embed its build, the first of another
+    /// storage vkey group it does, so that one destination on an unknown build does not fail every
+    /// storage proof request.
+    fn storage_proof_destination(&self) -> &Destination<P> {
+        self.storage_vkey_groups()
+            .into_iter()
+            .map(|group| group[0])
+            .find(|destination| {
+                self.programs
+                    .storage_slots
+                    .get(destination.vkeys().storage_slots)
+                    .is_some()
+            })
+            .unwrap_or(&self.destinations[0])
+    }
+
+    /// Follow vkey updates on the destinations, so that each is proven for with the program build
+    /// it now expects.
+    ///
+    /// A destination switching to a build the operator does not embed is reported, and its
+    /// updates fail until the operator is upgraded.
+    async fn sync_vkeys(&self) {
+        let results =
+            futures::future::join_all(self.destinations.iter().map(|d| d.vkey_updates())).await;
+
+        for (destination, result) in self.destinations.iter().zip(results) {
+            let updates = match result {
+                Ok(updates) => updates,
+                Err(e) => {
+                    warn!(
+                        "Failed to check {:?} for vkey updates: {:?}",
+                        destination.contract_address, e
+                    );
+                    continue;
+                }
+            };
+
+            for (program, vkey) in updates {
+                let version = self.programs.get(program).get(vkey).map(|v| v.version);
+                match version {
+                    Some(version) => info!(
+                        "{:?} switched to {program} vkey {vkey:?}, proving with program {version}",
+                        destination.contract_address
+                    ),
+                    None => error!(
+                        "{:?} switched to {program} vkey {vkey:?}, which none of the operator's programs have",
+                        destination.contract_address
+                    ),
+                }
+                self.events.emit(OperatorEvent::VkeyUpdated {
+                    contract_address: destination.contract_address,
+                    program,
+                    vkey,
+                    supported: version.is_some(),
+                });
+            }
+        }
+    }
+
+    /// Record the head an update relayed to a destination moved it to.
+    async fn set_contract_head(&self, contract_address: Address, po: &ProofOutputs) {
+        let head = po.newHead.to();
+        metrics().set_contract_head(contract_address, head);
+        self.contract_status.write().await.insert(
+            contract_address,
+            ContractStatus {
+                contract_address,
+                head,
+                header: po.newHeader,
+                checked_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            },
+        );
+    }
+
+    /// Remove the records of proofs that finished relaying long ago, if the operator has a
+    /// database.
+    async fn prune_db(&self) {
+        let Some(db) = &self.db else {
+            return;
+        };
+        match db.run(|db| db.prune(FINISHED_PROOF_RETENTION)).await {
+            Ok(0) => {}
+            Ok(pruned) => info!("Pruned {pruned} finished proof record(s)"),
+            Err(e) => warn!("Failed to prune finished proof records: {:?}", e),
+        }
+    }
+
+    /// Record a proof about to be relayed to `destinations`, along with an update's follow-up
+    /// storage, if the operator has a database.
+    async fn record_proof(
+        &self,
+        kind: ProofKind,
+        proof: &SP1ProofWithPublicValues,
+        inputs_hash: Option<B256>,
+        destinations: &[&Destination<P>],
+        follow_ups: &[Vec<ContractStorage>],
+    ) -> Option<u64> {
+        let db = self.db.as_ref()?;
+        let destinations = destinations.iter().map(|d| d.contract_address).collect();
+        let (proof, follow_ups) = (proof.clone(), follow_ups.to_vec());
+        match db
+            .run(move |db| db.insert_proof(kind, &proof, inputs_hash, destinations, &follow_ups))
+            .await
+        {
+            Ok(id) => Some(id),
+            Err(e) => {
+                warn!("Failed to record {kind:?} proof: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Record an attempt to relay a proof in the metrics and events, and in the database if the
+    /// proof was recorded there.
+    async fn record_relay(
+        &self,
+        record: Option<u64>,
+        kind: ProofKind,
+        contract_address: Address,
+        result: &Result<TransactionReceipt>,
+    ) {
+        metrics().observe_relay(kind, contract_address, result);
+        self.events.emit(match result {
+            Ok(receipt) => OperatorEvent::RelayConfirmed {
+                kind,
+                contract_address,
+                transaction_hash: receipt.transaction_hash,
+                block_number: receipt.block_number,
+                gas_used: receipt.gas_used,
+            },
+            Err(e) => match e.downcast_ref::<TransactionReverted>() {
+                Some(TransactionReverted(transaction_hash)) => OperatorEvent::RelayReverted {
+                    kind,
+                    contract_address,
+                    transaction_hash: *transaction_hash,
+                },
+                None => OperatorEvent::RelayFailed {
+                    kind,
+                    contract_address,
+                    error: format!("{e:#}"),
+                },
+            },
+        });
+
+        let (Some(db), Some(id)) = (&self.db, record) else {
+            return;
+        };
+        let result = match result {
+            Ok(receipt) => Ok(receipt.clone()),
+            Err(e) => Err(format!("{e:#}")),
+        };
+        if let Err(e) = db
+            .run(move |db| {
+                db.record_relay(id, contract_address, result.as_ref().map_err(String::clone))
+            })
+            .await
+        {
+            warn!("Failed to record relay of proof {id}: {:?}", e);
+        }
+    }
+
+    async fn abandon_record(&self, record: Option<u64>) {
+        let (Some(db), Some(id)) = (&self.db, record) else {
+            return;
+        };
+        if let Err(e) = db.run(move |db| db.abandon(id)).await {
+            warn!("Failed to abandon proof {id}: {:?}", e);
+        }
+    }
+
+    /// Record what a proof relayed outside of the iteration that generated it wrote to a
+    /// destination: an update's new head and storage slot values, or a storage proof's slot
+    /// values.
+    async fn record_landed(
+        &self,
+        kind: ProofKind,
+        contract_address: Address,
+        proof: &SP1ProofWithPublicValues,
+    ) -> Result<()> {
+        match kind {
+            ProofKind::Update { head } => {
+                let po = ProofOutputs::abi_decode(proof.public_values.as_slice())?;
+                self.record_update(contract_address, head, &po).await;
+            }
+            ProofKind::Storage { block_number } => {
+                let outputs = StorageProofOutputs::abi_decode(proof.public_values.as_slice())?;
+                self.slot_tracker.lock().await.record(
+                    contract_address,
+                    block_number,
+                    outputs
+                        .storageSlots
+                        .iter()
+                        .map(|slot| (slot.contractAddress, slot.key, slot.value)),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Relay the proofs recorded before a restart that had not landed on every destination yet.
+    ///
+    /// Proofs that still fail to relay, e.g. because the destinations moved past them, are
+    /// abandoned. Once an update lands on every destination, the watched storage recorded as left
+    /// out of it is proven separately, as right after it was generated. Follow-up storage proofs
+    /// already underway when the operator stopped are not restored: their slots are proven again
+    /// with a later update instead.
+    #[instrument(skip_all)]
+    async fn resume_relays(&self) {
+        let Some(db) = &self.db else {
+            return;
+        };
+
+        let records = match db.run(OperatorDb::relaying).await {
+            Ok(records) => records,
+            Err(e) => {
+                warn!("Failed to load the proofs to resume relaying: {:?}", e);
+                return;
+            }
+        };
+        for record in records {
+            let pending = record.pending_destinations();
+            let destinations: Vec<&Destination<P>> = self
+                .destinations
+                .iter()
+                .filter(|d| pending.contains(&d.contract_address))
+                .collect();
+            info!(
+                "Resuming relay of {:?} proof {} to {} destination(s)",
+                record.kind,
+                record.id,
+                destinations.len()
+            );
+
+            let (id, kind, proof) = (record.id, record.kind, &record.proof);
+            // Each destination is recorded as the proof lands on it, like a proof relayed right
+            // after it was generated.
+            let results = futures::future::join_all(destinations.iter().map(|d| async move {
+                let result = match kind {
+                    ProofKind::Update { .. } => d.relay_update(proof).await,
+                    ProofKind::Storage { block_number } => {
+                        d.relay_storage_proof(block_number, proof).await
+                    }
+                };
+                self.record_relay(Some(id), kind, d.contract_address, &result)
+                    .await;
+                if result.is_ok() {
+                    if let Err(e) = self.record_landed(kind, d.contract_address, proof).await {
+                        warn!(
+                            "Failed to record what proof {id} wrote to {:?}: {:?}",
+                            d.contract_address, e
+                        );
+                    }
+                }
+                result
+            }))
+            .await;
+
+            let mut failed = destinations.len() < pending.len();
+            for (destination, result) in destinations.iter().zip(results) {
+                if let Err(e) = &result {
+                    warn!(
+                        "Failed to resume relay of proof {id} to {:?}: {:?}",
+                        destination.contract_address, e
+                    );
+                    failed = true;
+                }
+            }
+            if failed {
+                self.abandon_record(Some(record.id)).await;
+                continue;
+            }
+            if matches!(kind, ProofKind::Update { .. }) && !record.follow_ups.is_empty() {
+                self.resume_follow_ups(&record).await;
+            }
+        }
+    }
+
+    /// Prove the follow-up storage of a resumed update that landed on every destination, for each
+    /// group of its destinations expecting the same build of the storage slots program.
+    async fn resume_follow_ups(&self, record: &ProofRecord) {
+        let po = match ProofOutputs::abi_decode(record.proof.public_values.as_slice()) {
+            Ok(po) => po,
+            Err(e) => {
+                warn!(
+                    "Failed to decode the outputs of proof {}: {:?}",
+                    record.id, e
+                );
+                return;
+            }
+        };
+
+        for group in self.storage_vkey_groups() {
+            let group: Vec<&Destination<P>> = group
+                .into_iter()
+                .filter(|d| record.destinations.contains(&d.contract_address))
+                .collect();
+            let Some(first) = group.first() else {
+                continue;
+            };
+            let storage_vkey = first.vkeys().storage_slots;
+            if let Err(e) = self
+                .prove_follow_ups(&po, record.follow_ups.clone(), &group, storage_vkey)
+                .await
+            {
+                warn!(
+                    "Failed to prove the follow-up storage of proof {}: {:?}",
+                    record.id, e
+                );
+            }
+        }
     }
 }
 
@@ -366,61 +1762,343 @@ where
     pub fn run(self, loop_delay: Duration) -> OperatorHandle {
         info!("Starting SP1 Helios operator");
 
-        let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
-        let (storage_proof_tx, mut storage_proof_rx) = mpsc::unbounded_channel();
-        let mut tick = tokio::time::interval(loop_delay);
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<ShutdownRequest>();
+        let (storage_proof_tx, storage_proof_rx) =
+            mpsc::channel(self.proving_config.max_queued_storage_proofs.max(1));
+        let storage_proof_jobs = StorageProofJobs::default();
+        // The first iteration runs right away, outside the ticks.
+        let mut tick =
+            tokio::time::interval_at(tokio::time::Instant::now() + loop_delay, loop_delay);
+        let (loop_delay_tx, mut loop_delay_rx) = watch::channel(loop_delay);
+        // The database is pruned on startup, then on a timer of its own, since records are kept
+        // for days.
+        let mut prune_tick = tokio::time::interval(DB_PRUNE_INTERVAL);
 
-        let operator_handle = OperatorHandle::new(
-            self.storage_slots_to_fetch.clone(),
-            shutdown_tx,
-            storage_proof_tx,
-        );
+        let shared = OperatorShared {
+            storage_slot_config: self.storage_slots_to_fetch.clone(),
+            watchlist_owners: self.watchlist_owners.clone(),
+            pending_update_slots: self.pending_update_slots.clone(),
+            slot_tracker: self.slot_tracker.clone(),
+            contract_status: self.contract_status.clone(),
+            storage_proof_jobs: storage_proof_jobs.clone(),
+            proving_queue: self.proving_queue.clone(),
+            storage_proof_cache: self.storage_proof_cache.clone(),
+            db: self.db.clone(),
+            relay_config: self.relay_config.clone(),
+            events: self.events.clone(),
+        };
+        let operator_handle =
+            OperatorHandle::new(shared, shutdown_tx, storage_proof_tx, loop_delay_tx);
+
+        // Keep a sender alive for the lifetime of the loop, so the receiver never closes even when
+        // finality events are disabled.
+        let (finality_tx, mut finality_rx) = mpsc::channel(16);
+        let finality_watcher = self.finality_events.then(|| {
+            tokio::spawn(finality::watch_finality(
+                self.source_consensus_rpc.urls.clone(),
+                finality_tx.clone(),
+                finality::IDLE_TIMEOUT,
+            ))
+        });
 
         tokio::spawn(async move {
-            // Do the first iteration right away.
-            if let Err(e) = self.run_once().await {
-                error!("Error running operator: {}", e);
-            }
+            let _finality_tx = finality_tx;
 
             let this = Arc::new(self);
-            loop {
+            let storage_proofs = tokio::spawn(
+                this.clone()
+                    .dispatch_storage_proofs(storage_proof_rx, storage_proof_jobs.clone()),
+            );
+
+            // Running iterations, waited for on shutdown.
+            let mut updates = JoinSet::new();
+
+            // Finish relaying proofs generated before a restart, rather than proving them again,
+            // then do the first iteration right away.
+            let first = this.clone();
+            updates.spawn(async move {
+                first.resume_relays().await;
+                first.run_iteration().await;
+            });
+
+            let request = loop {
                 let clone = this.clone();
 
                 tokio::select! {
+                    // Ticks keep running alongside finality events, as a safety net for a stream
+                    // that is connected but no longer delivering events. An iteration with
+                    // nothing to update costs a few RPC calls.
                     _ = tick.tick() => {
-                        tokio::spawn(async move {
-                            if let Err(e) = clone.run_once().await {
-                                error!("Error running operator: {:?}", e);
-                            }
+                        updates.spawn(async move {
+                            clone.run_iteration().await;
                         });
                     }
-                    req = storage_proof_rx.recv() => {
-                        tokio::spawn(async move {
-                            match req {
-                                Some(StorageProofRequest { block_number, contract_keys, tx }) => {
-                                    let proof_result = clone.prove_storage_slots(block_number, contract_keys).await.inspect_err(|e| {
-                                        tracing::error!("Error proving storage slot: {:?}", e);
-                                    });
-
-                                    if let Err(e) = tx.send(proof_result) {
-                                        tracing::error!("Failed to send storage proof: {:?}", e);
-                                    }
-                                }
-                                None => {
-                                    tracing::error!("State proof channel closed");
-                                }
-                            }
+                    _ = prune_tick.tick(), if this.db.is_some() => {
+                        updates.spawn(async move {
+                            clone.prune_db().await;
+                        });
+                    }
+                    Some(slot) = finality_rx.recv() => {
+                        info!("New finalized checkpoint at slot {slot}, requesting update");
+                        updates.spawn(async move {
+                            clone.run_event_iteration().await;
                         });
-
                     }
-                    _ = &mut shutdown_rx => {
+                    Some(_) = updates.join_next() => {}
+                    Ok(()) = loop_delay_rx.changed() => {
+                        let loop_delay = *loop_delay_rx.borrow_and_update();
+                        info!("Loop delay changed to {loop_delay:?}");
+                        tick = tokio::time::interval_at(
+                            tokio::time::Instant::now() + loop_delay,
+                            loop_delay,
+                        );
+                    }
+                    // Every handle was dropped if the request is missing, so there is nobody
+                    // to wait for.
+                    request = &mut shutdown_rx => {
                         info!("Received shutdown signal, shutting down");
-                        break;
+                        break request.ok();
                     }
                 }
+            };
+
+            if let Some(watcher) = finality_watcher {
+                watcher.abort();
+            }
+
+            let deadline = request
+                .as_ref()
+                .map_or(Duration::ZERO, |request| request.deadline);
+            let summary = this
+                .drain(storage_proofs, updates, &storage_proof_jobs, deadline)
+                .await;
+            info!("Operator shut down: {summary:?}");
+
+            if let Some(request) = request {
+                let _ = request.reply.send(summary);
             }
         });
 
         operator_handle
     }
+
+    /// Stop taking storage proof requests, and wait up to `deadline` for running iterations and
+    /// storage proof jobs to finish before cancelling them.
+    ///
+    /// Jobs that were never picked up, and storage slots waiting for the next update, are failed
+    /// right away.
+    async fn drain(
+        self: &Arc<Self>,
+        storage_proofs: JoinHandle<()>,
+        mut updates: JoinSet<()>,
+        jobs: &StorageProofJobs,
+        deadline: Duration,
+    ) -> ShutdownSummary {
+        // Stopping the dispatcher closes the request channel, so later requests are rejected and
+        // those still in the channel are never picked up.
+        storage_proofs.abort();
+        let _ = storage_proofs.await;
+
+        let mut summary = ShutdownSummary::default();
+        let (queued, running) = jobs.unfinished();
+        for id in queued {
+            if jobs.stop(id) {
+                summary.abandoned_jobs.push(id);
+            }
+        }
+
+        let finished = tokio::time::timeout(deadline, async {
+            while updates.join_next().await.is_some() {}
+            futures::future::join_all(running.iter().map(|id| jobs.wait(*id))).await;
+        })
+        .await
+        .is_ok();
+
+        if !finished {
+            warn!("In-flight work did not finish within {deadline:?}, cancelling it");
+            summary.timed_out = true;
+            (summary.abandoned_proofs, summary.abandoned_relays) = self.events.in_flight();
+
+            updates.abort_all();
+            while updates.join_next().await.is_some() {}
+            for id in running {
+                if jobs.stop(id) {
+                    summary.abandoned_jobs.push(id);
+                }
+            }
+        }
+
+        let pending = std::mem::take(&mut *self.pending_update_slots.lock().await);
+        summary.abandoned_next_update_requests = pending.len();
+        notify_attached(
+            pending,
+            Err(anyhow::anyhow!(
+                "The operator shut down before the next update"
+            )),
+        );
+
+        summary
+    }
+
+    /// Work on queued storage proof requests, one proving slot at a time.
+    ///
+    /// Requests for the same block that arrive within the batch window are proven together. A
+    /// request is only taken off the channel once the previous batch has a proving slot, so a full
+    /// channel pushes back on callers instead of piling up tasks.
+    async fn dispatch_storage_proofs(
+        self: Arc<Self>,
+        mut requests: mpsc::Receiver<StorageProofRequest>,
+        jobs: StorageProofJobs,
+    ) {
+        // Requests for other blocks received while gathering a batch.
+        let mut deferred: VecDeque<StorageProofRequest> = VecDeque::new();
+        let max_buffered = self.proving_config.max_queued_storage_proofs.max(1);
+
+        loop {
+            let first = match deferred.pop_front() {
+                Some(request) => request,
+                None => match requests.recv().await {
+                    Some(request) => match self.serve_from_cache(&jobs, request).await {
+                        Some(request) => request,
+                        None => continue,
+                    },
+                    None => break,
+                },
+            };
+
+            let mut batch = StorageProofBatch::new(first);
+            let (same_block, other_blocks): (Vec<_>, Vec<_>) = deferred
+                .drain(..)
+                .partition(|request| request.block_number == batch.block_number);
+            deferred.extend(other_blocks);
+            for request in same_block {
+                batch.push(request);
+            }
+
+            let window = tokio::time::sleep(self.proving_config.batch_window);
+            tokio::pin!(window);
+            while batch.jobs().len() + deferred.len() < max_buffered {
+                tokio::select! {
+                    Some(request) = requests.recv() => {
+                        let Some(request) = self.serve_from_cache(&jobs, request).await else {
+                            continue;
+                        };
+
+                        if request.block_number == batch.block_number {
+                            batch.push(request);
+                        } else {
+                            deferred.push_back(request);
+                        }
+                    }
+                    _ = &mut window => break,
+                }
+            }
+
+            let permit = self.proving_queue.acquire(Priority::Storage).await;
+
+            // Jobs may have been cancelled while they were queued.
+            batch.retain(|job| jobs.is_pending(job));
+            if batch.is_empty() {
+                continue;
+            }
+
+            let ids = batch.jobs();
+            if ids.len() > 1 {
+                info!(
+                    "Proving {} storage proof requests for block {} together",
+                    ids.len(),
+                    batch.block_number
+                );
+            }
+
+            let this = self.clone();
+            let task_jobs = jobs.clone();
+            let task = tokio::spawn(async move {
+                let _permit = permit;
+                this.run_storage_proof_batch(&task_jobs, batch).await;
+            });
+            jobs.set_task(&ids, task.abort_handle());
+        }
+    }
+
+    /// Finish a job from the cache if a cached proof covers it, or hand the request back.
+    ///
+    /// Cached proofs are still relayed if the job asks for it.
+    async fn serve_from_cache(
+        self: &Arc<Self>,
+        jobs: &StorageProofJobs,
+        request: StorageProofRequest,
+    ) -> Option<StorageProofRequest> {
+        let storage_vkey = self.storage_proof_destination().vkeys().storage_slots;
+        let proof = self
+            .storage_proof_cache
+            .get(request.block_number, storage_vkey, &request.contract_keys)
+            .await?;
+
+        info!(
+            "Serving storage proof job {} for block {} from the cache",
+            request.job, request.block_number
+        );
+        if !request.relay {
+            jobs.finish(request.job, Ok(proof));
+            return None;
+        }
+
+        let this = self.clone();
+        let task_jobs = jobs.clone();
+        let task = tokio::spawn(async move {
+            this.relay_and_finish(
+                &task_jobs,
+                request.block_number,
+                request.contract_keys,
+                vec![(request.job, proof)],
+                (storage_vkey, None),
+            )
+            .await;
+        });
+        jobs.set_task(&[request.job], task.abort_handle());
+
+        None
+    }
+}
+
+/// Log a failed iteration. The next one tries again, but fatal errors need an operator's
+/// attention first.
+fn log_run_error(e: &anyhow::Error) {
+    match OperatorError::find(e) {
+        Some(cause) if cause.is_fatal() => {
+            error!(
+                "Error running operator, which retrying will not fix: {:?}",
+                e
+            )
+        }
+        _ => warn!("Error running operator, retrying on the next run: {:?}", e),
+    }
+}
+
+/// Tell the callers who attached storage slots to an update how it went.
+fn notify_attached(attached: Vec<PendingUpdateSlots>, result: Result<IncludedUpdate>) {
+    let result = result.map_err(|e| format!("{e:#}"));
+    for pending in attached {
+        let _ = pending
+            .tx
+            .send(result.clone().map_err(|e| anyhow::anyhow!(e)));
+    }
+}
+
+/// Whether the sync committee period of `head` ends within [`URGENT_ROLLOVER_MARGIN_SLOTS`] of
+/// the source chain's current slot, or has already ended.
+fn is_rollover_near(head: u64, current_slot: u64) -> bool {
+    calc_sync_period::<MainnetConsensusSpec>(current_slot + URGENT_ROLLOVER_MARGIN_SLOTS)
+        > calc_sync_period::<MainnetConsensusSpec>(head)
+}
+
+/// Write storage proofs against `state_root` as inputs to the storage program, along with the
+/// hash of those inputs.
+fn storage_stdin(contracts: &[ContractStorage], state_root: B256) -> Result<(SP1Stdin, B256)> {
+    let mut stdin = SP1Stdin::new();
+    stdin.write(&contracts);
+    stdin.write(&state_root);
+
+    Ok((stdin, inputs_hash(&(contracts, state_root))?))
 }
@@ -0,0 +1,131 @@
+use crate::error::OperatorError;
+use alloy::primitives::{Address, B256};
+use serde::Serialize;
+use sp1_sdk::env::{EnvProver, EnvProvingKey};
+use sp1_sdk::{HashableKey, Prover, ProvingKey};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use tracing::info;
+
+/// The builds of the light client program the operator can prove with, by version.
+///
+/// A contract's `updateLightClientVkey` only goes through for operators that embed the new build,
+/// so keep older builds here, as `elf/light_client-<version>`, until no contract expects them.
+pub const LIGHT_CLIENT_ELFS: &[(&str, &[u8])] =
+    &[("latest", include_bytes!("../../elf/light_client"))];
+
+/// The builds of the storage slots program the operator can prove with, by version.
+///
+/// As with [`LIGHT_CLIENT_ELFS`], keep older builds as `elf/storage-<version>`.
+pub const STORAGE_ELFS: &[(&str, &[u8])] = &[("latest", include_bytes!("../../elf/storage"))];
+
+/// A program whose vkey a destination contract stores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgramKind {
+    /// Proves updates, verified against `lightClientVkey`.
+    LightClient,
+    /// Proves storage slots, verified against `storageSlotVkey`.
+    StorageSlots,
+}
+
+impl fmt::Display for ProgramKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LightClient => f.write_str("light client"),
+            Self::StorageSlots => f.write_str("storage slots"),
+        }
+    }
+}
+
+/// A build of a program, set up for proving.
+pub struct ProgramVersion {
+    pub version: &'static str,
+    pub pk: Arc<EnvProvingKey>,
+}
+
+/// Every embedded build of a program, by vkey.
+pub struct ProgramVersions {
+    kind: ProgramKind,
+    by_vkey: HashMap<B256, ProgramVersion>,
+}
+
+impl ProgramVersions {
+    /// Set up each of the program's `elfs`, labelled by version.
+    pub async fn setup(
+        client: &EnvProver,
+        kind: ProgramKind,
+        elfs: &[(&'static str, &'static [u8])],
+    ) -> Result<Self, OperatorError> {
+        let mut by_vkey = HashMap::new();
+        for &(version, elf) in elfs {
+            info!("Setting up {kind} program {version}...");
+            let pk = client
+                .setup(elf.into())
+                .await
+                .map_err(|e| OperatorError::Setup {
+                    program: kind,
+                    version,
+                    source: e.into(),
+                })?;
+            let vkey = pk.verifying_key().bytes32_raw().into();
+            by_vkey.insert(
+                vkey,
+                ProgramVersion {
+                    version,
+                    pk: Arc::new(pk),
+                },
+            );
+        }
+
+        Ok(Self { kind, by_vkey })
+    }
+
+    /// The build with the given vkey, if the operator embeds it.
+    pub fn get(&self, vkey: B256) -> Option<&ProgramVersion> {
+        self.by_vkey.get(&vkey)
+    }
+
+    /// The build a contract expecting `vkey` verifies proofs of.
+    pub fn require(
+        &self,
+        contract_address: Address,
+        vkey: B256,
+    ) -> Result<&ProgramVersion, OperatorError> {
+        self.get(vkey).ok_or(OperatorError::UnknownVkey {
+            contract_address,
+            program: self.kind,
+            vkey,
+        })
+    }
+}
+
+/// The embedded builds of both programs.
+pub struct Programs {
+    pub light_client: ProgramVersions,
+    pub storage_slots: ProgramVersions,
+}
+
+impl Programs {
+    /// Set up every embedded build of both programs.
+    pub async fn setup(client: &EnvProver) -> Result<Self, OperatorError> {
+        Ok(Self {
+            light_client: ProgramVersions::setup(
+                client,
+                ProgramKind::LightClient,
+                LIGHT_CLIENT_ELFS,
+            )
+            .await?,
+            storage_slots: ProgramVersions::setup(client, ProgramKind::StorageSlots, STORAGE_ELFS)
+                .await?,
+        })
+    }
+
+    pub fn get(&self, kind: ProgramKind) -> &ProgramVersions {
+        match kind {
+            ProgramKind::LightClient => &self.light_client,
+            ProgramKind::StorageSlots => &self.storage_slots,
+        }
+    }
+}
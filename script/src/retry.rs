@@ -0,0 +1,182 @@
+use crate::metrics::metrics;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How failed RPC requests are retried, and when an endpoint is taken out of rotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times a request is retried after it failed on every endpoint.
+    pub max_retries: u32,
+    /// The delay before the first retry, doubled for every retry after it.
+    pub initial_backoff: Duration,
+    /// The longest delay between retries.
+    pub max_backoff: Duration,
+    /// How many requests in a row must fail before an endpoint is marked unhealthy.
+    pub failure_threshold: u32,
+    /// How long an unhealthy endpoint is skipped before it is tried again.
+    pub unhealthy_for: Duration,
+    /// How long to wait for an execution RPC to respond before failing over to the next.
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            failure_threshold: 5,
+            unhealthy_for: Duration::from_secs(60),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry, nor take endpoints out of rotation.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            failure_threshold: u32::MAX,
+            ..Self::default()
+        }
+    }
+
+    /// The delay before the given retry, counting from 0.
+    ///
+    /// The delay doubles with every retry up to `max_backoff`, and a random half of it is left
+    /// out, so that clients failing together do not retry together.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let ceiling = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff);
+
+        ceiling.mul_f64(0.5 + rand::random::<f64>() / 2.0)
+    }
+
+    /// Run a request until it succeeds or the retries run out, backing off between attempts.
+    pub async fn retry<T, E, F, Fut>(&self, method: &str, mut request: F) -> Result<T, E>
+    where
+        E: Display,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut retry = 0;
+        loop {
+            match request().await {
+                Ok(response) => return Ok(response),
+                Err(e) if retry < self.max_retries => {
+                    let delay = self.backoff(retry);
+                    warn!(
+                        "{method} failed, retrying in {delay:?} ({} of {}): {e}",
+                        retry + 1,
+                        self.max_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Tracks consecutive failures per endpoint, and marks endpoints that keep failing as unhealthy
+/// for a while, so that requests skip them.
+///
+/// Each set of endpoints owns its breakers, which identify endpoints by their label within the
+/// set. Share them, such as through [`ConsensusEndpoints`](crate::rpc::ConsensusEndpoints), for
+/// the endpoints' health to carry over between the clients the operator creates for each run.
+#[derive(Debug, Default)]
+pub struct CircuitBreakers {
+    endpoints: Mutex<HashMap<String, Breaker>>,
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    consecutive_failures: u32,
+    /// Until when the endpoint is skipped, if it is unhealthy.
+    unhealthy_until: Option<Instant>,
+}
+
+impl CircuitBreakers {
+    /// Whether requests should be sent to an endpoint: it is healthy, or it has been unhealthy
+    /// long enough to be given another try.
+    pub fn is_available(&self, endpoint: &str) -> bool {
+        self.lock()
+            .get(endpoint)
+            .and_then(|breaker| breaker.unhealthy_until)
+            .is_none_or(|until| Instant::now() >= until)
+    }
+
+    /// Filter `endpoints` down to the available ones, or keep them all if none is, since a
+    /// request to an unhealthy endpoint beats no request at all.
+    pub fn available<T>(&self, endpoints: Vec<(String, T)>) -> Vec<(String, T)> {
+        if endpoints
+            .iter()
+            .any(|(endpoint, _)| self.is_available(endpoint))
+        {
+            endpoints
+                .into_iter()
+                .filter(|(endpoint, _)| self.is_available(endpoint))
+                .collect()
+        } else {
+            endpoints
+        }
+    }
+
+    /// Record the outcome of a request to an endpoint.
+    ///
+    /// After `failure_threshold` failures in a row, the endpoint is marked unhealthy for
+    /// `unhealthy_for`. A failure when it is tried again marks it unhealthy again right away, and
+    /// a success marks it healthy.
+    pub fn record(&self, endpoint: &str, ok: bool, policy: &RetryPolicy) {
+        let mut endpoints = self.lock();
+        let breaker = endpoints.entry(endpoint.to_string()).or_default();
+
+        if ok {
+            if breaker.unhealthy_until.take().is_some() {
+                info!("RPC endpoint {endpoint} recovered");
+            }
+            breaker.consecutive_failures = 0;
+            metrics().set_rpc_endpoint_up(endpoint, true);
+            return;
+        }
+
+        breaker.consecutive_failures = breaker.consecutive_failures.saturating_add(1);
+        if breaker.consecutive_failures >= policy.failure_threshold {
+            if breaker.unhealthy_until.is_none() {
+                warn!(
+                    "RPC endpoint {endpoint} failed {} times in a row, skipping it for {:?}",
+                    breaker.consecutive_failures, policy.unhealthy_for
+                );
+                metrics().set_rpc_endpoint_up(endpoint, false);
+            }
+            breaker.unhealthy_until = Some(Instant::now() + policy.unhealthy_for);
+        }
+    }
+
+    /// The endpoints currently marked unhealthy.
+    pub fn unhealthy(&self) -> Vec<String> {
+        let now = Instant::now();
+        let mut unhealthy: Vec<String> = self
+            .lock()
+            .iter()
+            .filter(|(_, breaker)| breaker.unhealthy_until.is_some_and(|until| now < until))
+            .map(|(endpoint, _)| endpoint.clone())
+            .collect();
+        unhealthy.sort();
+        unhealthy
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Breaker>> {
+        self.endpoints
+            .lock()
+            .expect("Circuit breakers lock poisoned")
+    }
+}
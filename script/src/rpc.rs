@@ -0,0 +1,348 @@
+use crate::error::OperatorError;
+use crate::metrics::{endpoint_label, metrics};
+use crate::retry::{CircuitBreakers, RetryPolicy};
+use async_trait::async_trait;
+use eyre::{eyre, Result};
+use helios_consensus_core::consensus_spec::ConsensusSpec;
+use helios_consensus_core::types::{
+    BeaconBlock, Bootstrap, FinalityUpdate, OptimisticUpdate, Update,
+};
+use helios_ethereum::rpc::http_rpc::HttpRpc;
+use helios_ethereum::rpc::ConsensusRpc;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::error::Elapsed;
+use tracing::warn;
+use tree_hash::TreeHash;
+
+/// The beacon node endpoints of the source chain.
+#[derive(Debug, Clone)]
+pub struct ConsensusEndpoints {
+    /// Endpoint URLs, in order of preference.
+    pub urls: Vec<String>,
+    /// How long to wait for a single endpoint before moving on to the next.
+    pub timeout: Duration,
+    /// How many endpoints must agree on a bootstrap or finality update before it is used.
+    ///
+    /// With a quorum of 1, endpoints are simply tried in order until one succeeds.
+    pub quorum: usize,
+    /// How requests that fail on every endpoint are retried, and when an endpoint is skipped.
+    pub retry: RetryPolicy,
+    /// The health of the endpoints, shared by every client created from these endpoints.
+    pub breakers: Arc<CircuitBreakers>,
+}
+
+impl ConsensusEndpoints {
+    /// Endpoints with the default timeout, quorum and retry policy.
+    ///
+    /// Fails if `urls` is empty.
+    pub fn new(urls: Vec<String>) -> Result<Self, OperatorError> {
+        if urls.is_empty() {
+            return Err(OperatorError::Config(
+                "at least one consensus RPC is required".to_string(),
+            ));
+        }
+
+        Ok(Self::with_urls(urls))
+    }
+
+    fn with_urls(urls: Vec<String>) -> Self {
+        Self {
+            urls,
+            timeout: Duration::from_secs(30),
+            quorum: 1,
+            retry: RetryPolicy::default(),
+            breakers: Arc::default(),
+        }
+    }
+
+    /// The most preferred endpoint.
+    pub fn primary(&self) -> Result<&str, OperatorError> {
+        self.urls
+            .first()
+            .map(String::as_str)
+            .ok_or_else(|| OperatorError::Config("no consensus RPCs".to_string()))
+    }
+}
+
+impl From<String> for ConsensusEndpoints {
+    fn from(url: String) -> Self {
+        Self::with_urls(vec![url])
+    }
+}
+
+impl From<&str> for ConsensusEndpoints {
+    fn from(url: &str) -> Self {
+        Self::with_urls(vec![url.to_string()])
+    }
+}
+
+/// A [`ConsensusRpc`] over several beacon node endpoints.
+///
+/// Requests fail over to the next endpoint on errors or timeouts. When a quorum is configured,
+/// bootstraps and finality updates are instead fetched from every endpoint and only returned if
+/// enough of them agree. Requests that fail anyway are retried with backoff, and endpoints that
+/// keep failing are skipped for a while.
+pub struct FallbackRpc {
+    /// Each endpoint by its [`endpoint_label`], so that API keys in the URLs stay out of logs.
+    endpoints: Vec<(String, HttpRpc)>,
+    timeout: Duration,
+    quorum: usize,
+    retry: RetryPolicy,
+    breakers: Arc<CircuitBreakers>,
+}
+
+impl FallbackRpc {
+    pub fn from_endpoints(endpoints: &ConsensusEndpoints) -> Self {
+        Self {
+            endpoints: endpoints
+                .urls
+                .iter()
+                .map(|url| (endpoint_label(url), HttpRpc::new(url)))
+                .collect(),
+            timeout: endpoints.timeout,
+            quorum: endpoints.quorum,
+            retry: endpoints.retry,
+            breakers: endpoints.breakers.clone(),
+        }
+    }
+
+    /// Try each endpoint in order, returning the first successful response, and retry with
+    /// backoff if every endpoint fails.
+    async fn first_ok<'a, T, F, Fut>(&'a self, method: &str, f: F) -> Result<T>
+    where
+        F: Fn(&'a HttpRpc) -> Fut,
+        Fut: Future<Output = Result<T>> + 'a,
+    {
+        let f = &f;
+        self.retry.retry(method, || self.try_each(method, f)).await
+    }
+
+    async fn try_each<'a, T, F, Fut>(&'a self, method: &str, f: &F) -> Result<T>
+    where
+        F: Fn(&'a HttpRpc) -> Fut,
+        Fut: Future<Output = Result<T>> + 'a,
+    {
+        let mut errors = Vec::new();
+        for (endpoint, rpc) in self.available_endpoints() {
+            match self.request(endpoint, method, f(rpc)).await {
+                Ok(Ok(response)) => return Ok(response),
+                Ok(Err(e)) => {
+                    warn!("{method} failed on {endpoint}: {e}");
+                    errors.push(format!("{endpoint}: {e}"));
+                }
+                Err(_) => {
+                    warn!("{method} timed out on {endpoint}");
+                    errors.push(format!("{endpoint}: timed out"));
+                }
+            }
+        }
+
+        Err(eyre!(
+            "{method} failed on every consensus endpoint: {}",
+            errors.join("; ")
+        ))
+    }
+
+    /// Query every endpoint and return a response that at least `quorum` endpoints agree on,
+    /// as identified by `key`, retrying with backoff if they do not.
+    async fn agreed<'a, T, K, F, Fut>(
+        &'a self,
+        method: &str,
+        f: F,
+        key: impl Fn(&T) -> K,
+    ) -> Result<T>
+    where
+        K: PartialEq + std::fmt::Debug,
+        F: Fn(&'a HttpRpc) -> Fut,
+        Fut: Future<Output = Result<T>> + 'a,
+    {
+        if self.quorum <= 1 {
+            return self.first_ok(method, f).await;
+        }
+
+        let (f, key) = (&f, &key);
+        self.retry
+            .retry(method, || self.try_agree(method, f, key))
+            .await
+    }
+
+    async fn try_agree<'a, T, K, F, Fut>(
+        &'a self,
+        method: &str,
+        f: &F,
+        key: &impl Fn(&T) -> K,
+    ) -> Result<T>
+    where
+        K: PartialEq + std::fmt::Debug,
+        F: Fn(&'a HttpRpc) -> Fut,
+        Fut: Future<Output = Result<T>> + 'a,
+    {
+        let endpoints = self.available_endpoints();
+        let responses = futures::future::join_all(
+            endpoints
+                .iter()
+                .map(|(endpoint, rpc)| self.request(endpoint, method, f(rpc))),
+        )
+        .await;
+
+        let mut succeeded = Vec::new();
+        for ((endpoint, _), response) in endpoints.into_iter().zip(responses) {
+            match response {
+                Ok(Ok(response)) => succeeded.push((endpoint.as_str(), response)),
+                Ok(Err(e)) => warn!("{method} failed on {endpoint}: {e}"),
+                Err(_) => warn!("{method} timed out on {endpoint}"),
+            }
+        }
+
+        agree(method, succeeded, key, self.quorum)
+    }
+
+    /// The endpoints to send requests to, in order, leaving out unhealthy ones unless every
+    /// endpoint is.
+    fn available_endpoints(&self) -> Vec<&(String, HttpRpc)> {
+        self.breakers
+            .available(
+                self.endpoints
+                    .iter()
+                    .map(|endpoint| (endpoint.0.clone(), endpoint))
+                    .collect(),
+            )
+            .into_iter()
+            .map(|(_, endpoint)| endpoint)
+            .collect()
+    }
+
+    /// Run a request against one endpoint within the timeout, recording its latency and whether
+    /// it failed.
+    async fn request<T>(
+        &self,
+        endpoint: &str,
+        method: &str,
+        request: impl Future<Output = Result<T>>,
+    ) -> Result<Result<T>, Elapsed> {
+        let started = Instant::now();
+        let response = tokio::time::timeout(self.timeout, request).await;
+        let ok = matches!(response, Ok(Ok(_)));
+        metrics().observe_rpc(endpoint, method, started.elapsed(), ok);
+        self.breakers.record(endpoint, ok, &self.retry);
+
+        response
+    }
+}
+
+/// Pick the response at least `quorum` endpoints agree on, as identified by `key`, from the
+/// successful responses of each endpoint.
+///
+/// Endpoints that diverge from the largest group are logged.
+pub fn agree<'a, T, K>(
+    method: &str,
+    responses: Vec<(&'a str, T)>,
+    key: impl Fn(&T) -> K,
+    quorum: usize,
+) -> Result<T>
+where
+    K: PartialEq + std::fmt::Debug,
+{
+    // Group the responses by key, remembering which endpoints returned each.
+    let mut groups: Vec<(K, Vec<&'a str>, T)> = Vec::new();
+    for (url, response) in responses {
+        let k = key(&response);
+        match groups.iter_mut().find(|(other, _, _)| *other == k) {
+            Some((_, urls, _)) => urls.push(url),
+            None => groups.push((k, vec![url], response)),
+        }
+    }
+
+    let Some(best) = (0..groups.len()).max_by_key(|i| groups[*i].1.len()) else {
+        return Err(eyre!("{method} failed on every consensus endpoint"));
+    };
+
+    for (i, (k, urls, _)) in groups.iter().enumerate() {
+        if i != best {
+            warn!(
+                "{method} from {urls:?} diverged: got {k:?}, while {:?} got {:?}",
+                groups[best].1, groups[best].0
+            );
+        }
+    }
+
+    if groups[best].1.len() < quorum {
+        return Err(eyre!(
+            "{method} has no quorum of {quorum} endpoints: {}",
+            groups
+                .iter()
+                .map(|(k, urls, _)| format!("{urls:?} returned {k:?}"))
+                .collect::<Vec<_>>()
+                .join("; ")
+        ));
+    }
+
+    Ok(groups.swap_remove(best).2)
+}
+
+#[async_trait]
+impl<S: ConsensusSpec> ConsensusRpc<S> for FallbackRpc {
+    /// Create an RPC over a comma-separated list of endpoints, failing over in order.
+    fn new(path: &str) -> Self {
+        Self::from_endpoints(&ConsensusEndpoints::with_urls(
+            path.split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(str::to_string)
+                .collect(),
+        ))
+    }
+
+    async fn get_bootstrap(&self, checkpoint: alloy_primitives::B256) -> Result<Bootstrap<S>> {
+        self.agreed(
+            "get_bootstrap",
+            |rpc| <HttpRpc as ConsensusRpc<S>>::get_bootstrap(rpc, checkpoint),
+            |bootstrap: &Bootstrap<S>| {
+                (
+                    bootstrap.header().beacon().tree_hash_root(),
+                    bootstrap.current_sync_committee().tree_hash_root(),
+                )
+            },
+        )
+        .await
+    }
+
+    async fn get_updates(&self, period: u64, count: u8) -> Result<Vec<Update<S>>> {
+        self.first_ok("get_updates", |rpc| {
+            <HttpRpc as ConsensusRpc<S>>::get_updates(rpc, period, count)
+        })
+        .await
+    }
+
+    async fn get_finality_update(&self) -> Result<FinalityUpdate<S>> {
+        self.agreed(
+            "get_finality_update",
+            |rpc| <HttpRpc as ConsensusRpc<S>>::get_finality_update(rpc),
+            |update: &FinalityUpdate<S>| update.finalized_header().beacon().tree_hash_root(),
+        )
+        .await
+    }
+
+    async fn get_optimistic_update(&self) -> Result<OptimisticUpdate<S>> {
+        self.first_ok("get_optimistic_update", |rpc| {
+            <HttpRpc as ConsensusRpc<S>>::get_optimistic_update(rpc)
+        })
+        .await
+    }
+
+    async fn get_block(&self, slot: u64) -> Result<BeaconBlock<S>> {
+        self.first_ok("get_block", |rpc| {
+            <HttpRpc as ConsensusRpc<S>>::get_block(rpc, slot)
+        })
+        .await
+    }
+
+    async fn chain_id(&self) -> Result<u64> {
+        self.first_ok("chain_id", |rpc| {
+            <HttpRpc as ConsensusRpc<S>>::chain_id(rpc)
+        })
+        .await
+    }
+}
@@ -0,0 +1,203 @@
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Limits on how many proofs the operator generates and queues at once.
+#[derive(Debug, Clone)]
+pub struct ProvingConfig {
+    /// The maximum number of proofs generated at once, across update and storage proofs.
+    pub max_concurrent_proofs: usize,
+    /// The maximum number of storage proof requests waiting to be proven.
+    ///
+    /// Further requests are rejected, or wait for room, until the queue drains.
+    pub max_queued_storage_proofs: usize,
+    /// How long to wait for more storage proof requests for the same block, to prove them
+    /// together.
+    pub batch_window: Duration,
+}
+
+impl Default for ProvingConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_proofs: 2,
+            max_queued_storage_proofs: 64,
+            batch_window: Duration::from_secs(1),
+        }
+    }
+}
+
+/// The priority of a proof in the [`ProvingQueue`], lowest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Storage,
+    Update,
+    /// An update that has to land before the contract's sync committee period rolls over.
+    UrgentUpdate,
+}
+
+/// The number of proofs being generated and waiting in the [`ProvingQueue`].
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct QueueDepth {
+    pub running_update_proofs: usize,
+    pub running_storage_proofs: usize,
+    pub queued_update_proofs: usize,
+    pub queued_storage_proofs: usize,
+}
+
+/// Limits how many proofs are generated at once, handing out free slots by priority and then in
+/// order of arrival.
+pub struct ProvingQueue {
+    max_concurrent: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    running_updates: usize,
+    running_storage: usize,
+    waiting: BinaryHeap<Waiter>,
+    next_seq: u64,
+}
+
+impl State {
+    fn running(&self) -> usize {
+        self.running_updates + self.running_storage
+    }
+
+    fn start(&mut self, priority: Priority) {
+        match priority {
+            Priority::Storage => self.running_storage += 1,
+            Priority::Update | Priority::UrgentUpdate => self.running_updates += 1,
+        }
+    }
+
+    fn stop(&mut self, priority: Priority) {
+        match priority {
+            Priority::Storage => self.running_storage -= 1,
+            Priority::Update | Priority::UrgentUpdate => self.running_updates -= 1,
+        }
+    }
+}
+
+struct Waiter {
+    priority: Priority,
+    seq: u64,
+    tx: oneshot::Sender<ProvingPermit>,
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Highest priority first, then first come first served.
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+/// A slot in the [`ProvingQueue`], freed when dropped.
+pub struct ProvingPermit {
+    queue: Arc<ProvingQueue>,
+    priority: Priority,
+}
+
+impl Drop for ProvingPermit {
+    fn drop(&mut self) {
+        self.queue.release(self.priority);
+    }
+}
+
+impl ProvingQueue {
+    pub fn new(max_concurrent: usize) -> Arc<Self> {
+        Arc::new(Self {
+            max_concurrent: max_concurrent.max(1),
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Wait for a free slot to generate a proof with the given priority.
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> ProvingPermit {
+        let rx = {
+            let mut state = self.lock();
+            if state.waiting.is_empty() && state.running() < self.max_concurrent {
+                state.start(priority);
+                return ProvingPermit {
+                    queue: self.clone(),
+                    priority,
+                };
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter { priority, seq, tx });
+            rx
+        };
+
+        rx.await
+            .expect("The queue holds a reference to itself while a waiter is pending")
+    }
+
+    /// The number of proofs being generated and waiting for a slot.
+    pub fn depth(&self) -> QueueDepth {
+        let state = self.lock();
+        let queued_storage = state
+            .waiting
+            .iter()
+            .filter(|waiter| waiter.priority == Priority::Storage)
+            .count();
+
+        QueueDepth {
+            running_update_proofs: state.running_updates,
+            running_storage_proofs: state.running_storage,
+            queued_update_proofs: state.waiting.len() - queued_storage,
+            queued_storage_proofs: queued_storage,
+        }
+    }
+
+    fn release(self: &Arc<Self>, priority: Priority) {
+        // Permits whose waiter gave up are dropped once the lock is released, which frees their
+        // slot in turn.
+        let mut abandoned = Vec::new();
+
+        let mut state = self.lock();
+        state.stop(priority);
+        while state.running() < self.max_concurrent {
+            let Some(waiter) = state.waiting.pop() else {
+                break;
+            };
+
+            state.start(waiter.priority);
+            let permit = ProvingPermit {
+                queue: self.clone(),
+                priority: waiter.priority,
+            };
+            if let Err(permit) = waiter.tx.send(permit) {
+                abandoned.push(permit);
+            }
+        }
+        drop(state);
+
+        drop(abandoned);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("Proving queue lock poisoned")
+    }
+}
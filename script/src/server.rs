@@ -0,0 +1,687 @@
+use crate::auth::{ApiClient, Authorizer, Rejection};
+use crate::cache::CacheStats;
+use crate::events::OperatorStatus;
+use crate::handle::{ContractKeys, IncludedUpdate, OperatorHandle};
+use crate::inclusion::WrittenStorageSlot;
+use crate::jobs::{JobId, JobInfo, JobStatus, StorageProof, SubmitError};
+use alloy::primitives::{Address, Bytes, B256};
+use alloy::sol_types::SolType;
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::header::{AUTHORIZATION, RETRY_AFTER};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use sp1_helios_primitives::types::StorageProofOutputs;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Serve the operator handle over HTTP JSON until the listener fails.
+///
+/// Without an [`Authorizer`], the API is only served on loopback addresses. With one, every route
+/// requires an `Authorization: Bearer <api-key>` header, and
+/// storage proof requests are checked against the client's quota. Clients own the storage slots
+/// they add to the watchlist, and only they or an admin client may change or remove them.
+///
+/// Routes:
+/// - `GET /status`: the state of each destination contract, the last update and error, the work
+///   in flight, the depth of the proving queue and the storage proof cache hit rate.
+/// - `GET /storage-slots`: the storage slots proven with every update.
+/// - `POST /storage-slots`: add a storage slot, given `{ "address", "storage_slot" }`.
+/// - `DELETE /storage-slots/{address}`: remove a contract and all its storage slots.
+/// - `DELETE /storage-slots/{address}/{slot}`: remove a single storage slot.
+/// - `POST /proofs`: prove storage slots, given `{ "block_number", "contracts" }`, and with
+///   `"relay": true` submit the proof to the destination contracts.
+/// - `POST /next-update`: prove storage slots with the next update only, given `{ "contracts" }`,
+///   and respond once it lands.
+/// - `POST /jobs`: submit a storage proof job, given the same body as `POST /proofs`.
+/// - `GET /jobs`: the storage proof jobs that are running or finished recently.
+/// - `GET /jobs/{id}`: the status of a storage proof job.
+/// - `GET /jobs/{id}/proof`: the proof of a finished storage proof job.
+/// - `DELETE /jobs/{id}`: cancel a storage proof job.
+pub async fn serve(
+    handle: OperatorHandle,
+    addr: SocketAddr,
+    auth: Option<Authorizer>,
+) -> Result<()> {
+    if auth.is_none() && !addr.ip().is_loopback() {
+        anyhow::bail!("Refusing to serve the operator API on {addr} without authentication");
+    }
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Serving operator API on {addr}");
+
+    axum::serve(listener, router(handle, auth)).await?;
+
+    Ok(())
+}
+
+pub fn router(handle: OperatorHandle, auth: Option<Authorizer>) -> Router {
+    Router::new()
+        .route("/status", get(status))
+        .route("/storage-slots", get(storage_slots).post(add_storage_slot))
+        .route("/storage-slots/:address", delete(remove_address))
+        .route("/storage-slots/:address/:slot", delete(remove_storage_slot))
+        .route("/written-storage-slots", get(written_storage_slots))
+        .route("/proofs", post(prove_storage_slots))
+        .route("/next-update", post(include_in_next_update))
+        .route("/jobs", get(storage_proof_jobs).post(submit_storage_proof))
+        .route(
+            "/jobs/:id",
+            get(storage_proof_job).delete(cancel_storage_proof),
+        )
+        .route("/jobs/:id/proof", get(storage_proof_result))
+        .with_state(ApiState {
+            handle,
+            auth: auth.map(Arc::new),
+        })
+}
+
+#[derive(Clone)]
+struct ApiState {
+    handle: OperatorHandle,
+    auth: Option<Arc<Authorizer>>,
+}
+
+impl ApiState {
+    /// Authenticate the request, if the API requires authentication, returning the client's name.
+    fn authenticate(&self, headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+        match &self.auth {
+            Some(auth) => Ok(Some(auth.authenticate(api_key(headers))?.name.clone())),
+            None => Ok(None),
+        }
+    }
+
+    /// Authenticate the request, if the API requires authentication, returning the client unless
+    /// it may change any part of the watchlist.
+    fn watchlist_client(&self, headers: &HeaderMap) -> Result<Option<&ApiClient>, ApiError> {
+        match &self.auth {
+            Some(auth) => Ok(Some(auth.authenticate(api_key(headers))?).filter(|c| !c.admin)),
+            None => Ok(None),
+        }
+    }
+
+    /// Validate a storage proof request and check it against the client's quota.
+    fn authorize(
+        &self,
+        headers: &HeaderMap,
+        request: &ProofRequest,
+    ) -> Result<Option<String>, ApiError> {
+        if request.contracts.is_empty() {
+            return Err(ApiError::bad_request("at least one contract is required"));
+        }
+
+        let client = self.authorize_proof_request(headers, &request.contracts)?;
+        Ok(client.map(|client| client.name.clone()))
+    }
+
+    /// Authenticate a storage proof request and check it against the client's quota, returning
+    /// the client.
+    fn authorize_proof_request(
+        &self,
+        headers: &HeaderMap,
+        contract_keys: &[ContractKeys],
+    ) -> Result<Option<&ApiClient>, ApiError> {
+        let Some(auth) = &self.auth else {
+            return Ok(None);
+        };
+
+        let client = auth
+            .authorize_proof_request(api_key(headers), contract_keys)
+            .inspect_err(|e| warn!("Rejected storage proof request: {e}"))?;
+        info!("Accepted storage proof request from {}", client.name);
+
+        Ok(Some(client))
+    }
+
+    /// Get a job, if the requesting client may see it.
+    ///
+    /// With authentication, clients only see the jobs they submitted, and others' read as unknown.
+    fn storage_proof_job(&self, headers: &HeaderMap, id: JobId) -> Result<JobInfo, ApiError> {
+        let client = self.authenticate(headers)?;
+
+        self.handle
+            .storage_proof_job(id)
+            .filter(|job| client.is_none() || job.client == client)
+            .ok_or_else(|| ApiError::not_found(format!("unknown job {id}")))
+    }
+}
+
+/// The API key from an `Authorization: Bearer <api-key>` header.
+fn api_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    #[serde(flatten)]
+    status: OperatorStatus,
+    storage_proof_cache: CacheStats,
+}
+
+#[derive(Deserialize)]
+struct StorageSlotRequest {
+    address: Address,
+    storage_slot: B256,
+    /// How long the slot may go unchanged without being included in an update, when the operator
+    /// only includes changed slots.
+    #[serde(default)]
+    max_staleness_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ProofRequest {
+    block_number: u64,
+    contracts: Vec<ContractKeys>,
+    /// Relay the proof to the destination contracts with `updateStorageSlot`.
+    #[serde(default)]
+    relay: bool,
+}
+
+#[derive(Serialize)]
+struct JobResponse {
+    id: JobId,
+}
+
+#[derive(Serialize)]
+struct ProofResponse {
+    /// The proof bytes, as passed to `updateStorageSlot`.
+    proof: Bytes,
+    public_values: Bytes,
+    outputs: DecodedStorageProofOutputs,
+    /// The position of each requested slot in `outputs.storage_slots`, in request order.
+    ///
+    /// The proof may cover slots requested by others for the same block.
+    slot_indices: Vec<usize>,
+    /// The `updateStorageSlot` transactions, if the proof was relayed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    transactions: Vec<RelayedTransaction>,
+}
+
+#[derive(Deserialize)]
+struct NextUpdateRequest {
+    contracts: Vec<ContractKeys>,
+}
+
+#[derive(Serialize)]
+struct NextUpdateResponse {
+    head: u64,
+    execution_block_number: u64,
+    transactions: Vec<RelayedTransaction>,
+}
+
+impl From<IncludedUpdate> for NextUpdateResponse {
+    fn from(update: IncludedUpdate) -> Self {
+        Self {
+            head: update.head,
+            execution_block_number: update.execution_block_number,
+            transactions: update
+                .receipts
+                .into_iter()
+                .map(|relayed| RelayedTransaction {
+                    contract_address: relayed.contract_address,
+                    transaction_hash: relayed.receipt.transaction_hash,
+                    block_number: relayed.receipt.block_number,
+                    gas_used: relayed.receipt.gas_used,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RelayedTransaction {
+    contract_address: Address,
+    transaction_hash: B256,
+    block_number: Option<u64>,
+    gas_used: u64,
+}
+
+/// The decoded `StorageProofOutputs` public values of a storage proof.
+#[derive(Serialize)]
+struct DecodedStorageProofOutputs {
+    state_root: B256,
+    storage_slots: Vec<DecodedStorageSlot>,
+}
+
+#[derive(Serialize)]
+struct DecodedStorageSlot {
+    contract_address: Address,
+    key: B256,
+    value: B256,
+}
+
+impl TryFrom<StorageProof> for ProofResponse {
+    type Error = anyhow::Error;
+
+    fn try_from(
+        StorageProof {
+            proof,
+            slot_indices,
+            receipts,
+        }: StorageProof,
+    ) -> Result<Self> {
+        let outputs = StorageProofOutputs::abi_decode(proof.public_values.as_slice())?;
+
+        Ok(Self {
+            proof: proof.bytes().into(),
+            public_values: proof.public_values.to_vec().into(),
+            outputs: DecodedStorageProofOutputs {
+                state_root: outputs.stateRoot,
+                storage_slots: outputs
+                    .storageSlots
+                    .into_iter()
+                    .map(|slot| DecodedStorageSlot {
+                        contract_address: slot.contractAddress,
+                        key: slot.key,
+                        value: slot.value,
+                    })
+                    .collect(),
+            },
+            slot_indices,
+            transactions: receipts
+                .into_iter()
+                .map(|relayed| RelayedTransaction {
+                    contract_address: relayed.contract_address,
+                    transaction_hash: relayed.receipt.transaction_hash,
+                    block_number: relayed.receipt.block_number,
+                    gas_used: relayed.receipt.gas_used,
+                })
+                .collect(),
+        })
+    }
+}
+
+/// An error returned to API clients as `{ "code": "...", "error": "..." }`.
+struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    retry_after_secs: Option<u64>,
+}
+
+impl ApiError {
+    fn internal(e: anyhow::Error) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            code: "internal",
+            message: format!("{e:#}"),
+            retry_after_secs: None,
+        }
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            code: "not_found",
+            message: message.into(),
+            retry_after_secs: None,
+        }
+    }
+
+    fn conflict(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::CONFLICT,
+            code: "conflict",
+            message: message.into(),
+            retry_after_secs: None,
+        }
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            code: "invalid_request",
+            message: message.into(),
+            retry_after_secs: None,
+        }
+    }
+}
+
+impl From<Rejection> for ApiError {
+    fn from(rejection: Rejection) -> Self {
+        let (status, retry_after_secs) = match &rejection {
+            Rejection::Unauthorized => (StatusCode::UNAUTHORIZED, None),
+            Rejection::RateLimited { retry_after } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                Some(retry_after.as_secs().max(1)),
+            ),
+            Rejection::TooManyContracts { .. } | Rejection::TooManySlots { .. } => {
+                (StatusCode::PAYLOAD_TOO_LARGE, None)
+            }
+            Rejection::TooManyWatchedSlots { .. } | Rejection::NotOwner => {
+                (StatusCode::FORBIDDEN, None)
+            }
+        };
+
+        Self {
+            status,
+            code: rejection.code(),
+            message: rejection.to_string(),
+            retry_after_secs,
+        }
+    }
+}
+
+impl From<SubmitError> for ApiError {
+    fn from(e: SubmitError) -> Self {
+        let code = match e {
+            SubmitError::QueueFull => "queue_full",
+            SubmitError::Stopped => "stopped",
+        };
+
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            code,
+            message: e.to_string(),
+            retry_after_secs: None,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        #[derive(Serialize)]
+        struct Body {
+            code: &'static str,
+            error: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            retry_after_secs: Option<u64>,
+        }
+
+        let mut response = (
+            self.status,
+            Json(Body {
+                code: self.code,
+                error: self.message,
+                retry_after_secs: self.retry_after_secs,
+            }),
+        )
+            .into_response();
+
+        if let Some(retry_after_secs) = self.retry_after_secs {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, retry_after_secs.into());
+        }
+
+        response
+    }
+}
+
+async fn status(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> Result<Json<StatusResponse>, ApiError> {
+    state.authenticate(&headers)?;
+
+    Ok(Json(StatusResponse {
+        status: state.handle.status().await,
+        storage_proof_cache: state.handle.storage_proof_cache(),
+    }))
+}
+
+async fn storage_slots(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> Result<Json<HashMap<Address, Vec<B256>>>, ApiError> {
+    state.authenticate(&headers)?;
+
+    Ok(Json(
+        state
+            .handle
+            .storage_slots()
+            .await
+            .into_iter()
+            .map(|(address, slots)| (address, slots.into_iter().collect()))
+            .collect(),
+    ))
+}
+
+async fn written_storage_slots(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<WrittenStorageSlot>>, ApiError> {
+    state.authenticate(&headers)?;
+
+    Ok(Json(state.handle.written_storage_slots().await))
+}
+
+async fn add_storage_slot(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(request): Json<StorageSlotRequest>,
+) -> Result<StatusCode, ApiError> {
+    // Every watched slot is proven with every update, so adding one counts against the quota.
+    let client = state.authorize_proof_request(
+        &headers,
+        &[ContractKeys {
+            address: request.address,
+            storage_slots: vec![request.storage_slot],
+        }],
+    )?;
+
+    match client {
+        Some(client) => {
+            if !state
+                .handle
+                .add_client_storage_slot(
+                    request.address,
+                    request.storage_slot,
+                    &client.name,
+                    client.max_watched_slots,
+                )
+                .await
+            {
+                return Err(Rejection::TooManyWatchedSlots {
+                    max: client.max_watched_slots,
+                }
+                .into());
+            }
+        }
+        None => {
+            state
+                .handle
+                .add_storage_slot(request.address, request.storage_slot)
+                .await
+        }
+    }
+
+    // Re-adding a watched slot succeeds, but only its owner or an admin may change how stale it
+    // may get.
+    let max_staleness = request.max_staleness_secs.map(Duration::from_secs);
+    match client.filter(|client| !client.admin) {
+        Some(client) => {
+            let owned = state
+                .handle
+                .set_client_storage_slot_max_staleness(
+                    request.address,
+                    request.storage_slot,
+                    &client.name,
+                    max_staleness,
+                )
+                .await;
+            if !owned && max_staleness.is_some() {
+                return Err(Rejection::NotOwner.into());
+            }
+        }
+        None => {
+            state
+                .handle
+                .set_storage_slot_max_staleness(
+                    request.address,
+                    request.storage_slot,
+                    max_staleness,
+                )
+                .await
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_address(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path(address): Path<Address>,
+) -> Result<StatusCode, ApiError> {
+    match state.watchlist_client(&headers)? {
+        Some(client) => {
+            if !state
+                .handle
+                .remove_client_address(address, &client.name)
+                .await
+            {
+                return Err(Rejection::NotOwner.into());
+            }
+        }
+        None => state.handle.remove_address(address).await,
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_storage_slot(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path((address, slot)): Path<(Address, B256)>,
+) -> Result<StatusCode, ApiError> {
+    match state.watchlist_client(&headers)? {
+        Some(client) => {
+            if !state
+                .handle
+                .remove_client_storage_slot(address, slot, &client.name)
+                .await
+            {
+                return Err(Rejection::NotOwner.into());
+            }
+        }
+        None => state.handle.remove_storage_slot(address, slot).await,
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn prove_storage_slots(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(request): Json<ProofRequest>,
+) -> Result<Json<ProofResponse>, ApiError> {
+    state.authorize(&headers, &request)?;
+
+    let proof = if request.relay {
+        state
+            .handle
+            .relay_proofs_for(request.block_number, request.contracts)
+            .await
+    } else {
+        state
+            .handle
+            .get_proofs_for(request.block_number, request.contracts)
+            .await
+    }
+    .map_err(ApiError::internal)?;
+
+    Ok(Json(proof.try_into().map_err(ApiError::internal)?))
+}
+
+async fn submit_storage_proof(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(request): Json<ProofRequest>,
+) -> Result<(StatusCode, Json<JobResponse>), ApiError> {
+    let client = state.authorize(&headers, &request)?;
+
+    let id = state.handle.submit_storage_proof(
+        request.block_number,
+        request.contracts,
+        request.relay,
+        client,
+    )?;
+
+    Ok((StatusCode::ACCEPTED, Json(JobResponse { id })))
+}
+
+async fn storage_proof_jobs(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<JobInfo>>, ApiError> {
+    let client = state.authenticate(&headers)?;
+
+    Ok(Json(
+        state
+            .handle
+            .storage_proof_jobs()
+            .into_iter()
+            .filter(|job| client.is_none() || job.client == client)
+            .collect(),
+    ))
+}
+
+async fn storage_proof_job(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path(id): Path<JobId>,
+) -> Result<Json<JobInfo>, ApiError> {
+    state.storage_proof_job(&headers, id).map(Json)
+}
+
+async fn storage_proof_result(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path(id): Path<JobId>,
+) -> Result<Json<ProofResponse>, ApiError> {
+    let job = state.storage_proof_job(&headers, id)?;
+    if job.status != JobStatus::Done {
+        return Err(ApiError::conflict(format!("job {id} has no proof")));
+    }
+
+    let proof = state
+        .handle
+        .storage_proof_result(id)
+        .ok_or_else(|| ApiError::not_found(format!("unknown job {id}")))?;
+
+    Ok(Json(proof.try_into().map_err(ApiError::internal)?))
+}
+
+async fn cancel_storage_proof(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path(id): Path<JobId>,
+) -> Result<StatusCode, ApiError> {
+    state.storage_proof_job(&headers, id)?;
+    if !state.handle.cancel_storage_proof(id) {
+        return Err(ApiError::conflict(format!("job {id} has already finished")));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn include_in_next_update(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(request): Json<NextUpdateRequest>,
+) -> Result<Json<NextUpdateResponse>, ApiError> {
+    if request.contracts.is_empty() {
+        return Err(ApiError::bad_request("at least one contract is required"));
+    }
+
+    state.authorize_proof_request(&headers, &request.contracts)?;
+
+    let update = state
+        .handle
+        .include_in_next_update(request.contracts)
+        .await
+        .map_err(ApiError::internal)?;
+
+    Ok(Json(update.into()))
+}
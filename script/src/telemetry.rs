@@ -0,0 +1,24 @@
+use anyhow::Result;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+
+/// The service name spans are exported under.
+pub const SERVICE_NAME: &str = "sp1-helios-operator";
+
+/// Build a tracer provider that exports spans in batches to an OTLP collector over gRPC, e.g.
+/// `http://localhost:4317`.
+///
+/// Shut the provider down before exiting to flush the spans still buffered.
+pub fn otlp_tracer_provider(endpoint: &str) -> Result<TracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", SERVICE_NAME)]))
+        .build())
+}
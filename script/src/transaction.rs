@@ -0,0 +1,404 @@
+use crate::retry::RetryPolicy;
+use alloy::network::TransactionBuilder;
+use alloy::primitives::TxHash;
+use alloy::providers::{Provider, WalletProvider};
+use alloy::rpc::types::{TransactionReceipt, TransactionRequest};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// How often to poll for receipts of sent transactions.
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(4);
+
+/// The minimum fee bump most nodes accept for a replacement transaction.
+const MIN_BUMP_PERCENT: u64 = 10;
+
+/// EIP-1559 fees for a single transaction, in wei.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fees {
+    pub max_fee_per_gas: u128,
+    pub max_priority_fee_per_gas: u128,
+}
+
+/// How the operator prices the transactions it sends.
+#[derive(Debug, Clone)]
+pub struct FeePolicy {
+    /// Multiplier applied to the node's fee estimate, in percent.
+    pub estimate_multiplier_percent: u64,
+    /// How much each replacement of a stuck transaction raises its fees, in percent.
+    ///
+    /// Values below 10% are raised to 10%, since nodes reject smaller replacements.
+    pub bump_percent: u64,
+    /// Upper bound on `maxFeePerGas`, in wei.
+    pub max_fee_per_gas_cap: Option<u128>,
+    /// Upper bound on `maxPriorityFeePerGas`, in wei.
+    pub max_priority_fee_per_gas_cap: Option<u128>,
+}
+
+impl Default for FeePolicy {
+    fn default() -> Self {
+        Self {
+            estimate_multiplier_percent: 100,
+            bump_percent: 20,
+            max_fee_per_gas_cap: None,
+            max_priority_fee_per_gas_cap: None,
+        }
+    }
+}
+
+impl FeePolicy {
+    /// Estimate the fees for a new transaction, applying the multiplier and caps.
+    pub async fn estimate<P: Provider>(&self, provider: &P) -> Result<Fees> {
+        let estimate = provider
+            .estimate_eip1559_fees()
+            .await
+            .context("Failed to estimate EIP-1559 fees")?;
+
+        Ok(self.capped(Fees {
+            max_fee_per_gas: scale(estimate.max_fee_per_gas, self.estimate_multiplier_percent),
+            max_priority_fee_per_gas: scale(
+                estimate.max_priority_fee_per_gas,
+                self.estimate_multiplier_percent,
+            ),
+        }))
+    }
+
+    /// The fees to use when replacing a transaction that was sent with `fees`.
+    pub fn bump(&self, fees: Fees) -> Fees {
+        let percent = 100 + self.bump_percent.max(MIN_BUMP_PERCENT);
+
+        self.capped(Fees {
+            max_fee_per_gas: scale(fees.max_fee_per_gas, percent),
+            max_priority_fee_per_gas: scale(fees.max_priority_fee_per_gas, percent),
+        })
+    }
+
+    fn capped(&self, fees: Fees) -> Fees {
+        let max_fee_per_gas = self
+            .max_fee_per_gas_cap
+            .map_or(fees.max_fee_per_gas, |cap| fees.max_fee_per_gas.min(cap));
+        let max_priority_fee_per_gas = self
+            .max_priority_fee_per_gas_cap
+            .map_or(fees.max_priority_fee_per_gas, |cap| {
+                fees.max_priority_fee_per_gas.min(cap)
+            });
+
+        Fees {
+            max_fee_per_gas,
+            // The priority fee can never exceed the max fee.
+            max_priority_fee_per_gas: max_priority_fee_per_gas.min(max_fee_per_gas),
+        }
+    }
+}
+
+/// How the operator sends and confirms transactions.
+#[derive(Debug, Clone)]
+pub struct RelayConfig {
+    /// The number of confirmations to wait for before a transaction is considered final.
+    pub confirmations: u64,
+    /// How long to wait for a transaction to be included before replacing it with higher fees.
+    pub replacement_timeout: Duration,
+    /// How many times a stuck transaction is replaced before giving up.
+    pub max_replacements: u32,
+    /// How long to wait for an included transaction to be confirmed before treating it as
+    /// dropped and replacing it.
+    pub confirmation_timeout: Duration,
+    /// How long to back off before bumping the fees of a replacement the node rejected.
+    pub retry: RetryPolicy,
+    /// The fee policy used to price transactions.
+    pub fees: FeePolicy,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            confirmations: 3,
+            replacement_timeout: Duration::from_secs(60),
+            max_replacements: 5,
+            confirmation_timeout: Duration::from_secs(10 * 60),
+            retry: RetryPolicy::default(),
+            fees: FeePolicy::default(),
+        }
+    }
+}
+
+/// A [`RelayConfig`] shared by the senders of every destination, so it can be changed while the
+/// operator runs.
+pub(crate) type SharedRelayConfig = Arc<RwLock<RelayConfig>>;
+
+/// Sends transactions from the provider's default signer.
+///
+/// Transactions are sent one at a time. A transaction that is not included within the
+/// replacement timeout is re-sent at the same nonce with bumped fees. If it is still pending once
+/// the replacements are exhausted, its nonce and fees are remembered, and the next transaction
+/// replaces it instead of queueing behind it. Pending transactions the sender did not send, such
+/// as those of another process using the same key, are never replaced; new transactions queue
+/// behind them.
+///
+/// Destinations on the same chain with the same signer must share a sender, or their transactions
+/// would race for the same nonce and replace each other. The config may be shared with other
+/// senders and changed between transactions.
+pub struct TxSender {
+    config: SharedRelayConfig,
+    /// Nonces of transactions this sender gave up on, and the last fees they were sent with.
+    stuck: Mutex<BTreeMap<u64, Fees>>,
+}
+
+impl TxSender {
+    pub fn new(config: RelayConfig) -> Self {
+        Self::with_shared_config(Arc::new(RwLock::new(config)))
+    }
+
+    pub(crate) fn with_shared_config(config: SharedRelayConfig) -> Self {
+        Self {
+            config,
+            stuck: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> RelayConfig {
+        self.config
+            .read()
+            .expect("Relay config lock poisoned")
+            .clone()
+    }
+
+    /// Send a transaction and wait for it to be confirmed, replacing it if it gets stuck.
+    ///
+    /// `on_sent` is called with the hash of the transaction and of each replacement as they are
+    /// broadcast.
+    pub async fn send<P>(
+        &self,
+        provider: &P,
+        tx: TransactionRequest,
+        on_sent: impl Fn(TxHash),
+    ) -> Result<TransactionReceipt>
+    where
+        P: Provider + WalletProvider,
+    {
+        let mut stuck = self.stuck.lock().await;
+        let config = self.config();
+
+        let signer = provider.default_signer_address();
+        let confirmed_nonce = provider
+            .get_transaction_count(signer)
+            .latest()
+            .await
+            .context("Failed to get confirmed nonce")?;
+
+        // Forget any stuck transactions that have since been mined.
+        stuck.retain(|nonce, _| *nonce >= confirmed_nonce);
+
+        let estimate = config.fees.estimate(provider).await?;
+        let (nonce, mut fees) = match stuck.first_key_value() {
+            Some((nonce, previous)) => {
+                info!("Replacing stuck transaction with nonce {nonce}");
+                (*nonce, max_fees(estimate, config.fees.bump(*previous)))
+            }
+            None => {
+                let pending_nonce = provider
+                    .get_transaction_count(signer)
+                    .pending()
+                    .await
+                    .context("Failed to get pending nonce")?;
+
+                // Transactions this sender did not send are pending, e.g. from another process
+                // using the same key. Their fees are unknown, so queue behind them rather than
+                // replacing them.
+                if pending_nonce > confirmed_nonce {
+                    warn!(
+                        "Found {} pending transaction(s) from {signer} that were not sent by this operator, queueing behind them at nonce {pending_nonce}",
+                        pending_nonce - confirmed_nonce
+                    );
+                }
+
+                (pending_nonce, estimate)
+            }
+        };
+
+        let mut hashes: Vec<TxHash> = Vec::new();
+        for attempt in 0..=config.max_replacements {
+            let request = tx
+                .clone()
+                .with_nonce(nonce)
+                .with_max_fee_per_gas(fees.max_fee_per_gas)
+                .with_max_priority_fee_per_gas(fees.max_priority_fee_per_gas);
+
+            match provider.send_transaction(request).await {
+                Ok(pending) => {
+                    info!(
+                        "Sent transaction {:?} with nonce {nonce} (attempt {attempt}, max fee {}, priority fee {})",
+                        pending.tx_hash(),
+                        fees.max_fee_per_gas,
+                        fees.max_priority_fee_per_gas
+                    );
+                    hashes.push(*pending.tx_hash());
+                    on_sent(*pending.tx_hash());
+                }
+                // The nonce was used, either by one of our transactions that has since been
+                // included or by a transaction from elsewhere. Replacing it is pointless.
+                Err(e) if is_nonce_too_low(&e.to_string()) => {
+                    stuck.remove(&nonce);
+                    return self.nonce_used(provider, nonce, &hashes).await;
+                }
+                // The fee caps keep the replacement from being accepted, or the node already has
+                // it. Either way, keep waiting on what was sent.
+                Err(e) if is_replacement_rejected(&e.to_string()) => {
+                    warn!("Replacement for nonce {nonce} was rejected: {e}");
+                }
+                Err(e) => return Err(e).context("Failed to send transaction"),
+            }
+
+            // Nothing we sent was accepted, so back off and bump instead of waiting on a receipt.
+            if hashes.is_empty() {
+                tokio::time::sleep(config.retry.backoff(attempt)).await;
+                fees = config.fees.bump(fees);
+                continue;
+            }
+
+            if let Some(receipt) = self.wait_for_receipt(provider, &hashes).await? {
+                stuck.remove(&nonce);
+                return Ok(receipt);
+            }
+
+            warn!(
+                "Transaction with nonce {nonce} not included after {:?}, bumping fees",
+                config.replacement_timeout
+            );
+            fees = config.fees.bump(fees);
+        }
+
+        stuck.insert(nonce, fees);
+
+        anyhow::bail!(
+            "Transaction with nonce {nonce} was not included after {} replacements",
+            config.max_replacements
+        )
+    }
+
+    /// Find out what happened to a nonce the node reports as used: if one of our transactions
+    /// took it, wait for its confirmations, and fail otherwise.
+    async fn nonce_used<P: Provider>(
+        &self,
+        provider: &P,
+        nonce: u64,
+        hashes: &[TxHash],
+    ) -> Result<TransactionReceipt> {
+        for hash in hashes {
+            if let Some(receipt) = provider.get_transaction_receipt(*hash).await? {
+                return self
+                    .wait_for_confirmations(provider, receipt)
+                    .await?
+                    .with_context(|| {
+                        format!("Transaction {hash:?} with nonce {nonce} was dropped")
+                    });
+            }
+        }
+
+        anyhow::bail!("Nonce {nonce} was used by another transaction")
+    }
+
+    /// Wait for any of the given transactions to be included and confirmed.
+    ///
+    /// Returns `None` if none of them was included within the replacement timeout.
+    async fn wait_for_receipt<P: Provider>(
+        &self,
+        provider: &P,
+        hashes: &[TxHash],
+    ) -> Result<Option<TransactionReceipt>> {
+        let deadline = Instant::now() + self.config().replacement_timeout;
+
+        loop {
+            for hash in hashes {
+                let Some(receipt) = provider.get_transaction_receipt(*hash).await? else {
+                    continue;
+                };
+
+                // Once included, there is nothing left to replace, so wait for the
+                // confirmations regardless of the deadline.
+                return self.wait_for_confirmations(provider, receipt).await;
+            }
+
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+
+            tokio::time::sleep(RECEIPT_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Wait until an included transaction has the required number of confirmations.
+    ///
+    /// Returns `None` if the transaction was dropped by a reorg in the meantime, or is not
+    /// confirmed within the confirmation timeout.
+    async fn wait_for_confirmations<P: Provider>(
+        &self,
+        provider: &P,
+        receipt: TransactionReceipt,
+    ) -> Result<Option<TransactionReceipt>> {
+        let timeout = self.config().confirmation_timeout;
+        let hash = receipt.transaction_hash;
+        match tokio::time::timeout(timeout, self.confirmations(provider, receipt)).await {
+            Ok(receipt) => receipt,
+            Err(_) => {
+                warn!("Transaction {hash:?} was not confirmed within {timeout:?}");
+                Ok(None)
+            }
+        }
+    }
+
+    async fn confirmations<P: Provider>(
+        &self,
+        provider: &P,
+        receipt: TransactionReceipt,
+    ) -> Result<Option<TransactionReceipt>> {
+        loop {
+            let Some(receipt) = provider
+                .get_transaction_receipt(receipt.transaction_hash)
+                .await?
+            else {
+                warn!(
+                    "Transaction {:?} was dropped after being included",
+                    receipt.transaction_hash
+                );
+                return Ok(None);
+            };
+
+            let block_number = receipt.block_number.unwrap_or_default();
+            let latest = provider.get_block_number().await?;
+            if latest + 1 >= block_number + self.config().confirmations {
+                return Ok(Some(receipt));
+            }
+
+            tokio::time::sleep(RECEIPT_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Scale a fee by a percentage.
+fn scale(fee: u128, percent: u64) -> u128 {
+    fee.saturating_mul(percent as u128) / 100
+}
+
+fn max_fees(a: Fees, b: Fees) -> Fees {
+    Fees {
+        max_fee_per_gas: a.max_fee_per_gas.max(b.max_fee_per_gas),
+        max_priority_fee_per_gas: a.max_priority_fee_per_gas.max(b.max_priority_fee_per_gas),
+    }
+}
+
+/// Whether a node rejected a transaction because one with the same nonce is already known or
+/// priced too closely.
+fn is_replacement_rejected(err: &str) -> bool {
+    let err = err.to_lowercase();
+    err.contains("underpriced") || err.contains("already known")
+}
+
+/// Whether a node rejected a transaction because its nonce was already used.
+fn is_nonce_too_low(err: &str) -> bool {
+    err.to_lowercase().contains("nonce too low")
+}
@@ -0,0 +1,117 @@
+//! API keys, request size limits and per-client rate limits, as enforced by `auth::Authorizer`.
+
+use alloy::primitives::{Address, B256};
+use sp1_helios_script::auth::{ApiClient, Authorizer, Rejection};
+use sp1_helios_script::handle::ContractKeys;
+use std::time::Duration;
+
+fn client(key: &str, requests_per_minute: u32) -> ApiClient {
+    ApiClient {
+        name: key.to_string(),
+        key: key.to_string(),
+        requests_per_minute,
+        max_contracts_per_request: 2,
+        max_slots_per_request: 3,
+        max_watched_slots: 2,
+        admin: false,
+    }
+}
+
+fn request(contracts: u8, slots: u8) -> Vec<ContractKeys> {
+    (0..contracts)
+        .map(|i| ContractKeys {
+            address: Address::repeat_byte(i),
+            storage_slots: (0..slots).map(B256::repeat_byte).collect(),
+        })
+        .collect()
+}
+
+#[test]
+fn rejects_unknown_keys() {
+    let auth = Authorizer::new(vec![client("a", 10)]);
+
+    assert!(matches!(
+        auth.authenticate(None),
+        Err(Rejection::Unauthorized)
+    ));
+    assert!(matches!(
+        auth.authenticate(Some("b")),
+        Err(Rejection::Unauthorized)
+    ));
+    assert_eq!(auth.authenticate(Some("a")).unwrap().name, "a");
+}
+
+/// Oversized requests are rejected without using up the client's rate limit.
+#[test]
+fn rejects_oversized_requests() {
+    let auth = Authorizer::new(vec![client("a", 1)]);
+
+    assert!(matches!(
+        auth.authorize_proof_request(Some("a"), &request(3, 1)),
+        Err(Rejection::TooManyContracts {
+            requested: 3,
+            max: 2
+        })
+    ));
+    assert!(matches!(
+        auth.authorize_proof_request(Some("a"), &request(2, 2)),
+        Err(Rejection::TooManySlots {
+            requested: 4,
+            max: 3
+        })
+    ));
+    assert!(auth
+        .authorize_proof_request(Some("a"), &request(1, 1))
+        .is_ok());
+}
+
+/// A client gets a minute's worth of requests up front, then waits for the bucket to refill.
+#[test]
+fn limits_requests_per_client() {
+    let auth = Authorizer::new(vec![client("a", 2), client("b", 2)]);
+
+    assert!(auth
+        .authorize_proof_request(Some("a"), &request(1, 1))
+        .is_ok());
+    assert!(auth
+        .authorize_proof_request(Some("a"), &request(1, 1))
+        .is_ok());
+    let Err(Rejection::RateLimited { retry_after }) =
+        auth.authorize_proof_request(Some("a"), &request(1, 1))
+    else {
+        panic!("the third request within a minute should be rate limited");
+    };
+    assert!(retry_after > Duration::from_secs(25) && retry_after <= Duration::from_secs(30));
+
+    // Other clients have buckets of their own.
+    assert!(auth
+        .authorize_proof_request(Some("b"), &request(1, 1))
+        .is_ok());
+}
+
+/// Once drained, the bucket refills continuously rather than once a minute.
+#[test]
+fn refills_over_time() {
+    // 100 requests per second.
+    let auth = Authorizer::new(vec![client("a", 6000)]);
+    while auth
+        .authorize_proof_request(Some("a"), &request(1, 1))
+        .is_ok()
+    {}
+
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(auth
+        .authorize_proof_request(Some("a"), &request(1, 1))
+        .is_ok());
+}
+
+/// Without any requests per minute, a client is always rate limited.
+#[test]
+fn never_refills_an_empty_bucket() {
+    let auth = Authorizer::new(vec![client("a", 0)]);
+
+    assert!(matches!(
+        auth.authorize_proof_request(Some("a"), &request(1, 1)),
+        Err(Rejection::RateLimited { retry_after }) if retry_after == Duration::MAX
+    ));
+}
@@ -0,0 +1,91 @@
+//! Coalesced storage proof requests: which keys a batch proves, and where each job finds its
+//! slots in the shared proof.
+
+use alloy::primitives::{Address, B256};
+use sp1_helios_script::batch::{slot_positions, StorageProofBatch};
+use sp1_helios_script::handle::{ContractKeys, StorageProofRequest};
+
+fn keys(address: u8, slots: &[u8]) -> ContractKeys {
+    ContractKeys {
+        address: Address::repeat_byte(address),
+        storage_slots: slots.iter().copied().map(B256::repeat_byte).collect(),
+    }
+}
+
+fn request(job: u64, contract_keys: Vec<ContractKeys>, relay: bool) -> StorageProofRequest {
+    StorageProofRequest {
+        job,
+        block_number: 100,
+        contract_keys,
+        relay,
+    }
+}
+
+/// The batch proves the union of the requested keys once, in order of first request.
+#[test]
+fn proves_the_union_of_keys() {
+    let mut batch = StorageProofBatch::new(request(0, vec![keys(1, &[1, 2])], false));
+    batch.push(request(1, vec![keys(2, &[7]), keys(1, &[2, 3])], true));
+
+    let union = batch.contract_keys();
+    assert_eq!(union.len(), 2);
+    assert_eq!(union[0].address, Address::repeat_byte(1));
+    assert_eq!(
+        union[0].storage_slots,
+        vec![
+            B256::repeat_byte(1),
+            B256::repeat_byte(2),
+            B256::repeat_byte(3)
+        ]
+    );
+    assert_eq!(union[1].storage_slots, vec![B256::repeat_byte(7)]);
+
+    assert_eq!(batch.jobs(), vec![0, 1]);
+    assert!(!batch.relays(0));
+    assert!(batch.relays(1));
+}
+
+/// Each job gets the position of its slots in the proof's outputs, in the order it asked.
+#[test]
+fn indexes_each_jobs_slots() {
+    let mut batch = StorageProofBatch::new(request(0, vec![keys(1, &[1, 2])], false));
+    batch.push(request(1, vec![keys(2, &[7]), keys(1, &[3, 1])], false));
+
+    // The proof outputs 0x01..[1, 2, 3] and then 0x02..[7].
+    assert_eq!(
+        batch.slot_indices(),
+        vec![(0, vec![0, 1]), (1, vec![3, 2, 0])]
+    );
+}
+
+/// Cancelled jobs drop out of the batch, along with their slots.
+#[test]
+fn drops_cancelled_jobs() {
+    let mut batch = StorageProofBatch::new(request(0, vec![keys(1, &[1])], true));
+    batch.push(request(1, vec![keys(2, &[2])], false));
+
+    batch.retain(|job| job != 0);
+    assert_eq!(batch.jobs(), vec![1]);
+    assert!(!batch.relays(0));
+    assert_eq!(batch.contract_keys().len(), 1);
+    assert_eq!(batch.slot_indices(), vec![(1, vec![0])]);
+
+    batch.retain(|_| false);
+    assert!(batch.is_empty());
+}
+
+/// Slots are numbered across contracts, in the order the program outputs them.
+#[test]
+fn numbers_slots_across_contracts() {
+    let positions = slot_positions(&[keys(1, &[1, 2]), keys(2, &[1])]);
+
+    assert_eq!(positions.len(), 3);
+    assert_eq!(
+        positions[&(Address::repeat_byte(1), B256::repeat_byte(2))],
+        1
+    );
+    assert_eq!(
+        positions[&(Address::repeat_byte(2), B256::repeat_byte(1))],
+        2
+    );
+}
@@ -0,0 +1,88 @@
+//! Splitting watched storage by cycle budget (see `budget::split_storage`).
+
+use alloy::primitives::{Address, Bytes, B256, U256};
+use alloy_trie::TrieAccount;
+use sp1_helios_primitives::types::{ContractStorage, StorageSlotWithProof};
+use sp1_helios_script::budget::{proof_cycles, split_storage, StorageCycleBudget};
+
+fn proof() -> Vec<Bytes> {
+    vec![Bytes::from(vec![0u8; 100]); 4]
+}
+
+fn contract(address: u8, slots: u8) -> ContractStorage {
+    ContractStorage {
+        address: Address::repeat_byte(address),
+        value: TrieAccount::default(),
+        mpt_proof: proof(),
+        storage_slots: (0..slots)
+            .map(|slot| StorageSlotWithProof {
+                key: B256::repeat_byte(slot),
+                value: U256::from(slot),
+                mpt_proof: proof(),
+            })
+            .collect(),
+    }
+}
+
+fn slots(contracts: &[ContractStorage]) -> Vec<(Address, B256)> {
+    contracts
+        .iter()
+        .flat_map(|contract| {
+            contract
+                .storage_slots
+                .iter()
+                .map(|slot| (contract.address, slot.key))
+        })
+        .collect()
+}
+
+/// Slots beyond the update's budget move to follow-up proofs, each with its contract's account
+/// proof, while required slots stay in the update.
+#[test]
+fn splits_overflow_into_follow_ups() {
+    let cycles = proof_cycles(&proof());
+    let contracts = vec![contract(1, 3), contract(2, 2)];
+    let required = (Address::repeat_byte(2), B256::repeat_byte(1));
+
+    let split = split_storage(
+        contracts,
+        &StorageCycleBudget {
+            // The account and one slot, plus the required slot of the second contract.
+            max_update_cycles: Some(4 * cycles),
+            max_follow_up_cycles: Some(3 * cycles),
+        },
+        |address, slot| (address, slot) == required,
+    );
+
+    assert_eq!(
+        slots(&split.update),
+        [required, (Address::repeat_byte(1), B256::repeat_byte(0)),]
+    );
+    assert_eq!(
+        split
+            .follow_ups
+            .iter()
+            .map(|c| slots(c))
+            .collect::<Vec<_>>(),
+        [
+            vec![
+                (Address::repeat_byte(1), B256::repeat_byte(1)),
+                (Address::repeat_byte(1), B256::repeat_byte(2)),
+            ],
+            vec![(Address::repeat_byte(2), B256::repeat_byte(0))],
+        ]
+    );
+}
+
+/// Without a budget, everything goes into the update.
+#[test]
+fn unlimited_budget_keeps_everything_in_the_update() {
+    let split = split_storage(
+        vec![contract(1, 3), contract(2, 2)],
+        &StorageCycleBudget::default(),
+        |_, _| false,
+    );
+
+    assert_eq!(slots(&split.update).len(), 5);
+    assert!(split.follow_ups.is_empty());
+}
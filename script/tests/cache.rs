@@ -0,0 +1,199 @@
+//! Serving storage proof requests from `cache::StorageProofCache`: which cached proofs cover a
+//! request, which get evicted, and what survives a restart.
+
+use alloy::primitives::{Address, B256};
+use sp1_helios_script::cache::{StorageProofCache, StorageProofCacheConfig};
+use sp1_helios_script::handle::ContractKeys;
+use sp1_helios_script::jobs::StorageProof;
+use sp1_sdk::{SP1Proof, SP1ProofWithPublicValues, SP1PublicValues};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const VKEY: B256 = B256::repeat_byte(0xee);
+
+fn keys(address: u8, slots: &[u8]) -> ContractKeys {
+    ContractKeys {
+        address: Address::repeat_byte(address),
+        storage_slots: slots.iter().copied().map(B256::repeat_byte).collect(),
+    }
+}
+
+/// A stand-in proof, told apart from the others by its public values.
+fn proof(id: u8) -> SP1ProofWithPublicValues {
+    SP1ProofWithPublicValues {
+        proof: SP1Proof::Core(vec![]),
+        public_values: SP1PublicValues::from(&[id]),
+        sp1_version: String::new(),
+        tee_proof: None,
+    }
+}
+
+/// Which of the stand-in proofs was served.
+fn id(hit: &StorageProof) -> u8 {
+    hit.proof.public_values.as_slice()[0]
+}
+
+fn cache(max_entries: usize) -> StorageProofCache {
+    StorageProofCache::new(StorageProofCacheConfig {
+        max_entries,
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+/// A fresh directory for the test to persist proofs to.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sp1-helios-cache-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// A request is served by a proof of the same block and program covering all of its slots.
+#[tokio::test]
+async fn serves_subsets_of_cached_proofs() {
+    let cache = cache(8);
+    cache
+        .insert(
+            100,
+            VKEY,
+            vec![keys(1, &[1, 2, 3]), keys(2, &[7])],
+            proof(1),
+        )
+        .await;
+
+    let hit = cache
+        .get(100, VKEY, &[keys(2, &[7]), keys(1, &[3])])
+        .await
+        .unwrap();
+    assert_eq!(id(&hit), 1);
+    assert_eq!(hit.slot_indices, vec![3, 2]);
+
+    assert!(cache.get(100, VKEY, &[keys(1, &[4])]).await.is_none());
+    assert!(cache.get(100, VKEY, &[keys(3, &[])]).await.is_none());
+    assert!(cache.get(101, VKEY, &[keys(1, &[1])]).await.is_none());
+    // A proof from another build of the program would be rejected by the destination.
+    assert!(cache
+        .get(100, B256::repeat_byte(0xdd), &[keys(1, &[1])])
+        .await
+        .is_none());
+
+    let stats = cache.stats();
+    assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 4));
+}
+
+/// Further lookups for a request already counted leave the hit rate alone.
+#[tokio::test]
+async fn counts_each_request_once() {
+    let cache = cache(8);
+    cache.insert(100, VKEY, vec![keys(1, &[1])], proof(1)).await;
+
+    assert!(cache.find(100, VKEY, &[keys(1, &[1])]).await.is_some());
+    assert!(cache.find(100, VKEY, &[keys(1, &[2])]).await.is_none());
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (0, 0));
+}
+
+/// A proof over more keys replaces cached proofs of the same program over a subset of them.
+#[tokio::test]
+async fn replaces_proofs_of_subsets() {
+    let other_vkey = B256::repeat_byte(0xdd);
+    let cache = cache(8);
+    cache.insert(100, VKEY, vec![keys(1, &[1])], proof(1)).await;
+    cache
+        .insert(100, other_vkey, vec![keys(1, &[1])], proof(2))
+        .await;
+    cache
+        .insert(100, VKEY, vec![keys(1, &[1, 2])], proof(3))
+        .await;
+
+    assert_eq!(cache.stats().entries, 2);
+    let hit = cache.get(100, VKEY, &[keys(1, &[1])]).await.unwrap();
+    assert_eq!(id(&hit), 3);
+    let hit = cache.get(100, other_vkey, &[keys(1, &[1])]).await.unwrap();
+    assert_eq!(id(&hit), 2);
+}
+
+/// Over the size limit, the least recently used proof goes first.
+#[tokio::test]
+async fn evicts_the_least_recently_used_proof() {
+    let cache = cache(2);
+    cache.insert(100, VKEY, vec![keys(1, &[1])], proof(1)).await;
+    cache.insert(101, VKEY, vec![keys(1, &[1])], proof(2)).await;
+    assert!(cache.get(100, VKEY, &[keys(1, &[1])]).await.is_some());
+
+    cache.insert(102, VKEY, vec![keys(1, &[1])], proof(3)).await;
+
+    assert_eq!(cache.stats().entries, 2);
+    assert!(cache.get(100, VKEY, &[keys(1, &[1])]).await.is_some());
+    assert!(cache.get(101, VKEY, &[keys(1, &[1])]).await.is_none());
+    assert!(cache.get(102, VKEY, &[keys(1, &[1])]).await.is_some());
+}
+
+#[tokio::test]
+async fn caches_nothing_without_entries() {
+    let cache = cache(0);
+    cache.insert(100, VKEY, vec![keys(1, &[1])], proof(1)).await;
+
+    assert_eq!(cache.stats().entries, 0);
+    assert!(cache.get(100, VKEY, &[keys(1, &[1])]).await.is_none());
+}
+
+/// Persisted proofs are loaded again on restart, and unreadable files and leftovers of interrupted
+/// writes are cleaned up.
+#[tokio::test]
+async fn loads_persisted_proofs() {
+    let dir = temp_dir("persist");
+    let config = StorageProofCacheConfig {
+        dir: Some(dir.clone()),
+        ..Default::default()
+    };
+
+    let cache = StorageProofCache::new(config.clone()).unwrap();
+    cache
+        .insert(100, VKEY, vec![keys(1, &[1, 2])], proof(1))
+        .await;
+    drop(cache);
+    let garbage = dir.join("garbage.cbor");
+    std::fs::write(&garbage, b"not a proof").unwrap();
+    let partial = dir.join("partial.tmp");
+    std::fs::write(&partial, b"half a pro").unwrap();
+
+    let cache = StorageProofCache::new(config).unwrap();
+    assert_eq!(cache.stats().entries, 1);
+    let hit = cache.get(100, VKEY, &[keys(1, &[2])]).await.unwrap();
+    assert_eq!(id(&hit), 1);
+    assert_eq!(hit.slot_indices, vec![1]);
+    assert!(!garbage.exists());
+    assert!(!partial.exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A proof generated on a miss is cached, so the same request is served without proving again.
+#[tokio::test]
+async fn proves_on_a_miss_only() {
+    let cache = cache(8);
+    let proven = AtomicU8::new(0);
+    let proven = &proven;
+    let prove = move |_: Vec<ContractKeys>| async move {
+        let id = proven.fetch_add(1, Ordering::Relaxed) + 1;
+        Ok::<_, anyhow::Error>((proof(id), id))
+    };
+
+    let (first, generated) = cache
+        .find_or_prove(100, VKEY, vec![keys(1, &[1, 2])], prove)
+        .await
+        .unwrap();
+    assert_eq!(generated, Some(1));
+    let (second, generated) = cache
+        .find_or_prove(100, VKEY, vec![keys(1, &[1, 2])], prove)
+        .await
+        .unwrap();
+    assert_eq!(generated, None);
+    assert_eq!(
+        second.public_values.as_slice(),
+        first.public_values.as_slice()
+    );
+    assert_eq!(cache.stats().entries, 1);
+}
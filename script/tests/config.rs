@@ -0,0 +1,112 @@
+//! Operator config files (see `config::OperatorConfig`).
+
+use sp1_helios_script::config::{OperatorConfig, ProvingMode};
+use std::time::Duration;
+
+fn load(name: &str, contents: &str) -> anyhow::Result<OperatorConfig> {
+    let path = std::env::temp_dir().join(format!("sp1-helios-{}-{name}", std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    let config = OperatorConfig::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    config
+}
+
+/// Settings left out of the file fall back to the operator's defaults.
+#[test]
+fn loads_toml_with_defaults() {
+    let config = load(
+        "config.toml",
+        r#"
+            source_chain_id = 1
+
+            [source]
+            consensus_rpcs = ["https://beacon.example"]
+
+            [[destinations]]
+            contract_address = "0x0000000000000000000000000000000000000001"
+            rpc_url = "https://l2.example"
+
+            [proving]
+            mode = "network"
+
+            [relay]
+            confirmations = 6
+
+            [[watchlist]]
+            address = "0x0000000000000000000000000000000000000002"
+            storage_slots = ["0x0000000000000000000000000000000000000000000000000000000000000001"]
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.loop_delay(), Duration::from_secs(5 * 60));
+    assert_eq!(config.proving.mode, Some(ProvingMode::Network));
+    assert_eq!(config.proving_config().max_concurrent_proofs, 2);
+    assert_eq!(config.relay_config().confirmations, 6);
+    assert_eq!(config.relay_config().max_replacements, 5);
+    assert_eq!(
+        config.storage_slots().unwrap().values().flatten().count(),
+        1
+    );
+}
+
+#[test]
+fn loads_yaml() {
+    let config = load(
+        "config.yaml",
+        r#"
+source_chain_id: 1
+loop_delay_mins: 1
+source:
+  consensus_rpcs: ["https://beacon.example"]
+destinations:
+  - contract_address: "0x0000000000000000000000000000000000000001"
+    rpc_url: "https://l2.example"
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.loop_delay(), Duration::from_secs(60));
+    assert!(config.storage_slots().is_none());
+}
+
+/// Unknown settings are rejected rather than silently ignored.
+#[test]
+fn rejects_unknown_settings() {
+    let config = load(
+        "unknown.toml",
+        r#"
+            source_chain_id = 1
+            loop_delay = 5
+
+            [source]
+            consensus_rpcs = ["https://beacon.example"]
+
+            [[destinations]]
+            contract_address = "0x0000000000000000000000000000000000000001"
+            rpc_url = "https://l2.example"
+        "#,
+    );
+
+    assert!(config.is_err());
+}
+
+#[test]
+fn rejects_a_quorum_above_the_consensus_rpcs() {
+    let config = load(
+        "quorum.toml",
+        r#"
+            source_chain_id = 1
+
+            [source]
+            consensus_rpcs = ["https://beacon.example", "https://beacon2.example"]
+            consensus_quorum = 3
+
+            [[destinations]]
+            contract_address = "0x0000000000000000000000000000000000000001"
+            rpc_url = "https://l2.example"
+        "#,
+    );
+
+    assert!(config.is_err());
+}
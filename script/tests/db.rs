@@ -0,0 +1,219 @@
+//! What `db::OperatorDb` remembers across restarts: the watchlist, and the proofs still being
+//! relayed.
+
+use alloy::primitives::{Address, B256, U256};
+use sp1_helios_primitives::types::{ContractStorage, StorageSlotWithProof};
+use sp1_helios_script::db::{
+    OperatorDb, ProofKind, ProofRecord, ProofState, RelayAttempt, RelayOutcome, Watchlist,
+};
+use sp1_sdk::{SP1Proof, SP1ProofWithPublicValues, SP1PublicValues};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn proof() -> SP1ProofWithPublicValues {
+    SP1ProofWithPublicValues {
+        proof: SP1Proof::Core(vec![]),
+        public_values: SP1PublicValues::from(&[1, 2, 3]),
+        sp1_version: String::new(),
+        tee_proof: None,
+    }
+}
+
+/// A fresh directory for the test's database.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sp1-helios-db-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// The watchlist comes back with its owners and the storage slots' maximum staleness, including
+/// contracts watched without any storage slot.
+#[test]
+fn round_trips_the_watchlist() {
+    let dir = temp_dir("watchlist");
+    let db = OperatorDb::open(&dir).unwrap();
+    assert_eq!(db.watchlist().unwrap(), Watchlist::default());
+
+    let [first, second] = [1, 2].map(B256::repeat_byte);
+    let watchlist = Watchlist {
+        storage_slots: HashMap::from([
+            (Address::repeat_byte(1), HashSet::from([first, second])),
+            (Address::repeat_byte(2), HashSet::new()),
+        ]),
+        owners: HashMap::from([
+            ((Address::repeat_byte(1), first), Some("alice".to_string())),
+            ((Address::repeat_byte(1), second), None),
+        ]),
+        max_staleness: HashMap::from([(
+            (Address::repeat_byte(1), first),
+            Duration::from_secs(600),
+        )]),
+    };
+    db.save_watchlist(&watchlist).unwrap();
+    drop(db);
+
+    assert_eq!(
+        OperatorDb::open(&dir).unwrap().watchlist().unwrap(),
+        watchlist
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Proofs still being relayed are picked up again after a restart, with their relay attempts.
+#[test]
+fn reopens_proofs_still_being_relayed() {
+    let dir = temp_dir("relaying");
+    let destination = Address::repeat_byte(1);
+    let db = OperatorDb::open(&dir).unwrap();
+
+    let relaying = db
+        .insert_proof(
+            ProofKind::Storage { block_number: 100 },
+            &proof(),
+            Some(B256::repeat_byte(9)),
+            vec![destination],
+            &[],
+        )
+        .unwrap();
+    let abandoned = db
+        .insert_proof(
+            ProofKind::Update { head: 64 },
+            &proof(),
+            None,
+            vec![destination],
+            &[],
+        )
+        .unwrap();
+    db.record_relay(relaying, destination, Err("reverted".to_string()))
+        .unwrap();
+    db.abandon(abandoned).unwrap();
+    // Finished proofs can't be relayed any more.
+    assert!(db.abandon(abandoned).is_err());
+    drop(db);
+
+    let db = OperatorDb::open(&dir).unwrap();
+    let records = db.relaying().unwrap();
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    assert_eq!(record.id, relaying);
+    assert_eq!(record.kind, ProofKind::Storage { block_number: 100 });
+    assert_eq!(record.inputs_hash, Some(B256::repeat_byte(9)));
+    assert_eq!(record.state, ProofState::Relaying);
+    assert_eq!(record.proof.public_values.as_slice(), &[1, 2, 3]);
+    assert!(matches!(
+        &record.relays[..],
+        [RelayAttempt { outcome: RelayOutcome::Failed { error }, .. }] if error == "reverted"
+    ));
+    assert_eq!(record.pending_destinations(), vec![destination]);
+
+    // Ids are not reused, even for the finished proofs that are no longer loaded.
+    let next = db
+        .insert_proof(ProofKind::Update { head: 128 }, &proof(), None, vec![], &[])
+        .unwrap();
+    assert!(next > abandoned);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// An update's follow-up storage is kept with it, to prove once the update is relayed again.
+#[test]
+fn keeps_follow_up_storage() {
+    let dir = temp_dir("follow-ups");
+    let db = OperatorDb::open(&dir).unwrap();
+
+    let follow_up = ContractStorage {
+        address: Address::repeat_byte(2),
+        value: Default::default(),
+        mpt_proof: vec![],
+        storage_slots: vec![StorageSlotWithProof {
+            key: B256::repeat_byte(3),
+            value: U256::from(4),
+            mpt_proof: vec![],
+        }],
+    };
+    db.insert_proof(
+        ProofKind::Update { head: 64 },
+        &proof(),
+        None,
+        vec![Address::repeat_byte(1)],
+        &[vec![follow_up]],
+    )
+    .unwrap();
+    drop(db);
+
+    let records = OperatorDb::open(&dir).unwrap().relaying().unwrap();
+    let [follow_up] = &records[0].follow_ups[..] else {
+        panic!("expected one follow-up storage proof");
+    };
+    assert_eq!(follow_up[0].address, Address::repeat_byte(2));
+    assert_eq!(follow_up[0].storage_slots[0].key, B256::repeat_byte(3));
+    assert_eq!(follow_up[0].storage_slots[0].value, U256::from(4));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Only a landed relay takes a destination off the list; failed attempts leave it pending.
+#[test]
+fn lists_destinations_not_landed_on() {
+    let [first, second, third] = [1, 2, 3].map(Address::repeat_byte);
+    let attempt = |contract_address, outcome| RelayAttempt {
+        contract_address,
+        attempted_at: 0,
+        outcome,
+    };
+    let record = ProofRecord {
+        id: 0,
+        kind: ProofKind::Update { head: 64 },
+        proof: proof(),
+        inputs_hash: None,
+        destinations: vec![first, second, third],
+        follow_ups: vec![],
+        relays: vec![
+            attempt(
+                first,
+                RelayOutcome::Landed {
+                    transaction_hash: B256::ZERO,
+                    block_number: Some(1),
+                    gas_used: 21_000,
+                },
+            ),
+            attempt(
+                second,
+                RelayOutcome::Failed {
+                    error: "nonce too low".to_string(),
+                },
+            ),
+        ],
+        state: ProofState::Relaying,
+        created_at: 0,
+    };
+
+    assert_eq!(record.pending_destinations(), vec![second, third]);
+}
+
+/// Finished records are removed once they are older than the retention, while proofs still being
+/// relayed are kept however old they are.
+#[test]
+fn prunes_old_finished_records() {
+    let dir = temp_dir("prune");
+    let db = OperatorDb::open(&dir).unwrap();
+
+    let relaying = db
+        .insert_proof(ProofKind::Update { head: 64 }, &proof(), None, vec![], &[])
+        .unwrap();
+    let finished = db
+        .insert_proof(ProofKind::Update { head: 128 }, &proof(), None, vec![], &[])
+        .unwrap();
+    db.abandon(finished).unwrap();
+    assert_eq!(db.prune(Duration::from_secs(60 * 60)).unwrap(), 0);
+
+    assert_eq!(db.prune(Duration::ZERO).unwrap(), 1);
+    assert_eq!(db.prune(Duration::ZERO).unwrap(), 0);
+    let records = db.relaying().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].id, relaying);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
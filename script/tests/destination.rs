@@ -0,0 +1,61 @@
+//! Reading a destination contract's vkey updates with `destination::Destination`, against a local
+//! JSON-RPC endpoint.
+
+use alloy::primitives::Address;
+use alloy::providers::ProviderBuilder;
+use alloy::signers::local::PrivateKeySigner;
+use axum::routing::post;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use sp1_helios_script::destination::Destination;
+use sp1_helios_script::transaction::RelayConfig;
+use std::sync::{Arc, Mutex};
+
+fn block(value: &Value) -> u64 {
+    u64::from_str_radix(value.as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+}
+
+/// Serve a chain at `latest` without any logs, recording the block range of each `eth_getLogs`.
+async fn rpc(latest: u64, ranges: Arc<Mutex<Vec<(u64, u64)>>>) -> String {
+    let router = Router::new().route(
+        "/",
+        post(move |Json(request): Json<Value>| async move {
+            let result = match request["method"].as_str().unwrap() {
+                "eth_blockNumber" => json!(format!("{latest:#x}")),
+                "eth_getLogs" => {
+                    let filter = &request["params"][0];
+                    ranges
+                        .lock()
+                        .unwrap()
+                        .push((block(&filter["fromBlock"]), block(&filter["toBlock"])));
+                    json!([])
+                }
+                method => panic!("unexpected {method}"),
+            };
+            Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }))
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    url
+}
+
+/// Logs are fetched in bounded block ranges, since RPCs reject large `eth_getLogs` ranges.
+#[tokio::test]
+async fn pages_vkey_log_queries() {
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let url = rpc(25_000, ranges.clone()).await;
+    let provider = ProviderBuilder::new()
+        .wallet(PrivateKeySigner::random())
+        .connect_http(url.parse().unwrap());
+    let destination = Destination::new(provider, Address::repeat_byte(1), RelayConfig::default());
+
+    assert!(destination.vkey_updates().await.unwrap().is_empty());
+
+    let mut ranges = ranges.lock().unwrap().clone();
+    ranges.sort();
+    ranges.dedup();
+    assert_eq!(ranges, vec![(0, 9_999), (10_000, 19_999), (20_000, 25_000)]);
+}
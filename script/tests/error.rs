@@ -0,0 +1,37 @@
+//! Retry classification of operator errors (see `error::OperatorError`).
+
+use alloy::primitives::{Address, B256};
+use anyhow::{anyhow, Context};
+use sp1_helios_script::error::OperatorError;
+use sp1_helios_script::programs::ProgramKind;
+
+/// RPC, proving and relay failures may go away on their own; setup and vkey failures do not.
+#[test]
+fn classifies_variants() {
+    assert!(OperatorError::ConsensusRpc(anyhow!("timed out")).is_retryable());
+    assert!(OperatorError::Proving(anyhow!("prover network unavailable")).is_retryable());
+    assert!(OperatorError::Relay(anyhow!("simulated update reverted")).is_retryable());
+
+    assert!(OperatorError::UnknownNetwork(12345).is_fatal());
+    assert!(OperatorError::UnknownVkey {
+        contract_address: Address::ZERO,
+        program: ProgramKind::LightClient,
+        vkey: B256::repeat_byte(1),
+    }
+    .is_fatal());
+}
+
+/// The classification survives context added on the way up.
+#[test]
+fn finds_the_cause_through_context() {
+    let error = Err::<(), _>(OperatorError::ExecutionRpc(anyhow!("connection refused")))
+        .context("Update from head 8192 failed")
+        .context("1 of 1 update(s) failed")
+        .unwrap_err();
+
+    let cause = OperatorError::find(&error).expect("the cause is kept");
+    assert!(matches!(cause, OperatorError::ExecutionRpc(_)));
+    assert!(cause.is_retryable());
+
+    assert!(OperatorError::find(&anyhow!("unclassified")).is_none());
+}
@@ -0,0 +1,94 @@
+//! Splitting storage proof fetches into `eth_getProof` calls, as `execution::StorageFetcher`
+//! issues them.
+
+use alloy::primitives::{Address, B256};
+use alloy::providers::{Provider, ProviderBuilder};
+use axum::http::StatusCode;
+use axum::Router;
+use sp1_helios_script::execution::{proof_requests, StorageFetchConfig, StorageFetcher};
+use sp1_helios_script::retry::{CircuitBreakers, RetryPolicy};
+use std::time::Duration;
+
+fn keys(n: u8) -> Vec<B256> {
+    (0..n).map(B256::repeat_byte).collect()
+}
+
+/// Keys are chunked per contract, in order, without mixing contracts in a call.
+#[test]
+fn chunks_keys_per_contract() {
+    let first = Address::repeat_byte(1);
+    let second = Address::repeat_byte(2);
+    let contracts = vec![(first, keys(5)), (second, keys(2))];
+
+    let requests = proof_requests(&contracts, 2);
+
+    assert_eq!(
+        requests,
+        vec![
+            (0, first, keys(2)),
+            (0, first, keys(4)[2..].to_vec()),
+            (0, first, vec![B256::repeat_byte(4)]),
+            (1, second, keys(2)),
+        ]
+    );
+}
+
+/// Contracts without keys still get a call for their account proof.
+#[test]
+fn requests_account_proofs_without_keys() {
+    let address = Address::repeat_byte(1);
+
+    assert_eq!(
+        proof_requests(&[(address, vec![])], 50),
+        vec![(0, address, vec![])]
+    );
+}
+
+/// A limit of zero is treated as one key per call rather than looping forever.
+#[test]
+fn clamps_a_zero_limit() {
+    let address = Address::repeat_byte(1);
+
+    assert_eq!(proof_requests(&[(address, keys(3))], 0).len(), 3);
+}
+
+async fn serve(router: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    url
+}
+
+/// An RPC that never responds is given up on after the timeout, rather than stalling the fetch.
+#[tokio::test]
+async fn times_out_hanging_rpcs() {
+    let hanging = serve(Router::new().fallback(|| std::future::pending::<StatusCode>())).await;
+    let failing = serve(Router::new().fallback(|| async { StatusCode::BAD_GATEWAY })).await;
+    let providers =
+        [hanging, failing].map(|url| ProviderBuilder::new().connect_http(url.parse().unwrap()));
+
+    let config = StorageFetchConfig::default();
+    let retry = RetryPolicy {
+        timeout: Duration::from_millis(100),
+        ..RetryPolicy::none()
+    };
+    let breakers = CircuitBreakers::default();
+    let fetcher = StorageFetcher::new(
+        providers
+            .iter()
+            .map(|provider| provider as &dyn Provider)
+            .collect(),
+        &config,
+        &retry,
+        &breakers,
+    );
+
+    let error = tokio::time::timeout(Duration::from_secs(5), fetcher.state_root(1))
+        .await
+        .expect("the hanging RPC was never given up on")
+        .unwrap_err();
+    assert!(
+        error.to_string().contains("#0: timed out"),
+        "unexpected error: {error}"
+    );
+}
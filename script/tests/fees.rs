@@ -0,0 +1,58 @@
+//! Fee bumping for replacement transactions (see `transaction::FeePolicy`).
+
+use sp1_helios_script::transaction::{FeePolicy, Fees};
+
+const GWEI: u128 = 1_000_000_000;
+
+/// Each replacement raises both fees by the configured percentage.
+#[test]
+fn bump_raises_both_fees() {
+    let policy = FeePolicy {
+        bump_percent: 25,
+        ..Default::default()
+    };
+
+    let bumped = policy.bump(Fees {
+        max_fee_per_gas: 40 * GWEI,
+        max_priority_fee_per_gas: 2 * GWEI,
+    });
+
+    assert_eq!(bumped.max_fee_per_gas, 50 * GWEI);
+    assert_eq!(bumped.max_priority_fee_per_gas, 5 * GWEI / 2);
+}
+
+/// Nodes reject replacements that bump by less than 10%, so smaller bumps are raised to 10%.
+#[test]
+fn bump_is_at_least_ten_percent() {
+    let policy = FeePolicy {
+        bump_percent: 1,
+        ..Default::default()
+    };
+
+    let bumped = policy.bump(Fees {
+        max_fee_per_gas: 100 * GWEI,
+        max_priority_fee_per_gas: 10 * GWEI,
+    });
+
+    assert_eq!(bumped.max_fee_per_gas, 110 * GWEI);
+    assert_eq!(bumped.max_priority_fee_per_gas, 11 * GWEI);
+}
+
+/// Bumped fees never exceed the caps, and the priority fee never exceeds the max fee.
+#[test]
+fn bump_respects_caps() {
+    let policy = FeePolicy {
+        bump_percent: 100,
+        max_fee_per_gas_cap: Some(60 * GWEI),
+        max_priority_fee_per_gas_cap: Some(100 * GWEI),
+        ..Default::default()
+    };
+
+    let bumped = policy.bump(Fees {
+        max_fee_per_gas: 40 * GWEI,
+        max_priority_fee_per_gas: 40 * GWEI,
+    });
+
+    assert_eq!(bumped.max_fee_per_gas, 60 * GWEI);
+    assert_eq!(bumped.max_priority_fee_per_gas, 60 * GWEI);
+}
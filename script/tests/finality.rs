@@ -0,0 +1,120 @@
+//! Following a beacon node's finality events with `finality::watch_finality`, over a local
+//! server-sent events endpoint.
+
+use axum::response::sse::{Event, Sse};
+use axum::routing::get;
+use axum::Router;
+use futures::stream::{self, BoxStream, StreamExt};
+use sp1_helios_script::finality::watch_finality;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+type Events = Sse<BoxStream<'static, Result<Event, Infallible>>>;
+
+fn checkpoint(epoch: u64) -> Event {
+    Event::default().event("finalized_checkpoint").data(format!(
+        r#"{{"epoch":"{epoch}","execution_optimistic":false}}"#
+    ))
+}
+
+fn finality_update(slot: u64) -> Event {
+    Event::default()
+        .event("light_client_finality_update")
+        .data(format!(
+            r#"{{"version":"deneb","data":{{"finalized_header":{{"beacon":{{"slot":"{slot}"}}}}}}}}"#
+        ))
+}
+
+/// Serve the events `connection` returns for each connection, in order, counting them. The
+/// stream is held open without further events if `hold_open` is set, and ended otherwise.
+async fn beacon_node(
+    connection: impl Fn(usize) -> (Vec<Event>, bool) + Clone + Send + Sync + 'static,
+) -> (String, Arc<AtomicUsize>) {
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+    let router = Router::new().route(
+        "/eth/v1/events",
+        get(move || {
+            let (events, hold_open) = connection(counter.fetch_add(1, Ordering::SeqCst));
+            let events = stream::iter(events.into_iter().map(Ok));
+            let stream: BoxStream<'static, Result<Event, Infallible>> = if hold_open {
+                events.chain(stream::pending()).boxed()
+            } else {
+                events.boxed()
+            };
+            async move { Events::new(stream) }
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    (url, connections)
+}
+
+async fn next_slot(slots: &mut mpsc::Receiver<u64>) -> u64 {
+    tokio::time::timeout(Duration::from_secs(10), slots.recv())
+        .await
+        .expect("no finalized checkpoint within 10s")
+        .expect("the watcher stopped")
+}
+
+/// Both topics report each checkpoint, which is only signalled once, including across a
+/// reconnect.
+#[tokio::test]
+async fn signals_each_checkpoint_once_across_reconnects() {
+    let (url, connections) = beacon_node(|connection| match connection {
+        0 => (
+            vec![checkpoint(10), finality_update(320), checkpoint(11)],
+            false,
+        ),
+        _ => (vec![finality_update(352), checkpoint(12)], true),
+    })
+    .await;
+
+    let (tx, mut slots) = mpsc::channel(16);
+    let watcher = tokio::spawn(watch_finality(vec![url], tx, Duration::from_secs(60)));
+
+    assert_eq!(next_slot(&mut slots).await, 320);
+    assert_eq!(next_slot(&mut slots).await, 352);
+    assert_eq!(next_slot(&mut slots).await, 384);
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+    watcher.abort();
+}
+
+/// A stream that stays open without events is dropped and replaced once it has been idle for too
+/// long.
+#[tokio::test]
+async fn reconnects_an_idle_stream() {
+    let (url, connections) =
+        beacon_node(|connection| (vec![checkpoint(connection as u64 + 1)], true)).await;
+
+    let (tx, mut slots) = mpsc::channel(16);
+    let watcher = tokio::spawn(watch_finality(vec![url], tx, Duration::from_millis(200)));
+
+    assert_eq!(next_slot(&mut slots).await, 32);
+    assert_eq!(next_slot(&mut slots).await, 64);
+    assert!(connections.load(Ordering::SeqCst) >= 2);
+
+    watcher.abort();
+}
+
+/// A node that closes every stream right away is reconnected to with a backoff, not in a loop.
+#[tokio::test]
+async fn backs_off_from_streams_closed_without_events() {
+    let (url, connections) = beacon_node(|_| (vec![], false)).await;
+
+    let (tx, _slots) = mpsc::channel(16);
+    let watcher = tokio::spawn(watch_finality(vec![url], tx, Duration::from_secs(60)));
+
+    // One reconnect after a second, the next two seconds after that.
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!((1..=2).contains(&connections.load(Ordering::SeqCst)));
+
+    watcher.abort();
+}
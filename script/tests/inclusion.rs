@@ -0,0 +1,106 @@
+//! Which watched slots `--changed-slots-only` leaves out of an update, as decided by
+//! `inclusion::SlotTracker`.
+
+use alloy::primitives::{Address, B256};
+use sp1_helios_script::inclusion::{InclusionPolicy, SlotTracker, WrittenStorageSlot};
+use std::time::Duration;
+
+const CONTRACT: Address = Address::repeat_byte(0xaa);
+const SLOT: B256 = B256::repeat_byte(1);
+
+fn destinations() -> [Address; 2] {
+    [Address::repeat_byte(1), Address::repeat_byte(2)]
+}
+
+fn changed_only(max_staleness: Option<Duration>) -> SlotTracker {
+    SlotTracker::new(InclusionPolicy::ChangedOnly { max_staleness })
+}
+
+/// Without a policy, every slot is included whatever was written.
+#[test]
+fn always_includes_every_slot() {
+    let mut tracker = SlotTracker::new(InclusionPolicy::Always);
+    tracker.record(
+        destinations()[0],
+        100,
+        [(CONTRACT, SLOT, B256::repeat_byte(7))],
+    );
+
+    assert!(!tracker.skips_unchanged());
+    assert!(tracker.needs_update(&destinations()[..1], CONTRACT, SLOT, B256::repeat_byte(7)));
+}
+
+/// A slot is skipped only once every destination is known to hold its current value.
+#[test]
+fn skips_slots_every_destination_holds() {
+    let [first, second] = destinations();
+    let value = B256::repeat_byte(7);
+    let mut tracker = changed_only(None);
+    assert!(tracker.needs_update(&[first, second], CONTRACT, SLOT, value));
+
+    tracker.record(first, 100, [(CONTRACT, SLOT, value)]);
+    assert!(!tracker.needs_update(&[first], CONTRACT, SLOT, value));
+    assert!(tracker.needs_update(&[first, second], CONTRACT, SLOT, value));
+
+    tracker.record(second, 100, [(CONTRACT, SLOT, value)]);
+    assert!(!tracker.needs_update(&[first, second], CONTRACT, SLOT, value));
+    assert!(tracker.needs_update(&[first, second], CONTRACT, SLOT, B256::repeat_byte(8)));
+}
+
+/// Unchanged slots come back once written long enough ago, with per-slot limits taking over.
+#[test]
+fn includes_stale_slots() {
+    let [first, _] = destinations();
+    let value = B256::repeat_byte(7);
+
+    let mut tracker = changed_only(Some(Duration::ZERO));
+    tracker.record(first, 100, [(CONTRACT, SLOT, value)]);
+    assert!(tracker.needs_update(&[first], CONTRACT, SLOT, value));
+
+    tracker.set_max_staleness(CONTRACT, SLOT, Some(Duration::from_secs(3600)));
+    assert!(!tracker.needs_update(&[first], CONTRACT, SLOT, value));
+
+    tracker.set_max_staleness(CONTRACT, SLOT, None);
+    assert!(tracker.needs_update(&[first], CONTRACT, SLOT, value));
+}
+
+/// Skipped slots keep the block they were last written at, and forgotten slots are unknown.
+#[test]
+fn keeps_the_block_each_slot_was_written_at() {
+    let [first, _] = destinations();
+    let other = B256::repeat_byte(2);
+    let mut tracker = changed_only(None);
+    tracker.record(
+        first,
+        100,
+        [
+            (CONTRACT, SLOT, B256::repeat_byte(7)),
+            (CONTRACT, other, B256::repeat_byte(9)),
+        ],
+    );
+    tracker.record(first, 164, [(CONTRACT, other, B256::repeat_byte(10))]);
+
+    assert_eq!(
+        tracker.written(),
+        vec![
+            WrittenStorageSlot {
+                contract_address: first,
+                address: CONTRACT,
+                slot: SLOT,
+                value: B256::repeat_byte(7),
+                block_number: 100,
+            },
+            WrittenStorageSlot {
+                contract_address: first,
+                address: CONTRACT,
+                slot: other,
+                value: B256::repeat_byte(10),
+                block_number: 164,
+            },
+        ]
+    );
+
+    tracker.forget(CONTRACT, Some(SLOT));
+    assert!(!tracker.is_known(first, CONTRACT, SLOT));
+    assert!(tracker.is_known(first, CONTRACT, other));
+}
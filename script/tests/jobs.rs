@@ -0,0 +1,104 @@
+//! The lifecycle of storage proof jobs, from submission to a proof, a failure or cancellation
+//! (`jobs::StorageProofJobs`).
+
+use alloy::primitives::{Address, B256};
+use anyhow::anyhow;
+use sp1_helios_script::handle::ContractKeys;
+use sp1_helios_script::jobs::{JobStatus, StorageProofJobs};
+
+fn contracts() -> Vec<ContractKeys> {
+    vec![ContractKeys {
+        address: Address::repeat_byte(1),
+        storage_slots: vec![B256::repeat_byte(1)],
+    }]
+}
+
+fn status(jobs: &StorageProofJobs, id: u64) -> JobStatus {
+    jobs.get(id).unwrap().status
+}
+
+/// Jobs move from queued through fetching and proving, and stay put once they fail.
+#[tokio::test]
+async fn moves_jobs_through_their_states() {
+    let jobs = StorageProofJobs::default();
+    let id = jobs.submit(100, contracts(), true, Some("bridge".to_string()));
+
+    let info = jobs.get(id).unwrap();
+    assert_eq!(info.status, JobStatus::Queued);
+    assert_eq!(info.client.as_deref(), Some("bridge"));
+    assert!(info.relay);
+    assert_eq!(jobs.queued(), 1);
+
+    jobs.set_status(id, JobStatus::Proving);
+    assert_eq!(status(&jobs, id), JobStatus::Proving);
+    assert_eq!(jobs.queued(), 0);
+
+    jobs.finish(id, Err(anyhow!("prover unavailable")));
+    assert_eq!(
+        status(&jobs, id),
+        JobStatus::Failed {
+            error: "prover unavailable".to_string()
+        }
+    );
+    let error = jobs.wait(id).await.unwrap_err();
+    assert_eq!(error.to_string(), "prover unavailable");
+
+    // Finished jobs can be neither moved along nor cancelled.
+    jobs.set_status(id, JobStatus::Relaying);
+    assert!(matches!(status(&jobs, id), JobStatus::Failed { .. }));
+    assert!(!jobs.cancel(id));
+    assert!(jobs.result(id).is_none());
+}
+
+/// A cancelled job fails its waiters, and late results are dropped.
+#[tokio::test]
+async fn cancels_jobs() {
+    let jobs = StorageProofJobs::default();
+    let id = jobs.submit(100, contracts(), false, None);
+
+    let waiter = tokio::spawn({
+        let jobs = jobs.clone();
+        async move { jobs.wait(id).await }
+    });
+
+    assert!(jobs.cancel(id));
+    assert_eq!(status(&jobs, id), JobStatus::Cancelled);
+    let error = waiter.await.unwrap().unwrap_err();
+    assert!(error.to_string().contains("was cancelled"));
+
+    jobs.finish(id, Err(anyhow!("too late")));
+    assert_eq!(status(&jobs, id), JobStatus::Cancelled);
+
+    assert!(!jobs.cancel(id + 1), "unknown jobs cannot be cancelled");
+    assert!(jobs.wait(id + 1).await.is_err());
+}
+
+/// A task shared by jobs proven together is only aborted once every one of them is cancelled.
+#[tokio::test]
+async fn aborts_shared_tasks_once_every_job_is_cancelled() {
+    let jobs = StorageProofJobs::default();
+    let first = jobs.submit(100, contracts(), false, None);
+    let second = jobs.submit(100, contracts(), false, None);
+
+    let task = tokio::spawn(std::future::pending::<()>());
+    jobs.set_task(&[first, second], task.abort_handle());
+
+    assert!(jobs.cancel(first));
+    tokio::task::yield_now().await;
+    assert!(!task.is_finished());
+
+    assert!(jobs.cancel(second));
+    assert!(task.await.unwrap_err().is_cancelled());
+}
+
+/// Jobs come back in submission order.
+#[test]
+fn lists_jobs_in_order() {
+    let jobs = StorageProofJobs::default();
+    let ids: Vec<u64> = (0..3)
+        .map(|i| jobs.submit(100 + i, contracts(), false, None))
+        .collect();
+
+    let listed: Vec<u64> = jobs.list().into_iter().map(|job| job.id).collect();
+    assert_eq!(listed, ids);
+}
@@ -0,0 +1,27 @@
+//! Matching the vkeys destination contracts expect to the program builds the operator embeds.
+
+use alloy::primitives::{Address, B256};
+use sp1_helios_script::error::OperatorError;
+use sp1_helios_script::programs::{ProgramKind, ProgramVersions, STORAGE_ELFS};
+use sp1_sdk::ProverClient;
+
+/// A contract expecting a build the operator does not embed is a fatal `UnknownVkey`, naming the
+/// contract and program.
+#[tokio::test]
+async fn rejects_unknown_vkeys() {
+    let client = ProverClient::from_env().await;
+    let versions = ProgramVersions::setup(&client, ProgramKind::StorageSlots, STORAGE_ELFS)
+        .await
+        .expect("storage slots program setup failed");
+
+    let contract = Address::repeat_byte(1);
+    let unknown = B256::repeat_byte(0xab);
+    let error = versions.require(contract, unknown).err().unwrap();
+    assert!(matches!(
+        error,
+        OperatorError::UnknownVkey { contract_address, program: ProgramKind::StorageSlots, vkey }
+            if contract_address == contract && vkey == unknown
+    ));
+    assert!(error.is_fatal());
+    assert!(versions.get(unknown).is_none());
+}
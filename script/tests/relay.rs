@@ -0,0 +1,183 @@
+//! The checks `destination::Destination` runs before relaying a proof: an update's anchor and a
+//! storage proof's state root against the contract's state, and `destination::revert_reason` for
+//! simulated calls that revert, over a mocked JSON-RPC transport.
+
+use alloy::primitives::{Address, Bytes, B256, U256};
+use alloy::providers::{Provider, ProviderBuilder, WalletProvider};
+use alloy::signers::local::PrivateKeySigner;
+use alloy::sol_types::{SolError, SolValue};
+use alloy::transports::mock::Asserter;
+use serde_json::json;
+use sp1_helios_primitives::types::ISP1Verifier::InvalidProof;
+use sp1_helios_primitives::types::SP1Helios::{self, SlotBehindHead};
+use sp1_helios_primitives::types::{ProofOutputs, StorageProofOutputs};
+use sp1_helios_script::destination::{revert_reason, Anchor, Destination};
+use sp1_helios_script::transaction::RelayConfig;
+use sp1_sdk::{SP1Proof, SP1ProofWithPublicValues, SP1PublicValues};
+
+const CONTRACT: Address = Address::repeat_byte(1);
+const SLOTS_PER_PERIOD: u64 = 8192;
+const HEAD: u64 = 3 * SLOTS_PER_PERIOD;
+const HEADER: B256 = B256::repeat_byte(2);
+const SYNC_COMMITTEE: B256 = B256::repeat_byte(3);
+
+fn destination(asserter: Asserter) -> Destination<impl Provider + WalletProvider> {
+    let provider = ProviderBuilder::new()
+        .wallet(PrivateKeySigner::random())
+        .connect_mocked_client(asserter);
+    Destination::new(provider, CONTRACT, RelayConfig::default())
+}
+
+/// Queue the contract's answers to reading its anchor: the head, the header at the head, the
+/// slots per period and the sync committee hash of the head's period.
+fn contract_state(asserter: &Asserter) {
+    asserter.push_success(&Bytes::from(U256::from(HEAD).abi_encode()));
+    asserter.push_success(&Bytes::from(HEADER.abi_encode()));
+    asserter.push_success(&Bytes::from(U256::from(SLOTS_PER_PERIOD).abi_encode()));
+    asserter.push_success(&Bytes::from(SYNC_COMMITTEE.abi_encode()));
+}
+
+/// A stand-in update proof from the given anchor. Only its public values are read before the
+/// anchor is checked.
+fn update_proof(
+    prev_head: u64,
+    prev_header: B256,
+    prev_sync_committee: B256,
+) -> SP1ProofWithPublicValues {
+    let po = ProofOutputs {
+        prevHeader: prev_header,
+        prevHead: U256::from(prev_head),
+        prevSyncCommitteeHash: prev_sync_committee,
+        newHead: U256::from(prev_head + 32),
+        newHeader: B256::ZERO,
+        executionStateRoot: B256::ZERO,
+        executionBlockNumber: U256::ZERO,
+        syncCommitteeHash: B256::ZERO,
+        nextSyncCommitteeHash: B256::ZERO,
+        storageSlots: vec![],
+    };
+
+    SP1ProofWithPublicValues {
+        proof: SP1Proof::Core(vec![]),
+        public_values: SP1PublicValues::from(po.abi_encode().as_slice()),
+        sp1_version: String::new(),
+        tee_proof: None,
+    }
+}
+
+/// Relay `proof` to a contract at [`HEAD`], returning why it was not relayed.
+async fn relay_update(proof: SP1ProofWithPublicValues) -> String {
+    let asserter = Asserter::new();
+    contract_state(&asserter);
+
+    destination(asserter)
+        .relay_update(&proof)
+        .await
+        .unwrap_err()
+        .to_string()
+}
+
+#[tokio::test]
+async fn reads_the_contract_anchor() {
+    let asserter = Asserter::new();
+    contract_state(&asserter);
+
+    assert_eq!(
+        destination(asserter).anchor().await.unwrap(),
+        Anchor {
+            head: HEAD,
+            header: HEADER,
+            sync_committee_hash: SYNC_COMMITTEE,
+        }
+    );
+}
+
+/// A proof generated before the contract moved on is caught before it is relayed.
+#[tokio::test]
+async fn rejects_proofs_from_another_anchor() {
+    let stale_head = relay_update(update_proof(HEAD - 32, HEADER, SYNC_COMMITTEE)).await;
+    assert!(stale_head.contains("the contract is now at head"));
+
+    let other_header = relay_update(update_proof(HEAD, B256::repeat_byte(9), SYNC_COMMITTEE)).await;
+    assert!(other_header.contains("but the contract has header"));
+
+    let other_committee = relay_update(update_proof(HEAD, HEADER, B256::repeat_byte(9))).await;
+    assert!(other_committee.contains("Proof was generated with sync committee"));
+}
+
+/// Relay a storage proof against `state_root` for block 100 to a contract that has
+/// `contract_state_root` for it, returning why it was not relayed.
+async fn relay_storage_proof(state_root: B256, contract_state_root: B256) -> String {
+    let asserter = Asserter::new();
+    asserter.push_success(&Bytes::from(contract_state_root.abi_encode()));
+    let outputs = StorageProofOutputs {
+        stateRoot: state_root,
+        storageSlots: vec![],
+    };
+    let proof = SP1ProofWithPublicValues {
+        proof: SP1Proof::Core(vec![]),
+        public_values: SP1PublicValues::from(outputs.abi_encode().as_slice()),
+        sp1_version: String::new(),
+        tee_proof: None,
+    };
+
+    destination(asserter)
+        .relay_storage_proof(100, &proof)
+        .await
+        .unwrap_err()
+        .to_string()
+}
+
+/// Storage proofs are only relayed once the contract has the state root they were generated
+/// against.
+#[tokio::test]
+async fn rejects_storage_proofs_against_another_state_root() {
+    let state_root = B256::repeat_byte(4);
+
+    let missing = relay_storage_proof(state_root, B256::ZERO).await;
+    assert!(missing.contains("has no execution state root for block 100 yet"));
+
+    let other = relay_storage_proof(state_root, B256::repeat_byte(5)).await;
+    assert!(other.contains("Proof was generated against state root"));
+}
+
+/// Make a call that reverts with `data`, and describe why.
+async fn revert(data: Vec<u8>) -> String {
+    let asserter = Asserter::new();
+    let error = json!({
+        "code": 3,
+        "message": "execution reverted",
+        "data": Bytes::from(data),
+    });
+    asserter.push_failure(serde_json::from_value(error).unwrap());
+    let provider = ProviderBuilder::new().connect_mocked_client(asserter);
+
+    let err = SP1Helios::new(CONTRACT, &provider)
+        .head()
+        .call()
+        .await
+        .unwrap_err();
+    revert_reason(&err)
+}
+
+/// Custom errors of both the contract and the verifier are decoded, and anything else is passed
+/// through as is.
+#[tokio::test]
+async fn decodes_revert_reasons() {
+    let slot_behind_head = SlotBehindHead {
+        slot: U256::from(HEAD),
+    };
+    assert_eq!(
+        revert(slot_behind_head.abi_encode()).await,
+        format!("SlotBehindHead: slot {HEAD} is not ahead of the contract head")
+    );
+
+    assert_eq!(
+        revert(InvalidProof {}.abi_encode()).await,
+        "verifier rejected the proof (InvalidProof)"
+    );
+
+    assert!(revert(vec![0xde, 0xad, 0xbe, 0xef])
+        .await
+        .contains("execution reverted"));
+}
@@ -0,0 +1,78 @@
+//! Retry backoff and endpoint circuit breaking (see `retry`).
+
+use sp1_helios_script::retry::{CircuitBreakers, RetryPolicy};
+use std::time::Duration;
+
+/// The backoff doubles with every retry up to the maximum, and jitter only ever shortens it by up
+/// to half.
+#[test]
+fn backoff_grows_exponentially_with_jitter() {
+    let policy = RetryPolicy {
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_secs(1),
+        ..Default::default()
+    };
+
+    for (retry, ceiling) in [
+        (0, 100),
+        (1, 200),
+        (2, 400),
+        (3, 800),
+        (4, 1000),
+        (10, 1000),
+    ] {
+        let ceiling = Duration::from_millis(ceiling);
+        for _ in 0..32 {
+            let backoff = policy.backoff(retry);
+            assert!(
+                backoff >= ceiling / 2 && backoff <= ceiling,
+                "retry {retry}: {backoff:?} is outside {:?}..={ceiling:?}",
+                ceiling / 2
+            );
+        }
+    }
+}
+
+/// An endpoint is skipped after enough failures in a row, and healthy again after a success.
+#[test]
+fn breaker_opens_after_consecutive_failures() {
+    let policy = RetryPolicy {
+        failure_threshold: 3,
+        unhealthy_for: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let breakers = CircuitBreakers::default();
+
+    breakers.record("a", false, &policy);
+    breakers.record("a", false, &policy);
+    breakers.record("a", true, &policy);
+    breakers.record("a", false, &policy);
+    breakers.record("a", false, &policy);
+    assert!(breakers.is_available("a"), "the success reset the count");
+
+    breakers.record("a", false, &policy);
+    assert!(!breakers.is_available("a"));
+    assert_eq!(breakers.unhealthy(), vec!["a".to_string()]);
+
+    let endpoints = vec![("a".to_string(), 0), ("b".to_string(), 1)];
+    assert_eq!(breakers.available(endpoints), vec![("b".to_string(), 1)]);
+
+    breakers.record("a", true, &policy);
+    assert!(breakers.is_available("a"));
+    assert!(breakers.unhealthy().is_empty());
+}
+
+/// When every endpoint is unhealthy, requests go to all of them rather than none.
+#[test]
+fn all_unhealthy_endpoints_are_still_tried() {
+    let policy = RetryPolicy {
+        failure_threshold: 1,
+        ..Default::default()
+    };
+    let breakers = CircuitBreakers::default();
+    breakers.record("a", false, &policy);
+    breakers.record("b", false, &policy);
+
+    let endpoints = vec![("a".to_string(), 0), ("b".to_string(), 1)];
+    assert_eq!(breakers.available(endpoints.clone()), endpoints);
+}
@@ -0,0 +1,95 @@
+//! How `rpc::FallbackRpc` cross-checks consensus endpoints against a quorum.
+
+use axum::http::StatusCode;
+use axum::Router;
+use helios_consensus_core::consensus_spec::MainnetConsensusSpec;
+use helios_ethereum::rpc::ConsensusRpc;
+use sp1_helios_script::error::OperatorError;
+use sp1_helios_script::retry::RetryPolicy;
+use sp1_helios_script::rpc::{agree, ConsensusEndpoints, FallbackRpc};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The response most endpoints returned wins, as long as it reaches the quorum.
+#[test]
+fn agrees_on_the_largest_group() {
+    let responses = vec![("a", (1, "x")), ("b", (2, "y")), ("c", (1, "z"))];
+
+    let (head, _) = agree("get_finality_update", responses, |(head, _)| *head, 2).unwrap();
+    assert_eq!(head, 1);
+}
+
+/// Divergent endpoints are not outvoted by a group smaller than the quorum.
+#[test]
+fn rejects_a_best_group_below_quorum() {
+    let responses = vec![("a", 1), ("b", 1), ("c", 2)];
+
+    let error = agree("get_bootstrap", responses, |head| *head, 3).unwrap_err();
+    assert!(
+        error.to_string().contains("no quorum of 3"),
+        "unexpected error: {error}"
+    );
+}
+
+#[test]
+fn fails_without_responses() {
+    let error = agree::<u64, u64>("get_bootstrap", vec![], |head| *head, 1).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("failed on every consensus endpoint"));
+}
+
+/// Without endpoints, the operator fails to start with a configuration error.
+#[test]
+fn rejects_empty_endpoints() {
+    assert!(matches!(
+        ConsensusEndpoints::new(vec![]),
+        Err(OperatorError::Config(_))
+    ));
+}
+
+/// Serve a beacon node that fails every request, counting them.
+async fn failing_endpoint() -> (String, Arc<AtomicUsize>) {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    let router = Router::new().fallback(move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+        async { StatusCode::NOT_FOUND }
+    });
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    (url, requests)
+}
+
+/// With every endpoint's breaker tripped, the quorum is still sought from all of them.
+#[tokio::test]
+async fn queries_every_endpoint_when_all_are_tripped() {
+    let (first, first_requests) = failing_endpoint().await;
+    let (second, second_requests) = failing_endpoint().await;
+
+    let endpoints = ConsensusEndpoints {
+        quorum: 2,
+        timeout: Duration::from_secs(5),
+        retry: RetryPolicy {
+            failure_threshold: 1,
+            ..RetryPolicy::none()
+        },
+        ..ConsensusEndpoints::new(vec![first.clone(), second.clone()]).unwrap()
+    };
+    for url in [&first, &second] {
+        endpoints.breakers.record(url, false, &endpoints.retry);
+    }
+    assert_eq!(endpoints.breakers.unhealthy().len(), 2);
+
+    let rpc = FallbackRpc::from_endpoints(&endpoints);
+    let result =
+        <FallbackRpc as ConsensusRpc<MainnetConsensusSpec>>::get_finality_update(&rpc).await;
+
+    assert!(result.is_err());
+    assert!(first_requests.load(Ordering::SeqCst) > 0);
+    assert!(second_requests.load(Ordering::SeqCst) > 0);
+}
@@ -0,0 +1,65 @@
+//! Proof scheduling (see `scheduler::ProvingQueue`).
+
+use sp1_helios_script::scheduler::{Priority, ProvingQueue};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Once the queue is full, freed slots go to the highest priority first, then in arrival order.
+#[tokio::test]
+async fn frees_slots_by_priority() {
+    let queue = ProvingQueue::new(1);
+    let running = queue.acquire(Priority::Storage).await;
+
+    let (order_tx, mut order_rx) = mpsc::unbounded_channel();
+    for (name, priority) in [
+        ("storage", Priority::Storage),
+        ("update", Priority::Update),
+        ("urgent", Priority::UrgentUpdate),
+        ("second update", Priority::Update),
+    ] {
+        let queue = queue.clone();
+        let order_tx = order_tx.clone();
+        tokio::spawn(async move {
+            let _permit = queue.acquire(priority).await;
+            order_tx.send(name).unwrap();
+        });
+        // Let the task join the queue before the next one.
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let depth = queue.depth();
+    assert_eq!(depth.running_storage_proofs, 1);
+    assert_eq!(depth.queued_update_proofs, 3);
+    assert_eq!(depth.queued_storage_proofs, 1);
+
+    drop(running);
+    drop(order_tx);
+
+    let mut order = Vec::new();
+    while let Some(name) = order_rx.recv().await {
+        order.push(name);
+    }
+    assert_eq!(order, ["urgent", "update", "second update", "storage"]);
+}
+
+/// A waiter that gives up does not hold on to the slot it would have been given.
+#[tokio::test]
+async fn abandoned_waiters_free_their_slot() {
+    let queue = ProvingQueue::new(1);
+    let running = queue.acquire(Priority::Update).await;
+
+    let abandoned = tokio::time::timeout(
+        Duration::from_millis(10),
+        queue.acquire(Priority::UrgentUpdate),
+    )
+    .await;
+    assert!(abandoned.is_err());
+
+    drop(running);
+
+    let permit = tokio::time::timeout(Duration::from_secs(1), queue.acquire(Priority::Storage))
+        .await
+        .expect("The abandoned waiter's slot should be freed");
+    assert_eq!(queue.depth().running_storage_proofs, 1);
+    drop(permit);
+}
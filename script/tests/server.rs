@@ -0,0 +1,245 @@
+//! The HTTP API's status codes and error bodies, from `server::router` over a handle that is not
+//! attached to an operator, so storage proof requests fail as stopped.
+
+use alloy::primitives::{Address, B256};
+use axum::body::Body;
+use axum::http::header::{AUTHORIZATION, RETRY_AFTER};
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use serde_json::{json, Value};
+use sp1_helios_script::auth::{ApiClient, Authorizer};
+use sp1_helios_script::handle::OperatorHandle;
+use sp1_helios_script::server::{router, serve};
+use tower::ServiceExt;
+
+fn client(name: &str, admin: bool) -> ApiClient {
+    ApiClient {
+        name: name.to_string(),
+        key: format!("{name}-key"),
+        requests_per_minute: 2,
+        max_contracts_per_request: 1,
+        max_slots_per_request: 1,
+        max_watched_slots: 1,
+        admin,
+    }
+}
+
+fn app() -> Router {
+    router(
+        OperatorHandle::detached(None).unwrap(),
+        Some(Authorizer::new(vec![
+            client("a", false),
+            client("b", false),
+            client("admin", true),
+        ])),
+    )
+}
+
+/// Send a request as `client`, returning the status, the `Retry-After` header and the JSON body,
+/// `Value::Null` if empty.
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    client: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, Option<String>, Value) {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(client) = client {
+        request = request.header(AUTHORIZATION, format!("Bearer {client}-key"));
+    }
+    let request = match body {
+        Some(body) => request
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .map(|value| value.to_str().unwrap().to_string());
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body = if body.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&body).unwrap()
+    };
+
+    (status, retry_after, body)
+}
+
+fn proof_request(contracts: u8) -> Value {
+    json!({
+        "block_number": 1,
+        "contracts": (0..contracts)
+            .map(|i| json!({
+                "address": Address::repeat_byte(i),
+                "storage_slots": [B256::ZERO],
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+#[tokio::test]
+async fn requires_a_known_api_key() {
+    let app = app();
+
+    let (status, _, body) = send(&app, "GET", "/status", None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "unauthorized");
+
+    let (status, _, body) = send(&app, "GET", "/status", Some("c"), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "unauthorized");
+
+    let (status, _, body) = send(&app, "GET", "/status", Some("a"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["storage_proof_cache"].is_object());
+}
+
+#[tokio::test]
+async fn unknown_routes_and_jobs_are_not_found() {
+    let app = app();
+
+    let (status, _, _) = send(&app, "GET", "/unknown", Some("a"), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, body) = send(&app, "GET", "/jobs/7", Some("a"), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "not_found");
+}
+
+/// Invalid and oversized requests are rejected before they use up the client's rate limit.
+#[tokio::test]
+async fn rejects_invalid_requests() {
+    let app = app();
+
+    let (status, _, body) = send(&app, "POST", "/jobs", Some("a"), Some(proof_request(0))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_request");
+
+    let (status, _, body) = send(&app, "POST", "/jobs", Some("a"), Some(proof_request(2))).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["code"], "too_many_contracts");
+
+    let (status, _, body) = send(&app, "POST", "/jobs", Some("a"), Some(proof_request(1))).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["code"], "stopped");
+}
+
+#[tokio::test]
+async fn rate_limits_with_retry_after() {
+    let app = app();
+
+    for _ in 0..2 {
+        let (status, _, _) = send(&app, "POST", "/jobs", Some("a"), Some(proof_request(1))).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    let (status, retry_after, body) =
+        send(&app, "POST", "/jobs", Some("a"), Some(proof_request(1))).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["code"], "rate_limited");
+    assert_eq!(retry_after, Some(body["retry_after_secs"].to_string()));
+}
+
+/// Clients only remove the slots they added, and only admins remove anyone's.
+#[tokio::test]
+async fn guards_the_watchlist() {
+    let app = app();
+    let slot = |byte: u8| {
+        json!({
+            "address": Address::repeat_byte(1),
+            "storage_slot": B256::repeat_byte(byte),
+        })
+    };
+    let uri = format!(
+        "/storage-slots/{}/{}",
+        Address::repeat_byte(1),
+        B256::repeat_byte(1)
+    );
+
+    let (status, _, _) = send(&app, "POST", "/storage-slots", Some("a"), Some(slot(1))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _, body) = send(&app, "POST", "/storage-slots", Some("a"), Some(slot(2))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "too_many_watched_slots");
+
+    let (status, _, body) = send(&app, "DELETE", &uri, Some("b"), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "not_owner");
+
+    let (status, _, _) = send(&app, "DELETE", &uri, Some("admin"), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _, body) = send(&app, "GET", "/storage-slots", Some("b"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[Address::repeat_byte(1).to_string()], json!([]));
+}
+
+/// Re-adding a slot another client owns leaves its maximum staleness alone, and setting one is
+/// rejected, unless the client is an admin.
+#[tokio::test]
+async fn guards_slot_max_staleness() {
+    let app = app();
+    let slot = |max_staleness_secs: Option<u64>| {
+        let mut slot = json!({
+            "address": Address::repeat_byte(1),
+            "storage_slot": B256::repeat_byte(1),
+        });
+        if let Some(secs) = max_staleness_secs {
+            slot["max_staleness_secs"] = json!(secs);
+        }
+        slot
+    };
+
+    let (status, _, _) = send(
+        &app,
+        "POST",
+        "/storage-slots",
+        Some("a"),
+        Some(slot(Some(3600))),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _, body) = send(
+        &app,
+        "POST",
+        "/storage-slots",
+        Some("b"),
+        Some(slot(Some(0))),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "not_owner");
+
+    let (status, _, _) = send(&app, "POST", "/storage-slots", Some("b"), Some(slot(None))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _, _) = send(
+        &app,
+        "POST",
+        "/storage-slots",
+        Some("admin"),
+        Some(slot(Some(0))),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn refuses_to_serve_unauthenticated_on_public_addresses() {
+    let handle = OperatorHandle::detached(None).unwrap();
+
+    assert!(serve(handle, "0.0.0.0:0".parse().unwrap(), None)
+        .await
+        .is_err());
+}
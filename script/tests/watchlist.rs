@@ -0,0 +1,213 @@
+//! Who may change which part of the watchlist: API clients own the slots they add, within their
+//! cap, and slots added at runtime survive the configured watchlist being replaced.
+
+use alloy::primitives::{Address, B256};
+use sp1_helios_script::db::OperatorDb;
+use sp1_helios_script::handle::OperatorHandle;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+fn slot(byte: u8) -> B256 {
+    B256::repeat_byte(byte)
+}
+
+async fn watched(handle: &OperatorHandle) -> HashSet<B256> {
+    handle
+        .storage_slots()
+        .await
+        .into_values()
+        .flatten()
+        .collect()
+}
+
+#[tokio::test]
+async fn only_owners_remove_client_slots() {
+    let handle = OperatorHandle::detached(None).unwrap();
+    let contract = Address::repeat_byte(1);
+
+    assert!(
+        handle
+            .add_client_storage_slot(contract, slot(1), "a", 10)
+            .await
+    );
+    assert!(
+        handle
+            .add_client_storage_slot(contract, slot(2), "b", 10)
+            .await
+    );
+
+    assert!(
+        !handle
+            .remove_client_storage_slot(contract, slot(2), "a")
+            .await
+    );
+    assert!(!handle.remove_client_address(contract, "a").await);
+    assert_eq!(watched(&handle).await, HashSet::from([slot(1), slot(2)]));
+
+    assert!(
+        handle
+            .remove_client_storage_slot(contract, slot(1), "a")
+            .await
+    );
+    assert!(handle.remove_client_address(contract, "b").await);
+    assert!(watched(&handle).await.is_empty());
+}
+
+/// Slots the client already owns count against its cap, and slots it no longer owns don't.
+#[tokio::test]
+async fn caps_watched_slots_per_client() {
+    let handle = OperatorHandle::detached(None).unwrap();
+    let contract = Address::repeat_byte(1);
+
+    assert!(
+        handle
+            .add_client_storage_slot(contract, slot(1), "a", 1)
+            .await
+    );
+    assert!(
+        !handle
+            .add_client_storage_slot(contract, slot(2), "a", 1)
+            .await
+    );
+    // Re-adding a watched slot is a no-op.
+    assert!(
+        handle
+            .add_client_storage_slot(contract, slot(1), "a", 1)
+            .await
+    );
+    assert!(
+        handle
+            .add_client_storage_slot(contract, slot(2), "b", 1)
+            .await
+    );
+
+    handle.remove_storage_slot(contract, slot(1)).await;
+    assert!(
+        handle
+            .add_client_storage_slot(contract, slot(3), "a", 1)
+            .await
+    );
+}
+
+/// Only the client that added a slot sets how stale it may get.
+#[tokio::test]
+async fn only_owners_set_client_slot_max_staleness() {
+    let handle = OperatorHandle::detached(None).unwrap();
+    let contract = Address::repeat_byte(1);
+    let max_staleness = Some(Duration::ZERO);
+    handle
+        .add_client_storage_slot(contract, slot(1), "a", 10)
+        .await;
+    handle.add_storage_slot(contract, slot(2)).await;
+
+    assert!(
+        handle
+            .set_client_storage_slot_max_staleness(contract, slot(1), "a", max_staleness)
+            .await
+    );
+    assert!(
+        !handle
+            .set_client_storage_slot_max_staleness(contract, slot(1), "b", max_staleness)
+            .await
+    );
+    // Slots added without a client, like configured ones, belong to no client.
+    assert!(
+        !handle
+            .set_client_storage_slot_max_staleness(contract, slot(2), "a", max_staleness)
+            .await
+    );
+}
+
+#[tokio::test]
+async fn keeps_runtime_slots_when_the_config_changes() {
+    let handle = OperatorHandle::detached(None).unwrap();
+    let contract = Address::repeat_byte(1);
+    handle
+        .set_storage_slots(HashMap::from([(contract, HashSet::from([slot(1)]))]))
+        .await;
+    handle
+        .add_client_storage_slot(contract, slot(2), "a", 10)
+        .await;
+    handle.add_storage_slot(contract, slot(3)).await;
+
+    handle
+        .set_storage_slots(HashMap::from([(contract, HashSet::from([slot(4)]))]))
+        .await;
+
+    assert_eq!(
+        watched(&handle).await,
+        HashSet::from([slot(2), slot(3), slot(4)])
+    );
+    // Configured slots belong to no client.
+    assert!(
+        !handle
+            .remove_client_storage_slot(contract, slot(4), "a")
+            .await
+    );
+}
+
+#[tokio::test]
+async fn restores_owners_from_the_db() {
+    let dir = std::env::temp_dir().join(format!(
+        "sp1-helios-watchlist-{}-owners",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    let contract = Address::repeat_byte(1);
+
+    let handle = OperatorHandle::detached(Some(OperatorDb::open(dir.clone()).unwrap())).unwrap();
+    handle
+        .add_client_storage_slot(contract, slot(1), "a", 10)
+        .await;
+    drop(handle);
+
+    let handle = OperatorHandle::detached(Some(OperatorDb::open(dir.clone()).unwrap())).unwrap();
+    assert!(
+        !handle
+            .remove_client_storage_slot(contract, slot(1), "b")
+            .await
+    );
+    assert!(
+        handle
+            .remove_client_storage_slot(contract, slot(1), "a")
+            .await
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// A slot's maximum staleness is saved with the watchlist, and dropped with the slot.
+#[tokio::test]
+async fn saves_slot_max_staleness_to_the_db() {
+    let dir = std::env::temp_dir().join(format!(
+        "sp1-helios-watchlist-{}-max-staleness",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    let contract = Address::repeat_byte(1);
+    let max_staleness = Duration::from_secs(600);
+
+    let handle = OperatorHandle::detached(Some(OperatorDb::open(dir.clone()).unwrap())).unwrap();
+    handle
+        .add_client_storage_slot(contract, slot(1), "a", 10)
+        .await;
+    handle
+        .set_client_storage_slot_max_staleness(contract, slot(1), "a", Some(max_staleness))
+        .await;
+    drop(handle);
+
+    let stored = OperatorDb::open(dir.clone()).unwrap().watchlist().unwrap();
+    assert_eq!(
+        stored.max_staleness,
+        HashMap::from([((contract, slot(1)), max_staleness)])
+    );
+
+    let handle = OperatorHandle::detached(Some(OperatorDb::open(dir.clone()).unwrap())).unwrap();
+    handle.remove_storage_slot(contract, slot(1)).await;
+    drop(handle);
+
+    let stored = OperatorDb::open(dir.clone()).unwrap().watchlist().unwrap();
+    assert!(stored.max_staleness.is_empty());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}